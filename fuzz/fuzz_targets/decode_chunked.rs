@@ -0,0 +1,12 @@
+#![no_main]
+
+use libfuzzer_sys::fuzz_target;
+use passkeymesh_gateway::http_client::HttpMethod;
+use passkeymesh_gateway::http_parser::parse_http_response;
+
+// Chunked framing header, so every input exercises the chunk decoder
+const HEAD: &[u8] = b"HTTP/1.1 200 OK\r\nTransfer-Encoding: chunked\r\n\r\n";
+
+fuzz_target!(|data: &[u8]| {
+    let _ = parse_http_response(&[HEAD, data].concat(), HttpMethod::Get);
+});
@@ -0,0 +1,15 @@
+#![no_main]
+
+use libfuzzer_sys::fuzz_target;
+use passkeymesh_gateway::http_client::HttpMethod;
+use passkeymesh_gateway::http_parser::{parse_http_response, ResponseBuffer};
+
+// Whatever a backend sends must parse or fail with a ParseError, never panic
+fuzz_target!(|data: &[u8]| {
+    let _ = parse_http_response(data, HttpMethod::Get);
+    let _ = parse_http_response(data, HttpMethod::Head);
+
+    // Pipelined responses are taken one at a time until the buffer runs dry
+    let mut buffer = ResponseBuffer::closed(data.to_vec());
+    while let Ok(Some(_)) = buffer.next_response(HttpMethod::Get) {}
+});
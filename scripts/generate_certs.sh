@@ -44,6 +44,7 @@ subjectAltName = @alt_names
 
 [ alt_names ]
 DNS.1 = localhost
+DNS.2 = quantum-safe-proxy
 IP.1 = 127.0.0.1
 EOF
 
@@ -0,0 +1,28 @@
+use axum::http::HeaderMap;
+use crate::config::Config;
+use crate::error::{AppError, AppResult};
+
+// Header carrying the admin token
+pub const ADMIN_TOKEN_HEADER: &str = "X-Admin-Token";
+
+// Check if the request carries the configured admin token
+pub fn is_admin(config: &Config, headers: &HeaderMap) -> bool {
+    let (Some(expected), Some(provided)) = (
+        config.admin_token.as_deref(),
+        headers.get(ADMIN_TOKEN_HEADER).and_then(|h| h.to_str().ok()),
+    ) else {
+        return false;
+    };
+
+    // Constant-time comparison; memcmp::eq requires equal lengths
+    expected.len() == provided.len() && openssl::memcmp::eq(expected.as_bytes(), provided.as_bytes())
+}
+
+// Require the admin token, rejecting the request otherwise
+pub fn require_admin(config: &Config, headers: &HeaderMap) -> AppResult<()> {
+    if is_admin(config, headers) {
+        Ok(())
+    } else {
+        Err(AppError::Authentication("Admin token required".to_string()))
+    }
+}
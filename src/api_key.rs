@@ -0,0 +1,145 @@
+use std::collections::{HashMap, HashSet};
+use axum::http::HeaderMap;
+use crate::error::{AppError, AppResult};
+use crate::service::ServiceType;
+
+// Header carrying a service-to-service API key
+pub const API_KEY_HEADER: &str = "X-API-Key";
+
+// API keys for service-to-service callers, stored as SHA-256 hex digests
+// together with the services each key may call
+#[derive(Debug, Clone, Default)]
+pub struct ApiKeys {
+    keys: HashMap<String, HashSet<ServiceType>>,
+}
+
+impl ApiKeys {
+    // Parse comma-separated "<sha256 hex>:<service>|<service>" entries
+    pub fn parse(value: &str) -> Result<Self, String> {
+        let mut keys = HashMap::new();
+        for entry in value.split(',').map(str::trim).filter(|entry| !entry.is_empty()) {
+            let (digest, services) = entry.split_once(':').ok_or_else(|| entry.to_string())?;
+            let digest = digest.trim().to_ascii_lowercase();
+            if digest.len() != 64 || !digest.bytes().all(|b| b.is_ascii_hexdigit()) {
+                return Err(entry.to_string());
+            }
+
+            let services = services.split('|')
+                .map(|name| name.parse::<ServiceType>().map_err(|_| entry.to_string()))
+                .collect::<Result<HashSet<_>, _>>()?;
+            keys.insert(digest, services);
+        }
+
+        Ok(Self { keys })
+    }
+
+    // Authenticate the caller's API key for a service, returning the key's identifier.
+    // Requests without a key yield None so other authentication can apply.
+    pub fn authenticate(&self, headers: &HeaderMap, service: ServiceType) -> AppResult<Option<String>> {
+        let Some(key) = headers.get(API_KEY_HEADER).and_then(|h| h.to_str().ok()) else {
+            return Ok(None);
+        };
+
+        let digest = sha256_hex(key.trim());
+        let services = self.keys.get(&digest)
+            .ok_or_else(|| AppError::Authentication("Invalid API key".to_string()))?;
+        if !services.contains(&service) {
+            return Err(AppError::Authentication(format!("API key is not allowed to call the {} service", service)));
+        }
+
+        // Digest prefix identifies the key in logs and tokens without revealing it
+        Ok(Some(format!("api-key:{}", &digest[..12])))
+    }
+}
+
+fn sha256_hex(value: &str) -> String {
+    openssl::sha::sha256(value.as_bytes()).iter().map(|b| format!("{:02x}", b)).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use axum::Router;
+    use serde_json::json;
+
+    use super::*;
+    use crate::{jwt, test_support::{self, MockBackend, TestResponse}};
+
+    // Gateway requiring auth for the log service, with one key allowed to call it
+    fn app(logs: &MockBackend) -> Router {
+        let keys = format!("{}:log, {}:users", sha256_hex("log-job"), sha256_hex("users-job"));
+        test_support::app(test_support::config(&[
+            ("LOG_SERVICE_URL", &logs.url()),
+            ("REQUIRE_AUTH_PER_SERVICE", "log"),
+            ("API_KEYS", &keys),
+        ])).0
+    }
+
+    async fn call_log(router: &Router, key: &str) -> TestResponse {
+        test_support::send(router, test_support::get("/api/service/log", &[(API_KEY_HEADER, key)])).await
+    }
+
+    #[tokio::test]
+    async fn valid_key_is_accepted_and_forwards_a_service_token() {
+        let logs = MockBackend::json(&json!({"logs": []}));
+        let router = app(&logs);
+
+        let response = call_log(&router, "log-job").await;
+
+        assert_eq!(response.status, 200, "{}", String::from_utf8_lossy(&response.body));
+        let request = String::from_utf8_lossy(&logs.requests()[0]).to_string();
+        let token = request.lines()
+            .find_map(|line| line.strip_prefix("Authorization: Bearer "))
+            .expect("service token forwarded");
+        let claims = jwt::verify_jwt(&test_support::config(&[]).jwt, token).unwrap();
+        assert_eq!(claims.sub, format!("api-key:{}", &sha256_hex("log-job")[..12]));
+        assert!(!request.contains("log-job"));
+    }
+
+    #[tokio::test]
+    async fn service_token_is_narrowed_to_the_service_scopes() {
+        let logs = MockBackend::json(&json!({"logs": []}));
+        let (router, _) = test_support::app(test_support::config(&[
+            ("LOG_SERVICE_URL", &logs.url()),
+            ("LOG_SERVICE_SCOPES", "logs:read"),
+            ("JWT_SCOPES", "logs:read users:write"),
+            ("API_KEYS", &format!("{}:log", sha256_hex("log-job"))),
+        ]));
+
+        assert_eq!(call_log(&router, "log-job").await.status, 200);
+
+        let request = String::from_utf8_lossy(&logs.requests()[0]).to_string();
+        let token = request.lines()
+            .find_map(|line| line.strip_prefix("Authorization: Bearer "))
+            .expect("service token forwarded");
+        let claims = jwt::verify_jwt(&test_support::config(&[]).jwt, token).unwrap();
+        assert_eq!(claims.typ.as_deref(), Some(jwt::TOKEN_TYPE));
+        assert_eq!(claims.scope.as_deref(), Some("logs:read"));
+    }
+
+    #[tokio::test]
+    async fn unknown_key_is_rejected() {
+        let logs = MockBackend::json(&json!({}));
+        let response = call_log(&app(&logs), "guessed-key").await;
+
+        assert_eq!(response.status, 401);
+        assert_eq!(response.json()["message"], "Invalid API key");
+        assert!(logs.requests().is_empty());
+    }
+
+    #[tokio::test]
+    async fn key_for_another_service_is_denied() {
+        let logs = MockBackend::json(&json!({}));
+        let response = call_log(&app(&logs), "users-job").await;
+
+        assert_eq!(response.status, 401);
+        assert!(response.json()["message"].as_str().unwrap().contains("not allowed to call the log service"));
+        assert!(logs.requests().is_empty());
+    }
+
+    #[test]
+    fn malformed_entries_are_rejected() {
+        for value in ["abc:log", &format!("{}:billing", sha256_hex("k")), "no-separator"] {
+            assert!(ApiKeys::parse(value).is_err(), "{:?}", value);
+        }
+    }
+}
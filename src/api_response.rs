@@ -1,10 +1,19 @@
 use serde::{Deserialize, Serialize};
 use serde_json::Value;
 
+// Outcome reported in the status field of gateway responses
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ResponseStatus {
+    Success,
+    Warning,
+    Error,
+}
+
 // API response structure
-#[derive(Debug, Serialize, Deserialize)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct ApiResponse {
-    pub status: String,
+    pub status: ResponseStatus,
     pub backend_response: Value,
     pub proxy_info: Value,
     pub tls_info: Value,
@@ -12,7 +21,7 @@ pub struct ApiResponse {
 
 // API response builder
 pub struct ApiResponseBuilder {
-    status: String,
+    status: ResponseStatus,
     backend_response: Value,
     proxy_info: Value,
     tls_info: Value,
@@ -21,15 +30,15 @@ pub struct ApiResponseBuilder {
 impl ApiResponseBuilder {
     pub fn new() -> Self {
         Self {
-            status: "success".to_string(),
+            status: ResponseStatus::Success,
             backend_response: Value::Null,
             proxy_info: Value::Null,
             tls_info: Value::Null,
         }
     }
 
-    pub fn status(mut self, status: impl Into<String>) -> Self {
-        self.status = status.into();
+    pub fn status(mut self, status: ResponseStatus) -> Self {
+        self.status = status;
         self
     }
 
@@ -63,8 +72,8 @@ impl Default for ApiResponseBuilder {
 }
 
 // Check if request is authenticated
-pub fn is_authenticated(auth: &str) -> bool {
-    !auth.is_empty() && auth.starts_with("Bearer ")
+pub fn is_authenticated(token: Option<&str>) -> bool {
+    token.is_some_and(|token| !token.is_empty())
 }
 
 // Ensure backend response authentication state matches request authentication state
@@ -83,11 +92,11 @@ pub fn ensure_auth_consistency(backend_json: &Value, is_auth: bool) -> Value {
 }
 
 // Determine response status based on backend response and HTTP status
-pub fn determine_response_status(backend_json: &Value, status_code: u16) -> String {
+pub fn determine_response_status(backend_json: &Value, status_code: u16) -> ResponseStatus {
     if backend_json.get("status").and_then(|v| v.as_str()) == Some("error") || status_code >= 400 {
-        "error".to_string()
+        ResponseStatus::Error
     } else {
-        "success".to_string()
+        ResponseStatus::Success
     }
 }
 
@@ -95,9 +104,22 @@ pub fn determine_response_status(backend_json: &Value, status_code: u16) -> Stri
 #[allow(dead_code)]
 pub fn create_error_response(message: &str) -> ApiResponse {
     ApiResponseBuilder::new()
-        .status("error")
-        .backend_response(serde_json::json!({"status": "error", "message": message}))
+        .status(ResponseStatus::Error)
+        .backend_response(serde_json::json!({"status": ResponseStatus::Error, "message": message}))
         .proxy_info(serde_json::json!({"status_line": "Error", "error": message}))
         .tls_info(serde_json::json!({"error": "TLS info unavailable"}))
         .build()
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn statuses_serialize_to_their_wire_strings() {
+        for (status, wire) in [(ResponseStatus::Success, "success"), (ResponseStatus::Warning, "warning"), (ResponseStatus::Error, "error")] {
+            assert_eq!(serde_json::to_value(status).unwrap(), wire);
+            assert_eq!(serde_json::from_value::<ResponseStatus>(wire.into()).unwrap(), status);
+        }
+    }
+}
@@ -0,0 +1,154 @@
+use std::{collections::HashMap, sync::{Arc, Mutex}};
+use axum::{handler::HandlerWithoutStateExt, middleware, response::Redirect, routing::{any, get, post, put}, Router, Extension};
+use tower_http::{catch_panic::CatchPanicLayer, cors::CorsLayer, services::ServeDir, trace::TraceLayer};
+use url::Url;
+use webauthn_rs::prelude::*;
+
+use crate::{assets, batch, discovery, error, handler, index, limiter, maintenance, metrics, stream, version, webauthn};
+use crate::config::Config;
+
+// State shared by the routes; main also hands parts of it to background tasks
+pub struct AppState {
+    pub webauthn: Arc<Webauthn>,
+    pub limiter: Arc<limiter::BackendLimiter>,
+    pub maintenance: Arc<maintenance::Maintenance>,
+    pub registry: Arc<discovery::ServiceRegistry>,
+    pub index_page: Arc<index::IndexPage>,
+    // Shared by the WebAuthn routes and metrics
+    pub user_store: webauthn::UserStore,
+    pub challenge_states: webauthn::ChallengeStates,
+}
+
+impl AppState {
+    pub fn new(config: &Config) -> Self {
+        let webauthn = Arc::new(
+            WebauthnBuilder::new(webauthn::RP_ID, &Url::parse(&format!("http://{}:{}", webauthn::RP_ID, config.port)).unwrap())
+                .expect("Invalid configuration")
+                .rp_name(&config.webauthn_rp_name)
+                .build()
+                .expect("Invalid configuration")
+        );
+
+        Self {
+            webauthn,
+            limiter: Arc::new(limiter::BackendLimiter::new(config)),
+            maintenance: Arc::new(maintenance::Maintenance::new(config)),
+            registry: Arc::new(discovery::ServiceRegistry::new(config)),
+            // Load index page once; admins can reload it without a restart
+            index_page: Arc::new(index::IndexPage::load(&config.index_html_path, &config.base_path)),
+            user_store: Arc::new(Mutex::new(HashMap::new())),
+            // Challenge states expire after CHALLENGE_TTL_SECS
+            challenge_states: webauthn::ChallengeStates::new(config),
+        }
+    }
+}
+
+// Build the gateway's routes, mounted under BASE_PATH
+pub fn router(config: Arc<Config>, state: &AppState) -> Router {
+    let port = config.port;
+
+    // Configure CORS
+    let cors = CorsLayer::new()
+        .allow_origin([format!("http://localhost:{}", port).parse().unwrap()])
+        .allow_methods(vec![
+            axum::http::Method::GET,
+            axum::http::Method::POST,
+            axum::http::Method::PUT,
+            axum::http::Method::PATCH,
+            axum::http::Method::DELETE,
+            axum::http::Method::OPTIONS,
+        ])
+        .allow_headers(vec![
+            axum::http::header::AUTHORIZATION,
+            axum::http::header::CONTENT_TYPE,
+            axum::http::HeaderName::from_static("x-request-deadline"),
+            axum::http::HeaderName::from_static("x-debug-tls"),
+            axum::http::HeaderName::from_static("x-admin-token"),
+            axum::http::HeaderName::from_static("x-request-id"),
+            axum::http::HeaderName::from_static("x-api-key"),
+        ])
+        .allow_credentials(true);
+
+    // Serve front-end assets, falling back to the index page for client-side routes
+    let static_files = Router::new()
+        .fallback_service(ServeDir::new(&config.static_dir).fallback(assets::spa_fallback.into_service()))
+        .layer(middleware::from_fn(assets::cache_headers));
+
+    // Create routes
+    let app = Router::new()
+        .route("/", get(index::serve_index))
+        .nest("/auth", webauthn::routes(Arc::clone(&state.webauthn), Arc::clone(&state.user_store), state.challenge_states.clone()))
+        .route("/metrics", get(metrics::metrics_handler))
+        .route("/version", get(version::version_info))
+        .route("/healthz", get(maintenance::healthz))
+        .route("/admin/credentials", get(webauthn::admin_list_credentials))
+        .route("/admin/credentials/import", post(webauthn::import_credentials))
+        .route("/admin/users/:username/credential-quota", put(webauthn::set_credential_quota))
+        .route("/admin/index/reload", post(index::reload_index))
+        .route("/admin/maintenance", put(maintenance::set_maintenance))
+        .route("/api/auth/verify", any(handler::handle_request))
+        .route("/api/batch", post(batch::handle_batch))
+        .route("/api/service/logs/stream", get(stream::stream_logs))
+        .route("/api/service/:service", any(handler::handle_service_by_path))
+        .route("/api/service/:service/", any(handler::handle_service_by_path))
+        .merge(static_files)
+        .layer(middleware::from_fn(maintenance::guard))
+        .layer(middleware::from_fn(error::timestamp_errors))
+        .layer(Extension(Arc::clone(&state.maintenance)))
+        .layer(Extension(Arc::clone(&state.webauthn)))
+        .layer(Extension(Arc::clone(&config)))
+        .layer(Extension(Arc::clone(&state.limiter)))
+        .layer(Extension(Arc::clone(&state.registry)))
+        .layer(Extension(Arc::clone(&state.user_store)))
+        .layer(Extension(state.challenge_states.clone()))
+        .layer(Extension(Arc::clone(&state.index_page)))
+        .layer(CatchPanicLayer::custom({
+            let clock = Arc::clone(&config.clock);
+            move |err| error::handle_panic(&clock, err)
+        }))
+        .layer(cors)
+        .layer(TraceLayer::new_for_http());
+
+    // Mount under BASE_PATH behind a reverse proxy. WebAuthn origins carry no path,
+    // so the RP configuration is unaffected.
+    if config.base_path.is_empty() {
+        return app;
+    }
+    // The nested index answers "/gateway" only; send "/gateway/" there too
+    let base_path = config.base_path.clone();
+    Router::new()
+        .nest(&config.base_path, app)
+        .route(&format!("{}/", config.base_path), get(move || async move { Redirect::permanent(&base_path) }))
+}
+
+#[cfg(test)]
+mod tests {
+    use axum::http::Method;
+    use serde_json::json;
+
+    use crate::test_support;
+
+    #[tokio::test]
+    async fn routes_resolve_under_the_base_path_and_not_at_the_root() {
+        let index = concat!(env!("CARGO_MANIFEST_DIR"), "/index.html");
+        let (router, _) = test_support::app(test_support::config(&[("BASE_PATH", "/gateway/"), ("INDEX_HTML_PATH", index)]));
+        let register = |uri: &str| test_support::json_request(Method::POST, uri, &[], &json!({"username": "alice"}));
+
+        assert_eq!(test_support::send(&router, test_support::get("/gateway/version", &[])).await.status, 200);
+        assert_eq!(test_support::send(&router, register("/gateway/auth/register")).await.status, 200);
+        assert_eq!(test_support::send(&router, test_support::get("/version", &[])).await.status, 404);
+        assert_eq!(test_support::send(&router, register("/auth/register")).await.status, 404);
+
+        // The page resolves its relative fetch URLs against the base path
+        let page = test_support::send(&router, test_support::get("/gateway", &[])).await;
+        assert_eq!(page.status, 200);
+        let html = String::from_utf8_lossy(&page.body);
+        assert!(html.contains(r#"<base href="/gateway/">"#));
+        assert!(html.contains("fetch('auth/register'"));
+        assert!(!html.contains("fetch('/") && !html.contains("fetch(\"/"), "page fetches a root-absolute URL");
+
+        let slash = test_support::send(&router, test_support::get("/gateway/", &[])).await;
+        assert_eq!(slash.status, 308);
+        assert_eq!(slash.headers["location"], "/gateway");
+    }
+}
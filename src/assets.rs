@@ -0,0 +1,90 @@
+use std::sync::Arc;
+use axum::{
+    http::{header, HeaderMap, HeaderValue, Request, StatusCode, Uri},
+    middleware::Next,
+    response::{IntoResponse, Response},
+    Extension,
+};
+use chrono::DateTime;
+
+use crate::error::{AppError, AppResult};
+use crate::index::{self, IndexPage};
+
+// Client-side routes fall back to the index page; unknown API paths stay 404
+pub async fn spa_fallback(uri: Uri, page: Extension<Arc<IndexPage>>) -> AppResult<Response> {
+    let path = uri.path();
+    if ["/api", "/auth", "/admin"].iter().any(|prefix| path == *prefix || path.starts_with(&format!("{}/", prefix))) {
+        return Err(AppError::NotFound(format!("No route for {}", path)));
+    }
+
+    let mut response = index::serve_index(page).await;
+    response.headers_mut().insert(header::CACHE_CONTROL, HeaderValue::from_static("no-cache"));
+    Ok(response)
+}
+
+// Add ETag and Cache-Control to static files, answering matching If-None-Match with 304
+pub async fn cache_headers<B>(request: Request<B>, next: Next<B>) -> Response {
+    let if_none_match = request.headers().get(header::IF_NONE_MATCH).cloned();
+    let mut response = next.run(request).await;
+
+    if response.status() != StatusCode::OK || response.headers().contains_key(header::CACHE_CONTROL) {
+        return response;
+    }
+
+    let Some(etag) = etag(response.headers()) else {
+        return response;
+    };
+
+    if if_none_match.as_ref().is_some_and(|value| value == etag) {
+        return (StatusCode::NOT_MODIFIED, [(header::ETAG, etag)]).into_response();
+    }
+
+    let headers = response.headers_mut();
+    headers.insert(header::ETAG, etag);
+    headers.insert(header::CACHE_CONTROL, HeaderValue::from_static("public, max-age=3600"));
+    response
+}
+
+// Weak validator from the file size and modification time
+fn etag(headers: &HeaderMap) -> Option<HeaderValue> {
+    let length = headers.get(header::CONTENT_LENGTH)?.to_str().ok()?.parse::<u64>().ok()?;
+    let modified = DateTime::parse_from_rfc2822(headers.get(header::LAST_MODIFIED)?.to_str().ok()?).ok()?;
+
+    HeaderValue::from_str(&format!("W/\"{:x}-{:x}\"", length, modified.timestamp())).ok()
+}
+
+#[cfg(test)]
+mod tests {
+    use axum::http::header;
+    use crate::test_support;
+
+    #[tokio::test]
+    async fn static_asset_carries_content_type_and_etag() {
+        let dir = tempfile::tempdir().unwrap();
+        std::fs::write(dir.path().join("app.js"), "console.log('hi');").unwrap();
+        let (router, _) = test_support::app(test_support::config(&[("STATIC_DIR", dir.path().to_str().unwrap())]));
+
+        let response = test_support::send(&router, test_support::get("/app.js", &[])).await;
+
+        assert_eq!(response.status, 200);
+        assert_eq!(response.headers[header::CONTENT_TYPE], "text/javascript");
+        assert_eq!(response.headers[header::CACHE_CONTROL], "public, max-age=3600");
+        let etag = response.headers[header::ETAG].to_str().unwrap().to_string();
+        assert!(etag.starts_with("W/\""), "{}", etag);
+
+        let revalidated = test_support::send(&router, test_support::get("/app.js", &[("If-None-Match", &etag)])).await;
+        assert_eq!(revalidated.status, 304);
+    }
+
+    #[tokio::test]
+    async fn unknown_api_paths_are_not_answered_with_the_index() {
+        let (router, _) = test_support::app(test_support::config(&[]));
+
+        let api = test_support::send(&router, test_support::get("/api/unknown", &[])).await;
+        let client_route = test_support::send(&router, test_support::get("/settings/profile", &[])).await;
+
+        assert_eq!(api.status, 404);
+        assert_eq!(api.json()["code"], "NOT_FOUND");
+        assert_eq!(client_route.headers[header::CACHE_CONTROL], "no-cache");
+    }
+}
@@ -0,0 +1,88 @@
+use axum::{async_trait, extract::FromRequestParts, http::{header, request::Parts, HeaderMap}};
+use crate::error::{AppError, AppResult};
+
+// Bearer token from the Authorization header, if one was sent
+pub struct AuthToken(pub Option<String>);
+
+// Bearer token from the Authorization header, rejecting the request when absent
+pub struct RequiredAuthToken(pub String);
+
+// Parse the bearer token, rejecting values that are not `Bearer <token>`
+fn parse_bearer(headers: &HeaderMap) -> AppResult<Option<String>> {
+    let Some(value) = headers.get(header::AUTHORIZATION) else {
+        return Ok(None);
+    };
+
+    let malformed = || AppError::Authentication("Malformed Authorization header".to_string());
+    let token = value.to_str()
+        .map_err(|_| malformed())?
+        .trim()
+        .strip_prefix("Bearer ")
+        .map(str::trim)
+        .filter(|token| !token.is_empty() && !token.contains(char::is_whitespace))
+        .ok_or_else(malformed)?;
+
+    Ok(Some(token.to_string()))
+}
+
+#[async_trait]
+impl<S: Send + Sync> FromRequestParts<S> for AuthToken {
+    type Rejection = AppError;
+
+    async fn from_request_parts(parts: &mut Parts, _state: &S) -> AppResult<Self> {
+        parse_bearer(&parts.headers).map(AuthToken)
+    }
+}
+
+#[async_trait]
+impl<S: Send + Sync> FromRequestParts<S> for RequiredAuthToken {
+    type Rejection = AppError;
+
+    async fn from_request_parts(parts: &mut Parts, _state: &S) -> AppResult<Self> {
+        parse_bearer(&parts.headers)?
+            .map(RequiredAuthToken)
+            .ok_or_else(|| AppError::Authentication("Missing bearer token".to_string()))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use axum::{body::Body, http::Request};
+    use super::*;
+
+    // Run an extractor over a request with the given Authorization header
+    async fn extract<T: FromRequestParts<()>>(authorization: Option<&[u8]>) -> Result<T, T::Rejection> {
+        let mut request = Request::builder();
+        if let Some(value) = authorization {
+            request = request.header(header::AUTHORIZATION, value);
+        }
+        let (mut parts, _) = request.body(Body::empty()).unwrap().into_parts();
+        T::from_request_parts(&mut parts, &()).await
+    }
+
+    #[tokio::test]
+    async fn present_bearer_token_is_trimmed() {
+        let AuthToken(token) = extract(Some(b"Bearer  abc.def ")).await.unwrap();
+        assert_eq!(token.as_deref(), Some("abc.def"));
+
+        let RequiredAuthToken(token) = extract(Some(b"Bearer abc.def")).await.unwrap();
+        assert_eq!(token, "abc.def");
+    }
+
+    #[tokio::test]
+    async fn absent_header_is_optional_for_auth_token_only() {
+        let AuthToken(token) = extract(None).await.unwrap();
+        assert_eq!(token, None);
+
+        let missing = extract::<RequiredAuthToken>(None).await;
+        assert!(matches!(missing, Err(AppError::Authentication(msg)) if msg == "Missing bearer token"));
+    }
+
+    #[tokio::test]
+    async fn malformed_header_is_rejected() {
+        for value in [&b"abc.def"[..], b"Basic dXNlcjpwYXNz", b"Bearer ", b"Bearer a b", b"Bearer \xff"] {
+            let result = extract::<AuthToken>(Some(value)).await;
+            assert!(matches!(result, Err(AppError::Authentication(msg)) if msg == "Malformed Authorization header"), "{:?}", value);
+        }
+    }
+}
@@ -0,0 +1,177 @@
+use std::sync::Arc;
+use axum::{
+    body::{Bytes, HttpBody},
+    http::{header, HeaderMap, Method},
+    response::{IntoResponse, Response},
+    Extension, Json,
+};
+use futures_util::future::join_all;
+use serde::Deserialize;
+use serde_json::{json, Value};
+
+use crate::auth::AuthToken;
+use crate::config::Config;
+use crate::discovery::ServiceRegistry;
+use crate::error::{AppError, AppResult};
+use crate::handler::{self, ServiceRequest};
+use crate::limiter::BackendLimiter;
+use crate::service::ServiceType;
+
+// Services to call in one batch, by name as in /api/service/:service
+#[derive(Debug, Deserialize)]
+pub struct BatchRequest {
+    pub services: Vec<String>,
+}
+
+// GET several services at once with the caller's credentials, answering with each
+// service's response in request order. Every call takes a backend slot like any
+// other request, so a batch never exceeds MAX_CONCURRENT_BACKEND.
+pub async fn handle_batch(
+    Extension(config): Extension<Arc<Config>>,
+    Extension(limiter): Extension<Arc<BackendLimiter>>,
+    Extension(registry): Extension<Arc<ServiceRegistry>>,
+    AuthToken(auth): AuthToken,
+    mut headers: HeaderMap,
+    Json(batch): Json<BatchRequest>,
+) -> AppResult<Json<Value>> {
+    let services = plan(&batch.services, config.max_batch_size)?;
+
+    // The calls carry no body, so the batch's own body headers do not apply
+    headers.remove(header::CONTENT_TYPE);
+    headers.remove(header::CONTENT_LENGTH);
+
+    let calls = services.into_iter().map(|service| {
+        let request = ServiceRequest { method: Method::GET, auth: auth.clone(), query: None, headers: headers.clone(), body: Bytes::new() };
+        let (config, limiter, registry) = (&config, &limiter, &registry);
+        async move {
+            let response = handler::handle_service_request(config, limiter, registry, service, request).await.into_response();
+            json!({
+                "service": service.name(),
+                "status_code": response.status().as_u16(),
+                "response": body_json(response).await,
+            })
+        }
+    });
+
+    Ok(Json(json!({ "results": join_all(calls).await })))
+}
+
+// Services named by a batch, in first-mention order and without repeats. Oversized
+// batches are refused before anything is parsed or called.
+fn plan(names: &[String], max_batch_size: usize) -> AppResult<Vec<ServiceType>> {
+    if names.is_empty() {
+        return Err(AppError::BadRequest("Batch names no services".to_string()));
+    }
+    if names.len() > max_batch_size {
+        return Err(AppError::BadRequest(format!(
+            "Batch of {} services exceeds MAX_BATCH_SIZE of {}", names.len(), max_batch_size
+        )));
+    }
+
+    let mut services = Vec::new();
+    for name in names {
+        let service = name.parse::<ServiceType>()
+            .map_err(|_| AppError::BadRequest(format!("Unknown service: {}", name)))?;
+        if !services.contains(&service) {
+            services.push(service);
+        }
+    }
+    Ok(services)
+}
+
+// A call's response body as JSON, or as text when it is not JSON
+async fn body_json(response: Response) -> Value {
+    let mut body = response.into_body();
+    let mut bytes = Vec::new();
+    while let Some(chunk) = body.data().await {
+        match chunk {
+            Ok(chunk) => bytes.extend_from_slice(&chunk),
+            Err(e) => return Value::String(format!("Unreadable response: {}", e)),
+        }
+    }
+    serde_json::from_slice(&bytes).unwrap_or_else(|_| Value::String(String::from_utf8_lossy(&bytes).into_owned()))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::{atomic::{AtomicUsize, Ordering}, Arc};
+    use std::time::Duration;
+    use axum::http::Method;
+    use serde_json::json;
+
+    use super::*;
+    use crate::test_support::{self, MockBackend};
+
+    fn batch(services: &[&str]) -> axum::http::Request<axum::body::Body> {
+        test_support::json_request(Method::POST, "/api/batch", &[], &json!({"services": services}))
+    }
+
+    #[tokio::test]
+    async fn repeated_services_are_called_once() {
+        let logs = MockBackend::json(&json!({"logs": []}));
+        let users = MockBackend::json(&json!({"users": []}));
+        let (router, _) = test_support::app(test_support::config(&[("LOG_SERVICE_URL", &logs.url()), ("USER_SERVICE_URL", &users.url())]));
+
+        let response = test_support::send(&router, batch(&["log", "users", "log", "log"])).await;
+
+        assert_eq!(response.status, 200, "{}", String::from_utf8_lossy(&response.body));
+        let results = response.json()["results"].clone();
+        assert_eq!(results.as_array().unwrap().len(), 2);
+        assert_eq!(results[0]["service"], "log");
+        assert_eq!(results[0]["status_code"], 200);
+        assert_eq!(results[0]["response"]["backend_response"]["logs"], json!([]));
+        assert_eq!(results[1]["service"], "users");
+        assert_eq!((logs.requests().len(), users.requests().len()), (1, 1));
+    }
+
+    #[tokio::test]
+    async fn batches_over_the_size_cap_are_rejected() {
+        let logs = MockBackend::json(&json!({}));
+        let (router, _) = test_support::app(test_support::config(&[("LOG_SERVICE_URL", &logs.url()), ("MAX_BATCH_SIZE", "2")]));
+
+        let response = test_support::send(&router, batch(&["log", "log", "log"])).await;
+
+        assert_eq!(response.status, 400);
+        assert!(response.json()["message"].as_str().unwrap().contains("exceeds MAX_BATCH_SIZE of 2"));
+        assert!(logs.requests().is_empty());
+        assert_eq!(test_support::send(&router, batch(&[])).await.status, 400);
+        assert_eq!(test_support::send(&router, batch(&["log", "billing"])).await.status, 400);
+    }
+
+    #[test]
+    fn default_size_cap_is_ten() {
+        let names = vec!["log".to_string(); 11];
+        let max = test_support::config(&[]).max_batch_size;
+
+        assert_eq!(max, 10);
+        assert_eq!(plan(&names[..10], max).unwrap(), [ServiceType::Log]);
+        assert!(plan(&names, max).is_err());
+    }
+
+    #[tokio::test(flavor = "multi_thread", worker_threads = 4)]
+    async fn calls_are_bounded_by_the_backend_concurrency_cap() {
+        // Both backends hold each call briefly and record the peak number served at once
+        let (current, peak) = (Arc::new(AtomicUsize::new(0)), Arc::new(AtomicUsize::new(0)));
+        let slow = || {
+            let (current, peak) = (Arc::clone(&current), Arc::clone(&peak));
+            MockBackend::start(move |_| {
+                peak.fetch_max(current.fetch_add(1, Ordering::SeqCst) + 1, Ordering::SeqCst);
+                std::thread::sleep(Duration::from_millis(100));
+                current.fetch_sub(1, Ordering::SeqCst);
+                test_support::http_response(200, "application/json", b"{}")
+            })
+        };
+        let (logs, users) = (slow(), slow());
+        let (router, _) = test_support::app(test_support::config(&[
+            ("LOG_SERVICE_URL", &logs.url()),
+            ("USER_SERVICE_URL", &users.url()),
+            ("MAX_CONCURRENT_BACKEND", "1"),
+        ]));
+
+        let response = test_support::send(&router, batch(&["log", "users"])).await;
+
+        let results = response.json()["results"].clone();
+        assert!(results.as_array().unwrap().iter().all(|result| result["status_code"] == 200), "{}", results);
+        assert_eq!(peak.load(Ordering::SeqCst), 1);
+    }
+}
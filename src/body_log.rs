@@ -0,0 +1,129 @@
+use serde_json::Value;
+use crate::service::ServiceType;
+
+// Placeholder for redacted values
+const REDACTED: &str = "***";
+
+// How forwarded bodies are logged
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum BodyLogMode {
+    Off,
+    // JSON bodies with configured fields masked; other bodies omitted
+    Redacted,
+    Full,
+}
+
+// Debug logging of forwarded request and response bodies
+#[derive(Debug, Clone)]
+pub struct BodyLogger {
+    pub mode: BodyLogMode,
+    // Lowercased JSON field names to mask
+    pub redact_fields: Vec<String>,
+}
+
+impl BodyLogger {
+    // Log a request body; the token itself is never logged
+    pub fn log_request(&self, service: ServiceType, has_token: bool, body: &[u8]) {
+        if self.mode == BodyLogMode::Off { return; }
+
+        tracing::info!(
+            "Request to {} service (authorization: {}): {}",
+            service.name(), if has_token { "Bearer ***" } else { "none" }, self.render(body)
+        );
+    }
+
+    // Log a response body
+    pub fn log_response(&self, service: ServiceType, status: u16, body: &[u8]) {
+        if self.mode == BodyLogMode::Off { return; }
+
+        tracing::info!("Response from {} service ({}): {}", service.name(), status, self.render(body));
+    }
+
+    fn render(&self, body: &[u8]) -> String {
+        if body.is_empty() { return "<empty>".to_string(); }
+
+        match self.mode {
+            BodyLogMode::Full => String::from_utf8_lossy(body).to_string(),
+            _ => match serde_json::from_slice::<Value>(body) {
+                Ok(mut json) => {
+                    self.redact(&mut json);
+                    json.to_string()
+                },
+                Err(_) => format!("<{} bytes, not JSON, omitted>", body.len()),
+            },
+        }
+    }
+
+    // Mask configured fields at any depth
+    fn redact(&self, value: &mut Value) {
+        match value {
+            Value::Object(map) => {
+                for (key, field) in map.iter_mut() {
+                    if self.redact_fields.iter().any(|name| name.eq_ignore_ascii_case(key)) {
+                        *field = Value::String(REDACTED.to_string());
+                    } else {
+                        self.redact(field);
+                    }
+                }
+            },
+            Value::Array(items) => items.iter_mut().for_each(|item| self.redact(item)),
+            _ => {},
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use axum::http::Method;
+    use serde_json::json;
+    use super::*;
+    use crate::test_support::{self, CapturedLogs, MockBackend};
+
+    fn logger(mode: BodyLogMode) -> BodyLogger {
+        BodyLogger { mode, redact_fields: vec!["password".to_string(), "token".to_string()] }
+    }
+
+    #[test]
+    fn redaction_masks_configured_fields_at_any_depth() {
+        let body = json!({
+            "user": "alice",
+            "Password": "hunter2",
+            "sessions": [{"token": "abc", "device": "laptop"}],
+        });
+
+        let rendered: Value = serde_json::from_str(&logger(BodyLogMode::Redacted).render(body.to_string().as_bytes())).unwrap();
+
+        assert_eq!(rendered, json!({
+            "user": "alice",
+            "Password": "***",
+            "sessions": [{"token": "***", "device": "laptop"}],
+        }));
+    }
+
+    #[test]
+    fn redacted_mode_omits_bodies_that_are_not_json() {
+        let rendered = logger(BodyLogMode::Redacted).render(b"password=hunter2");
+        assert_eq!(rendered, "<16 bytes, not JSON, omitted>");
+    }
+
+    #[test]
+    fn full_mode_logs_the_body_as_sent() {
+        let body = br#"{"password":"hunter2"}"#;
+        assert_eq!(logger(BodyLogMode::Full).render(body), r#"{"password":"hunter2"}"#);
+    }
+
+    #[tokio::test]
+    async fn token_never_reaches_the_logs_in_full_mode() {
+        let logs = MockBackend::json(&json!({}));
+        let (router, _) = test_support::app(test_support::config(&[("LOG_SERVICE_URL", &logs.url()), ("LOG_BODIES", "full")]));
+        let (captured, _guard) = CapturedLogs::start();
+
+        test_support::send(&router, test_support::json_request(
+            Method::POST, "/api/service/log", &[("Authorization", "Bearer secret-token-xyz")], &json!({"event": "login"}),
+        )).await;
+
+        let output = captured.contents();
+        assert!(output.contains(r#"(authorization: Bearer ***): {"event":"login"}"#), "{}", output);
+        assert!(!output.contains("secret-token-xyz"), "{}", output);
+    }
+}
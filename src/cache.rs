@@ -0,0 +1,179 @@
+use std::{collections::HashMap, sync::Mutex, time::{Duration, Instant}};
+use once_cell::sync::OnceCell;
+use serde_json::Value;
+
+use crate::api_response::ApiResponse;
+use crate::clock::SharedClock;
+use crate::jwt::{self, JwtConfig};
+use crate::service::ServiceType;
+
+// Response cache, enabled by init with a non-zero size
+static RESPONSE_CACHE: OnceCell<Mutex<ResponseCache>> = OnceCell::new();
+
+// Enable response cache with the given capacity, expiring entries by the clock
+pub fn init(capacity: usize, clock: SharedClock) {
+    if capacity > 0 {
+        let _ = RESPONSE_CACHE.set(Mutex::new(ResponseCache::new(capacity, clock)));
+    }
+}
+
+// Cache key, partitioned by authenticated subject to avoid cross-user leakage
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct CacheKey {
+    pub service: ServiceType,
+    pub path: String,
+    pub subject: Option<String>,
+}
+
+impl CacheKey {
+    // Build cache key for a request, or None if the request must not be cached
+    pub fn for_request(jwt_config: &JwtConfig, service: ServiceType, path: &str, token: Option<&str>) -> Option<Self> {
+        RESPONSE_CACHE.get()?;
+
+        // Only verified tokens may share a partition; anything else bypasses the cache
+        let subject = match token {
+            Some(token) => Some(jwt::verify_jwt(jwt_config, token).ok()?.sub),
+            None => None,
+        };
+
+        Some(Self { service, path: path.to_string(), subject })
+    }
+}
+
+struct CacheEntry {
+    response: ApiResponse,
+    expires_at: Instant,
+    last_used: u64,
+}
+
+// LRU response cache with per-entry expiry
+pub struct ResponseCache {
+    capacity: usize,
+    entries: HashMap<CacheKey, CacheEntry>,
+    tick: u64,
+    clock: SharedClock,
+}
+
+impl ResponseCache {
+    pub fn new(capacity: usize, clock: SharedClock) -> Self {
+        Self { capacity, entries: HashMap::new(), tick: 0, clock }
+    }
+
+    // Get unexpired response and mark it as recently used
+    pub fn get(&mut self, key: &CacheKey) -> Option<ApiResponse> {
+        self.tick += 1;
+        let tick = self.tick;
+
+        match self.entries.get_mut(key) {
+            Some(entry) if entry.expires_at > self.clock.now() => {
+                entry.last_used = tick;
+                Some(entry.response.clone())
+            },
+            Some(_) => {
+                self.entries.remove(key);
+                None
+            },
+            None => None,
+        }
+    }
+
+    // Insert response, evicting the least recently used entry when full
+    pub fn insert(&mut self, key: CacheKey, response: ApiResponse, max_age: Duration) {
+        if self.capacity == 0 { return; }
+
+        if !self.entries.contains_key(&key) && self.entries.len() >= self.capacity {
+            let lru = self.entries.iter()
+                .min_by_key(|(_, entry)| entry.last_used)
+                .map(|(key, _)| key.clone());
+            if let Some(lru) = lru {
+                self.entries.remove(&lru);
+            }
+        }
+
+        self.tick += 1;
+        self.entries.insert(key, CacheEntry {
+            response,
+            expires_at: self.clock.now() + max_age,
+            last_used: self.tick,
+        });
+    }
+}
+
+// Look up cached response
+pub fn get(key: &CacheKey) -> Option<ApiResponse> {
+    let mut response = RESPONSE_CACHE.get()?.lock().ok()?.get(key)?;
+
+    if let Some(obj) = response.proxy_info.as_object_mut() {
+        obj.insert("cache".to_string(), Value::String("hit".to_string()));
+    }
+
+    Some(response)
+}
+
+// Store response for the given max age
+pub fn put(key: CacheKey, response: &ApiResponse, max_age: Duration) {
+    if let Some(Ok(mut cache)) = RESPONSE_CACHE.get().map(|c| c.lock()) {
+        cache.insert(key, response.clone(), max_age);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use super::*;
+    use crate::api_response::ApiResponseBuilder;
+    use crate::clock::{self, TestClock};
+
+    fn key(subject: Option<&str>) -> CacheKey {
+        CacheKey { service: ServiceType::Users, path: "/api/users".to_string(), subject: subject.map(str::to_string) }
+    }
+
+    fn response(body: Value) -> ApiResponse {
+        ApiResponseBuilder::new().backend_response(body).build()
+    }
+
+    #[test]
+    fn hit_within_max_age() {
+        let mut cache = ResponseCache::new(4, clock::system());
+        cache.insert(key(Some("alice")), response(json!({"n": 1})), Duration::from_secs(60));
+
+        let cached = cache.get(&key(Some("alice"))).expect("cache hit");
+        assert_eq!(cached.backend_response, json!({"n": 1}));
+    }
+
+    #[test]
+    fn miss_after_expiry() {
+        let clock = TestClock::new();
+        let mut cache = ResponseCache::new(4, clock.clone());
+        cache.insert(key(None), response(json!({})), Duration::from_secs(60));
+
+        clock.advance(Duration::from_secs(59));
+        assert!(cache.get(&key(None)).is_some());
+        clock.advance(Duration::from_secs(1));
+        assert!(cache.get(&key(None)).is_none());
+        assert!(cache.entries.is_empty());
+    }
+
+    #[test]
+    fn subjects_are_isolated() {
+        let mut cache = ResponseCache::new(4, clock::system());
+        cache.insert(key(Some("alice")), response(json!({"owner": "alice"})), Duration::from_secs(60));
+
+        assert!(cache.get(&key(Some("bob"))).is_none());
+        assert!(cache.get(&key(None)).is_none());
+        assert_eq!(cache.get(&key(Some("alice"))).unwrap().backend_response["owner"], "alice");
+    }
+
+    #[test]
+    fn least_recently_used_entry_is_evicted() {
+        let mut cache = ResponseCache::new(2, clock::system());
+        cache.insert(key(Some("alice")), response(json!({})), Duration::from_secs(60));
+        cache.insert(key(Some("bob")), response(json!({})), Duration::from_secs(60));
+        cache.get(&key(Some("alice")));
+        cache.insert(key(Some("carol")), response(json!({})), Duration::from_secs(60));
+
+        assert!(cache.get(&key(Some("bob"))).is_none());
+        assert!(cache.get(&key(Some("alice"))).is_some());
+    }
+}
@@ -0,0 +1,142 @@
+use std::{fs, io::Write, path::Path, process::Command};
+use crate::error::{AppError, AppResult};
+
+// Signature algorithm for every generated certificate
+const SIGNATURE_ALGORITHM: &str = "ML-DSA-87";
+
+const SERVER_EXT: &str = "\
+[ server_ext ]
+basicConstraints = CA:FALSE
+keyUsage = digitalSignature, keyEncipherment
+extendedKeyUsage = serverAuth
+subjectAltName = DNS:localhost, IP:127.0.0.1
+";
+
+const CLIENT_EXT: &str = "\
+[ client_ext ]
+basicConstraints = CA:FALSE
+keyUsage = digitalSignature
+extendedKeyUsage = clientAuth
+";
+
+// Generate a hybrid CA plus server and client certificates for mTLS, using the
+// file layout the default CLIENT_CERT_PATH, CLIENT_KEY_PATH and CA_CERT_PATH expect
+pub fn generate(openssl: &str, dir: &Path) -> AppResult<()> {
+    let ca_dir = dir.join("hybrid-ca");
+    let server_dir = dir.join("hybrid-server");
+    let client_dir = dir.join("hybrid-client");
+    for sub in [&ca_dir, &server_dir, &client_dir] {
+        fs::create_dir_all(sub)
+            .map_err(|e| AppError::Internal(format!("Cannot create {}: {}", sub.display(), e)))?;
+    }
+
+    let ca_key = ca_dir.join("ca.key");
+    let ca_crt = ca_dir.join("ca.crt");
+    let server_key = server_dir.join("server.key");
+    let server_crt = server_dir.join("server.crt");
+    let client_key = client_dir.join("client_pkcs8.key");
+    let client_crt = client_dir.join("client.crt");
+
+    println!("Using OpenSSL: {}", openssl);
+
+    // CA
+    run(openssl, &["genpkey", "-algorithm", SIGNATURE_ALGORITHM, "-out", path(&ca_key)])?;
+    run(openssl, &[
+        "req", "-new", "-x509", "-key", path(&ca_key), "-out", path(&ca_crt),
+        "-days", "3650", "-subj", "/CN=Hybrid-PQC-CA",
+    ])?;
+
+    // Server and client certificates signed by the CA
+    issue(openssl, &ca_key, &ca_crt, &server_key, &server_crt, "/CN=localhost", "server_ext", SERVER_EXT)?;
+    issue(openssl, &ca_key, &ca_crt, &client_key, &client_crt, "/CN=client", "client_ext", CLIENT_EXT)?;
+
+    println!("Certificates written to {}. Set:", dir.display());
+    println!("CA_CERT_PATH={}", ca_crt.display());
+    println!("CLIENT_CERT_PATH={}", client_crt.display());
+    println!("CLIENT_KEY_PATH={}", client_key.display());
+    println!("Proxy server certificate: {} (key {})", server_crt.display(), server_key.display());
+
+    Ok(())
+}
+
+// Generate a key and a certificate for it signed by the CA
+#[allow(clippy::too_many_arguments)]
+fn issue(
+    openssl: &str,
+    ca_key: &Path,
+    ca_crt: &Path,
+    key: &Path,
+    crt: &Path,
+    subject: &str,
+    section: &str,
+    extensions: &str,
+) -> AppResult<()> {
+    let csr = crt.with_extension("csr");
+    run(openssl, &["genpkey", "-algorithm", SIGNATURE_ALGORITHM, "-out", path(key)])?;
+    run(openssl, &["req", "-new", "-key", path(key), "-out", path(&csr), "-subj", subject])?;
+
+    let mut ext_file = tempfile::NamedTempFile::new()
+        .map_err(|e| AppError::Internal(format!("Cannot create extension file: {}", e)))?;
+    ext_file.write_all(extensions.as_bytes())
+        .map_err(|e| AppError::Internal(format!("Cannot write extension file: {}", e)))?;
+
+    run(openssl, &[
+        "x509", "-req", "-in", path(&csr),
+        "-CA", path(ca_crt), "-CAkey", path(ca_key), "-CAcreateserial",
+        "-out", path(crt), "-days", "365",
+        "-extfile", path(ext_file.path()), "-extensions", section,
+    ])?;
+
+    let _ = fs::remove_file(&csr);
+    Ok(())
+}
+
+fn path(p: &Path) -> &str {
+    p.to_str().unwrap_or_default()
+}
+
+// Run an OpenSSL command, failing with its stderr
+fn run(openssl: &str, args: &[&str]) -> AppResult<()> {
+    let output = Command::new(openssl)
+        .args(args)
+        .output()
+        .map_err(|e| AppError::Internal(format!("OpenSSL startup error: {}", e)))?;
+
+    if !output.status.success() {
+        return Err(AppError::Internal(format!(
+            "openssl {} failed: {}", args.first().copied().unwrap_or_default(),
+            String::from_utf8_lossy(&output.stderr).trim()
+        )));
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::BTreeSet;
+    use super::*;
+    use crate::test_support::StubOpenssl;
+
+    #[test]
+    fn stub_openssl_is_asked_for_the_default_file_set() {
+        let stub = StubOpenssl::new(b"", "");
+        let dir = tempfile::tempdir().unwrap();
+
+        generate(&stub.path(), dir.path()).unwrap();
+
+        let prefix = format!("{}/", dir.path().display());
+        let written: BTreeSet<String> = stub.invocations().iter()
+            .filter_map(|args| args.split(' ').skip_while(|arg| *arg != "-out").nth(1).map(str::to_string))
+            .filter(|out| !out.ends_with(".csr"))
+            .map(|out| out.trim_start_matches(&prefix).to_string())
+            .collect();
+        assert_eq!(written, BTreeSet::from([
+            "hybrid-ca/ca.key", "hybrid-ca/ca.crt",
+            "hybrid-server/server.key", "hybrid-server/server.crt",
+            "hybrid-client/client_pkcs8.key", "hybrid-client/client.crt",
+        ].map(str::to_string)));
+        assert!(stub.invocations().iter().all(|args| !args.starts_with("genpkey") || args.contains("-algorithm ML-DSA-87")));
+        assert!(stub.invocations().iter().any(|args| args.contains("-extensions client_ext")));
+    }
+}
@@ -0,0 +1,213 @@
+use std::{collections::HashMap, net::IpAddr, time::{Duration, Instant}};
+use crate::clock::SharedClock;
+use crate::error::{AppError, AppResult};
+
+// Outstanding WebAuthn challenge state
+struct Pending<T> {
+    state: T,
+    created: Instant,
+    source: IpAddr,
+}
+
+// Bounded store of outstanding challenge states. A single source hitting its cap
+// is rejected; when the store as a whole is full the oldest state is evicted.
+// States older than the TTL are unusable and removed by periodic sweeps.
+pub struct ChallengeStore<T> {
+    entries: HashMap<String, Pending<T>>,
+    // Per-key start of the current ceremony and states issued in it, until its state is taken
+    attempts: HashMap<String, (Instant, usize)>,
+    max_entries: usize,
+    max_per_source: usize,
+    ttl: Duration,
+    clock: SharedClock,
+}
+
+impl<T> ChallengeStore<T> {
+    pub fn new(max_entries: usize, max_per_source: usize, ttl: Duration, clock: SharedClock) -> Self {
+        Self { entries: HashMap::new(), attempts: HashMap::new(), max_entries: max_entries.max(1), max_per_source, ttl, clock }
+    }
+
+    // Time since a state was created, by the store's clock
+    fn age(&self, created: Instant) -> Duration {
+        self.clock.now().saturating_duration_since(created)
+    }
+
+    // Number of outstanding states, including expired ones not yet swept
+    pub fn count(&self) -> usize {
+        self.entries.len()
+    }
+
+    // Store state for a key, replacing any previous state for it
+    pub fn insert(&mut self, key: String, state: T, source: IpAddr) -> AppResult<()> {
+        let replacing = self.entries.get(&key).is_some_and(|pending| pending.source == source);
+        let from_source = self.entries.values().filter(|pending| pending.source == source).count();
+        if !replacing && from_source >= self.max_per_source {
+            tracing::warn!("Rejecting challenge from {}: {} outstanding", source, from_source);
+
+            // A slot frees up once the source's oldest challenge expires
+            let oldest = self.entries.values()
+                .filter(|pending| pending.source == source)
+                .map(|pending| pending.created)
+                .min();
+            let retry_after = oldest.map_or(self.ttl, |created| self.ttl.saturating_sub(self.age(created)));
+            return Err(AppError::RateLimited { message: "Too many pending challenges".to_string(), retry_after });
+        }
+
+        if !self.entries.contains_key(&key) && self.entries.len() >= self.max_entries {
+            self.evict_oldest();
+        }
+
+        self.entries.insert(key, Pending { state, created: self.clock.now(), source });
+        Ok(())
+    }
+
+    // Store state for a key at most max_attempts times, from any source, while a ceremony
+    // for it is pending. The count starts with the key's first state and ends once the
+    // state is taken or the TTL passes, so one account cannot be flooded with restarts.
+    pub fn insert_counted(&mut self, key: String, state: T, source: IpAddr, max_attempts: usize) -> AppResult<()> {
+        let window = self.attempts.get(&key).copied().filter(|(started, _)| self.age(*started) < self.ttl);
+        if let Some((started, attempts)) = window.filter(|(_, attempts)| *attempts >= max_attempts) {
+            tracing::warn!("Rejecting challenge from {}: {} attempts for the same key", source, attempts);
+            let retry_after = self.ttl.saturating_sub(self.age(started));
+            return Err(AppError::RateLimited { message: "Too many registration attempts for this user".to_string(), retry_after });
+        }
+
+        self.insert(key.clone(), state, source)?;
+        let (started, attempts) = window.unwrap_or((self.clock.now(), 0));
+        self.attempts.insert(key, (started, attempts + 1));
+        Ok(())
+    }
+
+    // Take the state for a key, unless it has expired, ending the key's attempt count.
+    // Callers hold the store's lock, so a challenge is consumed exactly once; a racing
+    // finish sees it gone.
+    pub fn remove(&mut self, key: &str) -> Option<T> {
+        self.attempts.remove(key);
+        let pending = self.entries.remove(key)?;
+        (self.age(pending.created) < self.ttl).then_some(pending.state)
+    }
+
+    // Drop expired states, returning how many were removed
+    pub fn sweep(&mut self) -> usize {
+        let before = self.entries.len();
+        let (ttl, now) = (self.ttl, self.clock.now());
+        self.entries.retain(|_, pending| now.saturating_duration_since(pending.created) < ttl);
+        self.attempts.retain(|_, (started, _)| now.saturating_duration_since(*started) < ttl);
+        before - self.entries.len()
+    }
+
+    fn evict_oldest(&mut self) {
+        let oldest = self.entries.iter()
+            .min_by_key(|(_, pending)| pending.created)
+            .map(|(key, _)| key.clone());
+
+        if let Some(key) = oldest {
+            tracing::warn!("Challenge store full, evicting oldest state");
+            self.entries.remove(&key);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{net::Ipv4Addr, sync::Arc};
+    use super::*;
+    use crate::clock::{self, TestClock};
+
+    fn ip(last: u8) -> IpAddr {
+        IpAddr::V4(Ipv4Addr::new(198, 51, 100, last))
+    }
+
+    fn store(max_entries: usize, max_per_source: usize) -> ChallengeStore<u32> {
+        ChallengeStore::new(max_entries, max_per_source, Duration::from_secs(300), clock::system())
+    }
+
+    fn timed_store(max_entries: usize) -> (ChallengeStore<u32>, Arc<TestClock>) {
+        let clock = TestClock::new();
+        (ChallengeStore::new(max_entries, 10, Duration::from_secs(300), clock.clone()), clock)
+    }
+
+    #[test]
+    fn source_at_its_cap_is_rate_limited() {
+        let mut store = store(100, 2);
+        store.insert("a".to_string(), 1, ip(1)).unwrap();
+        store.insert("b".to_string(), 2, ip(1)).unwrap();
+
+        let rejected = store.insert("c".to_string(), 3, ip(1));
+
+        assert!(matches!(rejected, Err(AppError::RateLimited { retry_after, .. }) if retry_after <= Duration::from_secs(300)));
+        assert!(store.insert("c".to_string(), 3, ip(2)).is_ok());
+        // Replacing a source's own state never counts against its cap
+        assert!(store.insert("a".to_string(), 4, ip(1)).is_ok());
+    }
+
+    #[test]
+    fn full_store_evicts_the_oldest_state() {
+        let (mut store, clock) = timed_store(2);
+        for (key, source) in [("first", 1), ("second", 2), ("third", 3)] {
+            store.insert(key.to_string(), 0, ip(source)).unwrap();
+            clock.advance(Duration::from_secs(1));
+        }
+
+        assert_eq!(store.count(), 2);
+        assert_eq!(store.remove("first"), None);
+        assert!(store.remove("second").is_some());
+        assert!(store.remove("third").is_some());
+    }
+
+    #[test]
+    fn state_is_unusable_once_the_ttl_passes() {
+        let (mut store, clock) = timed_store(10);
+        store.insert("fresh".to_string(), 1, ip(1)).unwrap();
+        store.insert("stale".to_string(), 2, ip(1)).unwrap();
+
+        clock.advance(Duration::from_secs(299));
+        assert_eq!(store.remove("fresh"), Some(1));
+        clock.advance(Duration::from_secs(1));
+        assert_eq!(store.remove("stale"), None);
+        assert_eq!(store.count(), 0);
+    }
+
+    #[test]
+    fn sweep_drops_only_expired_states() {
+        let (mut store, clock) = timed_store(10);
+        store.insert("old".to_string(), 1, ip(1)).unwrap();
+        clock.advance(Duration::from_secs(200));
+        store.insert("new".to_string(), 2, ip(2)).unwrap();
+
+        assert_eq!(store.sweep(), 0);
+        clock.advance(Duration::from_secs(100));
+        assert_eq!(store.sweep(), 1);
+        assert_eq!(store.remove("new"), Some(2));
+    }
+
+    #[test]
+    fn only_stored_attempts_count_and_windows_are_swept() {
+        let mut store = ChallengeStore::new(100, 1, Duration::from_secs(300), TestClock::new());
+        store.insert("other".to_string(), 0, ip(1)).unwrap();
+
+        // Rejected by the per-source cap, so not an attempt
+        assert!(store.insert_counted("alice".to_string(), 1, ip(1), 1).is_err());
+        store.insert_counted("alice".to_string(), 2, ip(2), 1).unwrap();
+        assert!(matches!(store.insert_counted("alice".to_string(), 3, ip(3), 1), Err(AppError::RateLimited { .. })));
+
+        let (mut store, clock) = timed_store(10);
+        store.insert_counted("alice".to_string(), 1, ip(1), 1).unwrap();
+        clock.advance(Duration::from_secs(300));
+        store.sweep();
+        assert!(store.attempts.is_empty());
+        assert!(store.insert_counted("alice".to_string(), 2, ip(1), 1).is_ok());
+    }
+
+    #[test]
+    fn taking_the_state_ends_the_attempt_count() {
+        let (mut store, _) = timed_store(10);
+        store.insert_counted("alice".to_string(), 1, ip(1), 1).unwrap();
+        assert!(store.insert_counted("alice".to_string(), 2, ip(1), 1).is_err());
+
+        assert_eq!(store.remove("alice"), Some(1));
+
+        assert!(store.attempts.is_empty());
+        assert!(store.insert_counted("alice".to_string(), 3, ip(1), 1).is_ok());
+    }
+}
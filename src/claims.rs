@@ -0,0 +1,122 @@
+use std::{fmt::Debug, sync::Arc};
+use crate::error::{AppError, AppResult};
+use crate::jwt::Claims;
+use crate::service::ServiceType;
+
+// Deployment-specific authorization rule, run on claims whose signature and
+// expiry have already been verified. Err carries the reason for the denial.
+pub trait ClaimsValidator: Debug + Send + Sync {
+    fn validate(&self, claims: &Claims, service: ServiceType) -> Result<(), String>;
+}
+
+// Requires the token to be addressed to the gateway's audience
+#[derive(Debug, Clone)]
+pub struct AudienceValidator {
+    pub audience: String,
+}
+
+impl ClaimsValidator for AudienceValidator {
+    fn validate(&self, claims: &Claims, _service: ServiceType) -> Result<(), String> {
+        if claims.aud == self.audience {
+            Ok(())
+        } else {
+            Err(format!("token audience {:?} is not {:?}", claims.aud, self.audience))
+        }
+    }
+}
+
+// Validators run in order; the first denial rejects the request
+#[derive(Debug, Clone, Default)]
+pub struct ClaimsValidators {
+    validators: Vec<Arc<dyn ClaimsValidator>>,
+}
+
+impl ClaimsValidators {
+    pub fn new(audience: &str) -> Self {
+        Self::default().with(AudienceValidator { audience: audience.to_string() })
+    }
+
+    // Append a validator
+    pub fn with(mut self, validator: impl ClaimsValidator + 'static) -> Self {
+        self.validators.push(Arc::new(validator));
+        self
+    }
+
+    pub fn validate(&self, claims: &Claims, service: ServiceType) -> AppResult<()> {
+        for validator in &self.validators {
+            if let Err(reason) = validator.validate(claims, service) {
+                tracing::warn!(target: "audit", "Denied {} for {} service: {}", claims.sub, service, reason);
+                return Err(AppError::Forbidden(format!("Token not permitted for {} service: {}", service, reason)));
+            }
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use jsonwebtoken::{encode, EncodingKey, Header};
+    use serde_json::json;
+
+    use super::*;
+    use crate::jwt;
+    use crate::test_support::{self, MockBackend};
+
+    // Deployment rule: callers need the given entry in their "roles" claim
+    #[derive(Debug)]
+    struct RequireRole(&'static str);
+
+    impl ClaimsValidator for RequireRole {
+        fn validate(&self, claims: &Claims, _service: ServiceType) -> Result<(), String> {
+            let roles = claims.extra.get("roles").and_then(|roles| roles.as_array());
+            match roles {
+                Some(roles) if roles.iter().any(|role| role == self.0) => Ok(()),
+                _ => Err(format!("missing role {:?}", self.0)),
+            }
+        }
+    }
+
+    // Gateway token for alice carrying the given extra claims
+    fn token(config: &jwt::JwtConfig, extra: serde_json::Value) -> String {
+        let token = jwt::issue_jwt(config, "user-1", "alice", &[], None).unwrap();
+        let mut claims = serde_json::to_value(jwt::verify_jwt(config, &token).unwrap()).unwrap();
+        claims.as_object_mut().unwrap().extend(extra.as_object().unwrap().clone());
+        encode(&Header::new(jwt::JWT_ALGORITHM), &claims, &EncodingKey::from_secret(config.secret.as_bytes())).unwrap()
+    }
+
+    #[test]
+    fn validators_run_in_order_and_the_first_denial_wins() {
+        let config = test_support::config(&[]);
+        let validators = ClaimsValidators::new(&config.jwt.audience).with(RequireRole("auditor"));
+        let claims = |extra| jwt::verify_jwt(&config.jwt, &token(&config.jwt, extra)).unwrap();
+
+        assert!(validators.validate(&claims(json!({"roles": ["auditor"]})), ServiceType::Log).is_ok());
+
+        let err = validators.validate(&claims(json!({"roles": ["viewer"]})), ServiceType::Log).unwrap_err();
+        assert!(matches!(&err, AppError::Forbidden(message) if message.ends_with("missing role \"auditor\"")), "{:?}", err);
+
+        let mut foreign = claims(json!({"roles": ["auditor"]}));
+        foreign.aud = "another-service".to_string();
+        let err = validators.validate(&foreign, ServiceType::Log).unwrap_err();
+        assert!(matches!(&err, AppError::Forbidden(message) if message.contains("audience")), "{:?}", err);
+    }
+
+    #[tokio::test]
+    async fn custom_validator_denies_a_token_without_the_role() {
+        let logs = MockBackend::json(&json!({"logs": []}));
+        let mut config = test_support::config(&[("LOG_SERVICE_URL", &logs.url()), ("REQUIRE_AUTH_PER_SERVICE", "log")]);
+        config.claims_validators = config.claims_validators.with(RequireRole("auditor"));
+        let jwt = config.jwt.clone();
+        let (router, _) = test_support::app(config);
+        let call = |token: &str| test_support::get("/api/service/log", &[("Authorization", &test_support::bearer(token))]);
+
+        let denied = test_support::send(&router, call(&token(&jwt, json!({})))).await;
+        assert_eq!(denied.status, 403, "{}", String::from_utf8_lossy(&denied.body));
+        assert!(denied.json()["message"].as_str().unwrap().contains("missing role"));
+        assert!(logs.requests().is_empty());
+
+        let allowed = test_support::send(&router, call(&token(&jwt, json!({"roles": ["auditor"]})))).await;
+        assert_eq!(allowed.status, 200, "{}", String::from_utf8_lossy(&allowed.body));
+        assert_eq!(logs.requests().len(), 1);
+    }
+}
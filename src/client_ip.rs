@@ -0,0 +1,99 @@
+use std::net::{IpAddr, SocketAddr};
+use axum::http::HeaderMap;
+use crate::config::Config;
+
+// Resolve the client IP. Forwarding headers are only honoured when proxies are trusted:
+// with N trusted hops, the N-th address from the right is the one the outermost
+// trusted proxy saw; anything left of it is client-controlled.
+pub fn resolve(config: &Config, headers: &HeaderMap, socket: SocketAddr) -> IpAddr {
+    let hops = config.trusted_proxy_hops;
+    if hops == 0 {
+        return socket.ip();
+    }
+
+    let chain = forwarded_chain(headers);
+    chain.len().checked_sub(hops)
+        .and_then(|index| chain.get(index).copied())
+        .unwrap_or_else(|| socket.ip())
+}
+
+// Addresses from X-Forwarded-For, or from the Forwarded header's `for=` parameters
+fn forwarded_chain(headers: &HeaderMap) -> Vec<IpAddr> {
+    let values = |name: &str| headers.get_all(name)
+        .iter()
+        .filter_map(|value| value.to_str().ok())
+        .flat_map(|value| value.split(','))
+        .map(|entry| entry.trim().to_string())
+        .collect::<Vec<_>>();
+
+    let xff = values("X-Forwarded-For");
+    if !xff.is_empty() {
+        return xff.iter().filter_map(|entry| parse_ip(entry)).collect();
+    }
+
+    values("Forwarded").iter()
+        .filter_map(|element| element.split(';')
+            .map(str::trim)
+            .find_map(|pair| pair.get(..4).filter(|key| key.eq_ignore_ascii_case("for=")).map(|_| &pair[4..])))
+        .filter_map(parse_ip)
+        .collect()
+}
+
+// Parse an address, allowing quotes, brackets and a port
+fn parse_ip(value: &str) -> Option<IpAddr> {
+    let value = value.trim().trim_matches('"');
+    if let Ok(ip) = value.parse() {
+        return Some(ip);
+    }
+    if let Ok(addr) = value.parse::<SocketAddr>() {
+        return Some(addr.ip());
+    }
+    value.strip_prefix('[')?.split(']').next()?.parse().ok()
+}
+
+#[cfg(test)]
+mod tests {
+    use axum::http::HeaderValue;
+    use super::*;
+    use crate::test_support;
+
+    const SOCKET: &str = "10.0.0.5:40000";
+
+    fn resolve_with(trust: &str, headers: &[(&'static str, &str)]) -> IpAddr {
+        let config = test_support::config(&[("TRUST_FORWARDED_FOR", trust)]);
+        let mut map = HeaderMap::new();
+        for (name, value) in headers {
+            map.append(*name, HeaderValue::from_str(value).unwrap());
+        }
+        resolve(&config, &map, SOCKET.parse().unwrap())
+    }
+
+    fn ip(value: &str) -> IpAddr {
+        value.parse().unwrap()
+    }
+
+    #[test]
+    fn trusted_hops_pick_the_address_the_outermost_proxy_saw() {
+        let chain = [("X-Forwarded-For", "6.6.6.6, 203.0.113.7, 10.0.0.9")];
+        assert_eq!(resolve_with("true", &chain), ip("10.0.0.9"));
+        assert_eq!(resolve_with("2", &chain), ip("203.0.113.7"));
+        // Hops split across repeated headers count as one chain
+        assert_eq!(resolve_with("2", &[("X-Forwarded-For", "6.6.6.6, 203.0.113.7"), ("X-Forwarded-For", "10.0.0.9")]), ip("203.0.113.7"));
+    }
+
+    #[test]
+    fn forwarded_header_is_used_without_x_forwarded_for() {
+        let forwarded = [("Forwarded", r#"for=6.6.6.6, for="[2001:db8::1]:4711";proto=https, For=198.51.100.2:80"#)];
+        assert_eq!(resolve_with("true", &forwarded), ip("198.51.100.2"));
+        assert_eq!(resolve_with("2", &forwarded), ip("2001:db8::1"));
+    }
+
+    #[test]
+    fn untrusted_headers_fall_back_to_the_socket_address() {
+        let chain = [("X-Forwarded-For", "203.0.113.7")];
+        assert_eq!(resolve_with("false", &chain), ip("10.0.0.5"));
+        // A chain shorter than the trusted hops cannot be attributed
+        assert_eq!(resolve_with("2", &chain), ip("10.0.0.5"));
+        assert_eq!(resolve_with("true", &[("X-Forwarded-For", "not-an-ip")]), ip("10.0.0.5"));
+    }
+}
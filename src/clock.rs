@@ -0,0 +1,67 @@
+use std::{fmt::Debug, sync::Arc, time::Instant};
+use chrono::{DateTime, Utc};
+
+// Time source for expiry decisions (token lifetimes, challenge TTLs), so they can
+// be driven by a controllable clock instead of the system one
+pub trait Clock: Debug + Send + Sync {
+    // Monotonic time, for measuring ages
+    fn now(&self) -> Instant;
+    // Wall-clock time, for timestamps such as JWT claims
+    fn utc(&self) -> DateTime<Utc>;
+}
+
+pub type SharedClock = Arc<dyn Clock>;
+
+// The real system clock
+#[derive(Debug, Clone, Copy, Default)]
+pub struct SystemClock;
+
+impl Clock for SystemClock {
+    fn now(&self) -> Instant {
+        Instant::now()
+    }
+
+    fn utc(&self) -> DateTime<Utc> {
+        Utc::now()
+    }
+}
+
+// Shared handle to the system clock
+pub fn system() -> SharedClock {
+    Arc::new(SystemClock)
+}
+
+// Clock that only moves when advanced, for expiry tests without sleeping
+#[cfg(test)]
+#[derive(Debug)]
+pub struct TestClock {
+    offset: std::sync::Mutex<std::time::Duration>,
+    start: Instant,
+    start_utc: DateTime<Utc>,
+}
+
+#[cfg(test)]
+impl TestClock {
+    pub fn new() -> Arc<Self> {
+        Arc::new(Self { offset: Default::default(), start: Instant::now(), start_utc: Utc::now() })
+    }
+
+    pub fn advance(&self, by: std::time::Duration) {
+        *self.offset.lock().unwrap() += by;
+    }
+
+    fn offset(&self) -> std::time::Duration {
+        *self.offset.lock().unwrap()
+    }
+}
+
+#[cfg(test)]
+impl Clock for TestClock {
+    fn now(&self) -> Instant {
+        self.start + self.offset()
+    }
+
+    fn utc(&self) -> DateTime<Utc> {
+        self.start_utc + chrono::Duration::from_std(self.offset()).unwrap_or_else(|_| chrono::Duration::zero())
+    }
+}
@@ -0,0 +1,523 @@
+use std::{collections::{HashMap, HashSet}, env, str::FromStr, sync::Arc, time::Duration};
+use crate::api_key::ApiKeys;
+use crate::body_log::{BodyLogMode, BodyLogger};
+use crate::claims::ClaimsValidators;
+use crate::clock::{self, SharedClock};
+use crate::error::{AppError, AppResult};
+use crate::host_policy::HostPolicy;
+use crate::http_client::{AuthTemplate, HeaderLimits, RetryPolicy, TcpOptions};
+use crate::jwt::JwtConfig;
+use crate::schema::ResponseSchema;
+use crate::service::{BackendTarget, Scheme, ServiceType};
+use crate::tls::{SessionCache, TlsConfig};
+use crate::webauthn::UserIdStrategy;
+
+// Service behind /api/auth/verify when DEFAULT_SERVICE is unset. The log service's
+// /api path is where the gateway forwarded everything before per-service routing, so
+// deployments that predate DEFAULT_SERVICE keep their behavior.
+pub const FALLBACK_DEFAULT_SERVICE: ServiceType = ServiceType::Log;
+
+// Gateway configuration, loaded once at startup
+#[derive(Debug, Clone)]
+pub struct Config {
+    pub port: u16,
+    pub default_service: ServiceType,
+    pub service_targets: HashMap<ServiceType, Vec<BackendTarget>>,
+    pub host_policy: HostPolicy,
+    pub sticky_services: HashSet<ServiceType>,
+    pub auth_templates: HashMap<ServiceType, AuthTemplate>,
+    pub host_headers: HashMap<ServiceType, String>,
+    pub expected_content_types: HashMap<ServiceType, Vec<String>>,
+    pub service_scopes: HashMap<ServiceType, Vec<String>>,
+    pub response_schemas: HashMap<ServiceType, ResponseSchema>,
+    pub auth_required_services: HashSet<ServiceType>,
+    pub step_up_services: HashSet<ServiceType>,
+    pub step_up_max_age: Duration,
+    pub request_timeout: Option<Duration>,
+    pub service_timeouts: HashMap<ServiceType, Duration>,
+    pub slow_request: Duration,
+    pub health_check_interval: Option<Duration>,
+    pub wait_for_backend: Option<Duration>,
+    pub startup_tls_selftest: bool,
+    pub strict_selftest: bool,
+    pub max_concurrent_backend: usize,
+    pub max_concurrent_backend_per_service: Option<usize>,
+    pub backend_queue_timeout: Duration,
+    // Services one /api/batch request may name, repeats included
+    pub max_batch_size: usize,
+    pub response_cache_size: usize,
+    pub propagate_backend_status: bool,
+    pub empty_body_response: serde_json::Value,
+    pub header_limits: HeaderLimits,
+    pub surface_response_headers: Vec<String>,
+    pub retry: RetryPolicy,
+    pub tcp: TcpOptions,
+    pub body_logger: BodyLogger,
+    pub webauthn_rp_name: String,
+    pub index_html_path: String,
+    pub static_dir: String,
+    pub base_path: String,
+    pub user_id_strategy: UserIdStrategy,
+    pub max_pending_challenges: usize,
+    pub max_pending_challenges_per_ip: usize,
+    pub max_registration_attempts_per_user: usize,
+    pub challenge_ttl: Duration,
+    pub state_sweep_interval: Duration,
+    pub require_backup_eligible: bool,
+    pub require_user_handle: bool,
+    pub max_credential_payload_bytes: usize,
+    pub max_credentials_per_user: Option<usize>,
+    pub issue_jwt: bool,
+    pub admin_token: Option<String>,
+    pub maintenance_mode: bool,
+    pub maintenance_retry_after: Duration,
+    pub maintenance_includes_auth: bool,
+    pub api_keys: ApiKeys,
+    pub trusted_proxy_hops: usize,
+    pub jwt: JwtConfig,
+    // Authorization rules applied to verified tokens; deployments add their own
+    pub claims_validators: ClaimsValidators,
+    pub tls: TlsConfig,
+    pub service_tls: HashMap<ServiceType, TlsConfig>,
+    // Time source for expiry decisions; also held by jwt
+    pub clock: SharedClock,
+}
+
+impl Config {
+    // Load configuration from environment variables
+    pub fn from_env() -> AppResult<Self> {
+        Self::from_vars(|key| env::var(key).ok())
+    }
+
+    // Load configuration from a variable lookup, validating every value
+    pub fn from_vars(var: impl Fn(&str) -> Option<String>) -> AppResult<Self> {
+        let default_service = match var("DEFAULT_SERVICE") {
+            Some(name) => name.parse().map_err(|_| invalid("DEFAULT_SERVICE", &name))?,
+            None => {
+                tracing::info!("DEFAULT_SERVICE not set; /api/auth/verify routes to the {} service", FALLBACK_DEFAULT_SERVICE);
+                FALLBACK_DEFAULT_SERVICE
+            },
+        };
+
+        // Each service falls back to QUANTUM_SAFE_PROXY_URL; a comma-separated list configures replicas
+        let proxy_url = var("QUANTUM_SAFE_PROXY_URL").unwrap_or_else(|| "https://localhost:8443".to_string());
+        let allow_insecure_backends = flag(&var, "ALLOW_INSECURE_BACKENDS");
+        let mut service_targets = HashMap::new();
+        let mut auth_templates = HashMap::new();
+        let mut service_timeouts = HashMap::new();
+        let mut host_headers = HashMap::new();
+        let mut expected_content_types = HashMap::new();
+        let mut service_scopes = HashMap::new();
+        let mut sticky_services = HashSet::new();
+        let mut response_schemas = HashMap::new();
+
+        for service in ServiceType::ALL {
+            let (source, urls) = match var(&service.env_var("URL")) {
+                Some(urls) => (service.env_var("URL"), urls),
+                None => ("QUANTUM_SAFE_PROXY_URL".to_string(), proxy_url.clone()),
+            };
+            let targets = urls.split(',')
+                .map(str::trim)
+                .filter(|url| !url.is_empty())
+                .map(|url| BackendTarget::parse(url).ok_or_else(|| invalid(&source, url)))
+                .collect::<AppResult<Vec<_>>>()?;
+
+            if targets.is_empty() {
+                return Err(invalid(&source, &urls));
+            }
+            if targets.iter().any(|t| t.scheme == Scheme::Http) && service == ServiceType::Payment && !allow_insecure_backends {
+                return Err(AppError::Internal(
+                    "Plain HTTP is not allowed for payment service; set ALLOW_INSECURE_BACKENDS to override".to_string()
+                ));
+            }
+
+            service_targets.insert(service, targets);
+
+            // Stateful backends can keep each user on one replica
+            if flag(&var, &service.env_var("STICKY")) {
+                sticky_services.insert(service);
+            }
+
+            // Backends may expect the token under a different header or format
+            let header = var(&service.env_var("AUTH_HEADER")).unwrap_or_else(|| "Authorization".to_string());
+            if axum::http::HeaderName::from_bytes(header.as_bytes()).is_err() {
+                return Err(invalid(&service.env_var("AUTH_HEADER"), &header));
+            }
+            auth_templates.insert(service, AuthTemplate {
+                header,
+                format: var(&service.env_var("AUTH_FORMAT")).unwrap_or_else(|| "Bearer {token}".to_string()),
+            });
+
+            // Virtual-hosted backends behind the proxy expect their own name in Host;
+            // HeaderValue rejects CR/LF, so the value cannot inject request lines
+            if let Some(host) = var(&service.env_var("HOST_HEADER")) {
+                if host.is_empty() || axum::http::HeaderValue::from_str(&host).is_err() {
+                    return Err(invalid(&service.env_var("HOST_HEADER"), &host));
+                }
+                host_headers.insert(service, host);
+            }
+
+            // Non-JSON media types the backend returns by design, passed through unwrapped
+            if let Some(types) = var(&service.env_var("CONTENT_TYPES")) {
+                let types: Vec<String> = types.split(',').map(str::trim).filter(|t| !t.is_empty()).map(str::to_string).collect();
+                expected_content_types.insert(service, types);
+            }
+
+            // Scopes of gateway-issued service tokens, narrowed to what the service needs
+            if let Some(scopes) = var(&service.env_var("SCOPES")) {
+                service_scopes.insert(service, split_scopes(&scopes));
+            }
+
+            // Schemas are compiled once here so a bad file fails startup, not requests
+            if let Some(path) = var(&service.env_var("RESPONSE_SCHEMA")) {
+                let schema = ResponseSchema::load(&path)
+                    .map_err(|e| invalid(&service.env_var("RESPONSE_SCHEMA"), &format!("{} ({})", path, e)))?;
+                response_schemas.insert(service, schema);
+            }
+
+            // Slow backends may need a longer budget than REQUEST_TIMEOUT_MS
+            if let Some(ms) = parse_opt::<u64>(&var, &service.env_var("TIMEOUT_MS"))? {
+                service_timeouts.insert(service, Duration::from_millis(ms));
+            }
+        }
+
+        // Services that reject anonymous requests locally instead of forwarding them
+        let auth_required_services = var("REQUIRE_AUTH_PER_SERVICE")
+            .unwrap_or_else(|| "payment".to_string())
+            .split(',')
+            .map(str::trim)
+            .filter(|name| !name.is_empty())
+            .map(|name| name.parse::<ServiceType>().map_err(|_| invalid("REQUIRE_AUTH_PER_SERVICE", name)))
+            .collect::<AppResult<HashSet<_>>>()?;
+
+        // Services that additionally need a recent step-up passkey assertion
+        let step_up_services = var("STEP_UP_REQUIRED_SERVICES")
+            .unwrap_or_default()
+            .split(',')
+            .map(str::trim)
+            .filter(|name| !name.is_empty())
+            .map(|name| name.parse::<ServiceType>().map_err(|_| invalid("STEP_UP_REQUIRED_SERVICES", name)))
+            .collect::<AppResult<HashSet<_>>>()?;
+
+        let body_log_mode = match var("LOG_BODIES").as_deref() {
+            None | Some("off") => BodyLogMode::Off,
+            Some("redacted") => BodyLogMode::Redacted,
+            Some("full") => BodyLogMode::Full,
+            Some(other) => return Err(invalid("LOG_BODIES", other)),
+        };
+
+        let user_id_strategy = match var("USER_ID_STRATEGY").as_deref() {
+            None | Some("generated") => UserIdStrategy::Generated,
+            Some("external") => UserIdStrategy::External,
+            Some(other) => return Err(invalid("USER_ID_STRATEGY", other)),
+        };
+
+        // Skipping verification is a development escape hatch only
+        let mut tls = TlsConfig::from_vars(&var)?;
+        if tls.insecure_skip_verify && var("ENVIRONMENT").as_deref() == Some("production") {
+            return Err(AppError::Internal(
+                "INSECURE_SKIP_VERIFY cannot be enabled when ENVIRONMENT=production".to_string()
+            ));
+        }
+        // One session cache shared by all services, keyed by their TLS identity. Resumption
+        // is opt-in; DISABLE_CONNECTION_REUSE turns it off for comparing against full handshakes.
+        let clock = clock::system();
+        tls.sessions = SessionCache::with_max_age(Duration::from_secs(parse(&var, "TLS_SESSION_MAX_AGE_SECS", 0)?), Arc::clone(&clock))
+            .filter(|_| !flag(&var, "DISABLE_CONNECTION_REUSE"));
+
+        // Services fall back to the global client identity and CA
+        let service_tls = ServiceType::ALL.iter()
+            .map(|service| (*service, tls.with_overrides(&var, service.env_prefix())))
+            .collect();
+
+        // Sub-path the gateway is mounted at, normalized to "/prefix" ("" at the root)
+        let base_path = match var("BASE_PATH").map(|path| path.trim().trim_matches('/').to_string()) {
+            Some(path) if path.is_empty() => String::new(),
+            Some(path) if path.contains(['?', '#', ' ']) => return Err(invalid("BASE_PATH", &path)),
+            Some(path) => format!("/{}", path),
+            None => String::new(),
+        };
+
+        let jwt_issuer = var("JWT_ISSUER").unwrap_or_else(|| "passkeymesh-gateway".to_string());
+        let jwt_audience = var("JWT_AUDIENCE").unwrap_or_else(|| "backend-service".to_string());
+        let accepted_issuers = match var("JWT_ACCEPTED_ISSUERS") {
+            Some(list) => {
+                let issuers: Vec<String> = list.split(',').map(str::trim).filter(|iss| !iss.is_empty()).map(str::to_string).collect();
+                if issuers.is_empty() {
+                    return Err(invalid("JWT_ACCEPTED_ISSUERS", &list));
+                }
+                issuers
+            },
+            None => vec![jwt_issuer.clone()],
+        };
+
+        Ok(Self {
+            port: parse(&var, "PORT", 3000)?,
+            default_service,
+            service_targets,
+            sticky_services,
+            host_policy: var("BACKEND_HOST_ALLOWLIST").map(|list| HostPolicy::parse(&list)).unwrap_or_default(),
+            auth_templates,
+            host_headers,
+            expected_content_types,
+            service_scopes,
+            response_schemas,
+            auth_required_services,
+            step_up_services,
+            step_up_max_age: Duration::from_secs(parse(&var, "STEP_UP_MAX_AGE_SECS", 300)?),
+            request_timeout: parse_opt::<u64>(&var, "REQUEST_TIMEOUT_MS")?.map(Duration::from_millis),
+            service_timeouts,
+            slow_request: Duration::from_millis(parse(&var, "SLOW_REQUEST_MS", 3000)?),
+            health_check_interval: parse_opt::<u64>(&var, "HEALTH_CHECK_INTERVAL_SECS")?
+                .filter(|secs| *secs > 0)
+                .map(Duration::from_secs),
+            wait_for_backend: parse_opt::<u64>(&var, "WAIT_FOR_BACKEND_SECS")?
+                .filter(|secs| *secs > 0)
+                .map(Duration::from_secs),
+            startup_tls_selftest: flag(&var, "STARTUP_TLS_SELFTEST"),
+            strict_selftest: flag(&var, "STRICT_SELFTEST"),
+            max_concurrent_backend: parse(&var, "MAX_CONCURRENT_BACKEND", 64)?,
+            max_concurrent_backend_per_service: parse_opt(&var, "MAX_CONCURRENT_BACKEND_PER_SERVICE")?,
+            backend_queue_timeout: Duration::from_millis(parse(&var, "BACKEND_QUEUE_TIMEOUT_MS", 5000)?),
+            max_batch_size: parse(&var, "MAX_BATCH_SIZE", 10)?,
+            response_cache_size: parse(&var, "RESPONSE_CACHE_SIZE", 0)?,
+            propagate_backend_status: flag(&var, "PROPAGATE_BACKEND_STATUS"),
+            empty_body_response: match var("EMPTY_BODY_RESPONSE") {
+                Some(json) => serde_json::from_str(&json).map_err(|_| invalid("EMPTY_BODY_RESPONSE", &json))?,
+                None => serde_json::Value::Null,
+            },
+            header_limits: HeaderLimits {
+                max_count: parse(&var, "MAX_FORWARD_HEADERS", 32)?,
+                max_bytes: parse(&var, "MAX_FORWARD_HEADER_BYTES", 8192)?,
+            },
+            surface_response_headers: var("SURFACE_RESPONSE_HEADERS")
+                .map(|list| list.split(',').map(str::trim).filter(|name| !name.is_empty()).map(str::to_string).collect())
+                .unwrap_or_default(),
+            retry: RetryPolicy {
+                connect_retries: parse(&var, "BACKEND_CONNECT_RETRIES", 2)?,
+                request_retries: parse(&var, "BACKEND_REQUEST_RETRIES", 0)?,
+            },
+            tcp: TcpOptions {
+                keepalive: parse_opt::<u64>(&var, "TCP_KEEPALIVE_SECS")?
+                    .filter(|secs| *secs > 0)
+                    .map(Duration::from_secs),
+                nodelay: var("TCP_NODELAY").is_none_or(|v| v != "false" && v != "0"),
+            },
+            body_logger: BodyLogger {
+                mode: body_log_mode,
+                redact_fields: var("LOG_REDACT_FIELDS")
+                    .unwrap_or_else(|| "password,token,authorization,secret".to_string())
+                    .split(',')
+                    .map(|field| field.trim().to_ascii_lowercase())
+                    .filter(|field| !field.is_empty())
+                    .collect(),
+            },
+            webauthn_rp_name: var("WEBAUTHN_RP_NAME").unwrap_or_else(|| "PasskeyMesh Gateway".to_string()),
+            index_html_path: var("INDEX_HTML_PATH").unwrap_or_else(|| "index.html".to_string()),
+            static_dir: var("STATIC_DIR").unwrap_or_else(|| "static".to_string()),
+            base_path,
+            user_id_strategy,
+            max_pending_challenges: parse(&var, "MAX_PENDING_CHALLENGES", 10000)?,
+            max_pending_challenges_per_ip: parse(&var, "MAX_PENDING_CHALLENGES_PER_IP", 20)?,
+            max_registration_attempts_per_user: parse(&var, "MAX_REGISTRATION_ATTEMPTS_PER_USER", 1)?,
+            challenge_ttl: Duration::from_secs(parse(&var, "CHALLENGE_TTL_SECS", 300)?),
+            state_sweep_interval: Duration::from_secs(parse(&var, "STATE_SWEEP_INTERVAL_SECS", 60)?.max(1)),
+            require_backup_eligible: flag(&var, "REQUIRE_BACKUP_ELIGIBLE"),
+            require_user_handle: var("REQUIRE_USER_HANDLE").is_none_or(|v| v != "false" && v != "0"),
+            max_credential_payload_bytes: parse(&var, "MAX_CREDENTIAL_PAYLOAD_BYTES", 64 * 1024)?,
+            max_credentials_per_user: parse_opt(&var, "MAX_CREDENTIALS_PER_USER")?,
+            issue_jwt: var("ISSUE_JWT").is_none_or(|v| v != "false" && v != "0"),
+            admin_token: var("ADMIN_TOKEN").filter(|token| !token.is_empty()),
+            maintenance_mode: flag(&var, "MAINTENANCE_MODE"),
+            maintenance_retry_after: Duration::from_secs(parse(&var, "MAINTENANCE_RETRY_AFTER_SECS", 300)?),
+            maintenance_includes_auth: flag(&var, "MAINTENANCE_INCLUDES_AUTH"),
+            api_keys: var("API_KEYS")
+                .map(|keys| ApiKeys::parse(&keys).map_err(|entry| invalid("API_KEYS", &entry)))
+                .transpose()?
+                .unwrap_or_default(),
+            trusted_proxy_hops: match var("TRUST_FORWARDED_FOR").as_deref() {
+                None | Some("false") => 0,
+                Some("true") => 1,
+                Some(other) => other.trim().parse().map_err(|_| invalid("TRUST_FORWARDED_FOR", other))?,
+            },
+            jwt: JwtConfig {
+                secret: var("JWT_SECRET").ok_or_else(|| AppError::Internal("Missing JWT_SECRET environment variable".to_string()))?,
+                accepted_issuers,
+                issuer: jwt_issuer,
+                audience: jwt_audience.clone(),
+                leeway_secs: parse(&var, "JWT_LEEWAY_SECS", 30)?,
+                issue_backdate_secs: parse(&var, "JWT_ISSUE_BACKDATE_SECS", 0)?,
+                user_scopes: var("JWT_SCOPES").map(|scopes| split_scopes(&scopes)).unwrap_or_default(),
+                bind_to_client: flag(&var, "BIND_TOKEN_TO_CLIENT"),
+                clock: Arc::clone(&clock),
+            },
+            claims_validators: ClaimsValidators::new(&jwt_audience),
+            tls,
+            service_tls,
+            clock,
+        })
+    }
+
+    // Drive expiry decisions by the given clock instead of the system one
+    pub fn with_clock(mut self, clock: SharedClock) -> Self {
+        let sessions = self.tls.sessions.as_ref().map(|cache| cache.with_clock(Arc::clone(&clock)));
+        for tls in std::iter::once(&mut self.tls).chain(self.service_tls.values_mut()) {
+            tls.sessions = sessions.clone();
+        }
+        self.jwt.clock = Arc::clone(&clock);
+        self.clock = clock;
+        self
+    }
+
+    // TLS configuration for a service's backend
+    pub fn tls_for(&self, service: ServiceType) -> &TlsConfig {
+        &self.service_tls[&service]
+    }
+
+    // Default request deadline for a service, covering handshake, request and response
+    pub fn timeout_for(&self, service: ServiceType) -> Option<Duration> {
+        self.service_timeouts.get(&service).copied().or(self.request_timeout)
+    }
+}
+
+fn invalid(key: &str, value: &str) -> AppError {
+    AppError::Internal(format!("Invalid {}: {}", key, value))
+}
+
+// Parse optional typed value
+fn parse_opt<T: FromStr>(var: &impl Fn(&str) -> Option<String>, key: &str) -> AppResult<Option<T>> {
+    var(key).map(|value| value.trim().parse::<T>().map_err(|_| invalid(key, &value))).transpose()
+}
+
+// Split a space- or comma-separated scope list
+fn split_scopes(scopes: &str) -> Vec<String> {
+    scopes.split(|c: char| c == ',' || c.is_whitespace())
+        .filter(|scope| !scope.is_empty())
+        .map(str::to_string)
+        .collect()
+}
+
+// Parse typed value with default
+fn parse<T: FromStr>(var: &impl Fn(&str) -> Option<String>, key: &str, default: T) -> AppResult<T> {
+    Ok(parse_opt(var, key)?.unwrap_or(default))
+}
+
+// Parse boolean flag ("true" or "1")
+fn flag(var: &impl Fn(&str) -> Option<String>, key: &str) -> bool {
+    var(key).map(|v| v == "true" || v == "1").unwrap_or(false)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support;
+
+    #[test]
+    fn default_service_falls_back_to_log() {
+        assert_eq!(test_support::config(&[]).default_service, ServiceType::Log);
+    }
+
+    #[test]
+    fn default_service_is_parsed() {
+        let config = test_support::config(&[("DEFAULT_SERVICE", "users")]);
+        assert_eq!(config.default_service, ServiceType::Users);
+    }
+
+    #[test]
+    fn unknown_default_service_is_rejected() {
+        assert!(test_support::try_config(&[("DEFAULT_SERVICE", "billing")]).is_err());
+    }
+
+    #[test]
+    fn plain_http_payment_backend_requires_opt_in() {
+        assert!(test_support::try_config(&[("PAYMENT_SERVICE_URL", "http://sidecar:8080")]).is_err());
+
+        let config = test_support::config(&[("PAYMENT_SERVICE_URL", "http://sidecar:8080"), ("ALLOW_INSECURE_BACKENDS", "true")]);
+        assert_eq!(config.service_targets[&ServiceType::Payment][0].scheme, Scheme::Http);
+    }
+
+    #[test]
+    fn defaults_apply_when_unset() {
+        let config = test_support::config(&[]);
+
+        assert_eq!(config.port, 3000);
+        assert_eq!(config.base_path, "");
+        assert_eq!(config.jwt.leeway_secs, 30);
+        assert_eq!(config.challenge_ttl, Duration::from_secs(300));
+        assert!(config.require_user_handle);
+        assert!(config.issue_jwt);
+        assert!(!config.maintenance_mode);
+        for service in ServiceType::ALL {
+            let target = &config.service_targets[&service][0];
+            assert_eq!((target.scheme, target.host.as_str(), target.port), (Scheme::Https, "localhost", 8443));
+        }
+    }
+
+    #[test]
+    fn variables_override_defaults() {
+        let config = test_support::config(&[
+            ("PORT", "8080"),
+            ("JWT_LEEWAY_SECS", "5"),
+            ("REQUIRE_USER_HANDLE", "false"),
+            ("QUANTUM_SAFE_PROXY_URL", "https://proxy:9443"),
+            ("LOG_SERVICE_URL", "http://logs:8080"),
+        ]);
+
+        assert_eq!(config.port, 8080);
+        assert_eq!(config.jwt.leeway_secs, 5);
+        assert!(!config.require_user_handle);
+        assert_eq!(config.service_targets[&ServiceType::Users][0].host, "proxy");
+        assert_eq!(config.service_targets[&ServiceType::Log][0].host, "logs");
+    }
+
+    #[test]
+    fn invalid_values_are_rejected() {
+        for vars in [[("PORT", "http")], [("JWT_LEEWAY_SECS", "-1")], [("QUANTUM_SAFE_PROXY_URL", "https://")]] {
+            assert!(test_support::try_config(&vars).is_err(), "{:?}", vars);
+        }
+    }
+
+    #[test]
+    fn empty_or_malformed_proxy_url_is_a_clean_error() {
+        for url in ["", " , ", "https://", "https://:8443", "proxy:port", "proxy/api"] {
+            let result = test_support::try_config(&[("QUANTUM_SAFE_PROXY_URL", url)]);
+            assert!(matches!(&result, Err(AppError::Internal(msg)) if msg.starts_with("Invalid QUANTUM_SAFE_PROXY_URL")), "{:?}: {:?}", url, result.err());
+        }
+
+        // A per-service URL is reported under its own name
+        let result = test_support::try_config(&[("PAYMENT_SERVICE_URL", "")]);
+        assert!(matches!(&result, Err(AppError::Internal(msg)) if msg.starts_with("Invalid PAYMENT_SERVICE_URL")), "{:?}", result.err());
+    }
+
+    #[test]
+    fn jwt_secret_is_required() {
+        assert!(Config::from_vars(|key| (key == "OPENSSL_PATH").then(|| "openssl".to_string())).is_err());
+    }
+
+    #[test]
+    fn insecure_skip_verify_is_rejected_in_production() {
+        let result = test_support::try_config(&[("INSECURE_SKIP_VERIFY", "true"), ("ENVIRONMENT", "production")]);
+        assert!(matches!(result, Err(AppError::Internal(msg)) if msg.contains("INSECURE_SKIP_VERIFY")));
+
+        let development = test_support::config(&[("INSECURE_SKIP_VERIFY", "true"), ("ENVIRONMENT", "development")]);
+        assert!(development.tls_for(ServiceType::Log).insecure_skip_verify);
+    }
+
+    #[test]
+    fn payment_service_uses_its_own_client_identity() {
+        let config = test_support::config(&[
+            ("CLIENT_CERT_PATH", "/certs/client.crt"),
+            ("CA_CERT_PATH", "/certs/ca.crt"),
+            ("PAYMENT_CLIENT_CERT_PATH", "/certs/payment.crt"),
+            ("PAYMENT_CLIENT_KEY_PATH", "/certs/payment.key"),
+            ("PAYMENT_CA_CERT_PATH", "/certs/payment-ca.crt"),
+        ]);
+
+        let payment = config.tls_for(ServiceType::Payment);
+        assert_eq!((payment.cert.as_str(), payment.key.as_str(), payment.ca.as_str()), ("/certs/payment.crt", "/certs/payment.key", "/certs/payment-ca.crt"));
+        for service in [ServiceType::Log, ServiceType::Users] {
+            let tls = config.tls_for(service);
+            assert_eq!((tls.cert.as_str(), tls.ca.as_str()), ("/certs/client.crt", "/certs/ca.crt"), "{:?}", service);
+        }
+    }
+}
@@ -0,0 +1,103 @@
+use std::time::{Duration, Instant};
+use axum::http::HeaderMap;
+use chrono::{DateTime, Utc};
+
+use crate::clock::SharedClock;
+
+// Header carrying the request deadline (RFC3339 timestamp or remaining milliseconds)
+pub const DEADLINE_HEADER: &str = "X-Request-Deadline";
+
+// Request deadline, measured by the clock it was created with
+#[derive(Debug, Clone)]
+pub struct Deadline {
+    at: Instant,
+    clock: SharedClock,
+}
+
+impl Deadline {
+    // Create a deadline the given duration from now
+    pub fn after(timeout: Duration, clock: &SharedClock) -> Self {
+        Self { at: clock.now() + timeout, clock: clock.clone() }
+    }
+
+    // Get deadline from request headers, falling back to the default timeout
+    pub fn from_headers(headers: &HeaderMap, default_timeout: Option<Duration>, clock: &SharedClock) -> Option<Self> {
+        if let Some(value) = headers.get(DEADLINE_HEADER).and_then(|h| h.to_str().ok()) {
+            match Self::parse(value, clock) {
+                Some(deadline) => return Some(deadline),
+                None => tracing::warn!("Ignoring invalid {} header: {}", DEADLINE_HEADER, value),
+            }
+        }
+
+        default_timeout.map(|timeout| Self::after(timeout, clock))
+    }
+
+    // Parse deadline from remaining milliseconds or an RFC3339 timestamp
+    pub fn parse(value: &str, clock: &SharedClock) -> Option<Self> {
+        let value = value.trim();
+        if let Ok(ms) = value.parse::<u64>() {
+            return Some(Self::after(Duration::from_millis(ms), clock));
+        }
+
+        let at = DateTime::parse_from_rfc3339(value).ok()?.with_timezone(&Utc);
+        let remaining = (at - clock.utc()).to_std().unwrap_or(Duration::ZERO);
+        Some(Self::after(remaining, clock))
+    }
+
+    // Remaining time, or None if the deadline has passed
+    pub fn remaining(&self) -> Option<Duration> {
+        self.at.checked_duration_since(self.clock.now())
+            .filter(|remaining| !remaining.is_zero())
+    }
+
+    // Check if deadline has passed
+    pub fn is_exceeded(&self) -> bool {
+        self.remaining().is_none()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::clock::{self, TestClock};
+
+    #[test]
+    fn zero_milliseconds_is_already_exceeded() {
+        assert!(Deadline::parse("0", &clock::system()).unwrap().is_exceeded());
+    }
+
+    #[test]
+    fn past_timestamp_is_already_exceeded() {
+        assert!(Deadline::parse("2000-01-01T00:00:00Z", &clock::system()).unwrap().is_exceeded());
+    }
+
+    #[test]
+    fn remaining_budget_never_exceeds_the_header() {
+        let remaining = Deadline::parse("5000", &clock::system()).unwrap().remaining().unwrap();
+        assert!(remaining <= Duration::from_millis(5000));
+    }
+
+    #[test]
+    fn deadline_is_exceeded_once_the_clock_passes_it() {
+        let test_clock = TestClock::new();
+        let clock: SharedClock = test_clock.clone();
+        let deadline = Deadline::parse("5000", &clock).unwrap();
+
+        test_clock.advance(Duration::from_millis(4999));
+        assert_eq!(deadline.remaining(), Some(Duration::from_millis(1)));
+        test_clock.advance(Duration::from_millis(1));
+        assert!(deadline.is_exceeded());
+
+        // A timestamp counts from the clock's wall time
+        let at = (clock.utc() + chrono::Duration::seconds(2)).to_rfc3339();
+        assert_eq!(Deadline::parse(&at, &clock).unwrap().remaining(), Some(Duration::from_secs(2)));
+    }
+
+    #[test]
+    fn invalid_header_falls_back_to_default_timeout() {
+        let mut headers = HeaderMap::new();
+        headers.insert(DEADLINE_HEADER, "soon".parse().unwrap());
+        assert!(Deadline::from_headers(&headers, None, &clock::system()).is_none());
+        assert!(Deadline::from_headers(&headers, Some(Duration::from_secs(1)), &clock::system()).is_some());
+    }
+}
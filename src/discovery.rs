@@ -0,0 +1,279 @@
+use std::{
+    collections::{hash_map::DefaultHasher, HashMap},
+    hash::{Hash, Hasher},
+    net::{TcpStream, ToSocketAddrs},
+    sync::{atomic::{AtomicBool, AtomicUsize, Ordering}, Arc},
+    time::Duration,
+};
+
+use crate::config::Config;
+use crate::service::{BackendTarget, Scheme, ServiceType};
+use crate::tls::TlsConfig;
+
+// Upper bound on a single readiness probe
+const PROBE_TIMEOUT: Duration = Duration::from_secs(5);
+
+// Backoff bounds while waiting for the backend at startup
+const WAIT_INITIAL_BACKOFF: Duration = Duration::from_millis(500);
+const WAIT_MAX_BACKOFF: Duration = Duration::from_secs(5);
+
+// Backend replica with its last probed health
+struct Replica {
+    target: BackendTarget,
+    healthy: AtomicBool,
+}
+
+// Replicas of one service, picked round-robin
+struct ServiceReplicas {
+    replicas: Vec<Replica>,
+    next: AtomicUsize,
+}
+
+// Backend replicas per service, kept up to date by background probes
+pub struct ServiceRegistry {
+    services: HashMap<ServiceType, ServiceReplicas>,
+}
+
+impl ServiceRegistry {
+    pub fn new(config: &Config) -> Self {
+        let services = config.service_targets.iter()
+            .map(|(service, targets)| {
+                let replicas = targets.iter()
+                    .map(|target| Replica { target: target.clone(), healthy: AtomicBool::new(true) })
+                    .collect();
+                (*service, ServiceReplicas { replicas, next: AtomicUsize::new(0) })
+            })
+            .collect();
+
+        Self { services }
+    }
+
+    // Pick the next healthy replica, falling back to any replica when none is healthy.
+    // With a subject, the subject's preferred healthy replica is used instead.
+    pub fn pick(&self, service: ServiceType, subject: Option<&str>) -> &BackendTarget {
+        let entry = &self.services[&service];
+        if let Some(replica) = subject.and_then(|subject| sticky_replica(entry, subject)) {
+            return &replica.target;
+        }
+
+        let count = entry.replicas.len();
+        let start = entry.next.fetch_add(1, Ordering::Relaxed);
+
+        let healthy = (0..count)
+            .map(|offset| &entry.replicas[(start + offset) % count])
+            .find(|replica| replica.healthy.load(Ordering::Relaxed));
+
+        match healthy {
+            Some(replica) => &replica.target,
+            None => {
+                tracing::warn!("No healthy replica for {} service, trying one anyway", service.name());
+                &entry.replicas[start % count].target
+            },
+        }
+    }
+
+    // Probe every replica once and record the result
+    fn probe_all(&self, config: &Config) {
+        for (service, entry) in &self.services {
+            for replica in &entry.replicas {
+                let healthy = probe(config.tls_for(*service), &replica.target);
+                let was_healthy = replica.healthy.swap(healthy, Ordering::Relaxed);
+
+                if healthy != was_healthy {
+                    let (host, port) = (&replica.target.host, replica.target.port);
+                    if healthy {
+                        tracing::info!("{} service replica {}:{} recovered", service.name(), host, port);
+                    } else {
+                        tracing::warn!("{} service replica {}:{} failed readiness probe", service.name(), host, port);
+                    }
+                }
+            }
+        }
+    }
+}
+
+// Rendezvous hashing: every replica gets a per-subject score and the best healthy one
+// wins, so a subject keeps its replica and only moves while that replica is down
+fn sticky_replica<'a>(entry: &'a ServiceReplicas, subject: &str) -> Option<&'a Replica> {
+    entry.replicas.iter()
+        .filter(|replica| replica.healthy.load(Ordering::Relaxed))
+        .max_by_key(|replica| {
+            let mut hasher = DefaultHasher::new();
+            (subject, &replica.target.host, replica.target.port).hash(&mut hasher);
+            hasher.finish()
+        })
+}
+
+// Probe replicas in the background at the given interval
+pub fn spawn_health_checks(registry: Arc<ServiceRegistry>, config: Arc<Config>, interval: Duration) {
+    tokio::spawn(async move {
+        let mut ticker = tokio::time::interval(interval);
+        loop {
+            ticker.tick().await;
+
+            // Probes shell out to OpenSSL, so keep them off the async workers
+            let registry = Arc::clone(&registry);
+            let config = Arc::clone(&config);
+            if let Err(e) = tokio::task::spawn_blocking(move || registry.probe_all(&config)).await {
+                tracing::error!("Health check task failed: {}", e);
+            }
+        }
+    });
+}
+
+// Probe the default service's backends with backoff until one is ready or the timeout
+// elapses. Returns whether a backend became ready.
+pub async fn wait_for_backend(config: Arc<Config>, timeout: Duration) -> bool {
+    let service = config.default_service;
+    let started = tokio::time::Instant::now();
+    let mut backoff = WAIT_INITIAL_BACKOFF;
+    let mut attempt = 0;
+
+    loop {
+        attempt += 1;
+        let probe_config = Arc::clone(&config);
+        let ready = tokio::task::spawn_blocking(move || {
+            probe_config.service_targets[&service].iter()
+                .any(|target| probe(probe_config.tls_for(service), target))
+        }).await.unwrap_or(false);
+
+        if ready {
+            tracing::info!("{} service backend ready after {} attempt(s)", service.name(), attempt);
+            return true;
+        }
+
+        let elapsed = started.elapsed();
+        if elapsed >= timeout {
+            return false;
+        }
+        tracing::info!("Waiting for {} service backend (attempt {}, {}s elapsed)", service.name(), attempt, elapsed.as_secs());
+        tokio::time::sleep(backoff.min(timeout - elapsed)).await;
+        backoff = (backoff * 2).min(WAIT_MAX_BACKOFF);
+    }
+}
+
+// Lightweight readiness check: a TLS handshake, or a TCP connect for plain HTTP
+fn probe(tls: &TlsConfig, target: &BackendTarget) -> bool {
+    match target.scheme {
+        Scheme::Https => tls.clone()
+            .with_timeout(Some(PROBE_TIMEOUT))
+            .run(&target.host, target.port, &["-brief"], Some(b""))
+            .map(|output| output.status.success())
+            .unwrap_or(false),
+        Scheme::Http => (target.host.as_str(), target.port).to_socket_addrs()
+            .ok()
+            .and_then(|mut addrs| addrs.next())
+            .is_some_and(|addr| TcpStream::connect_timeout(&addr, PROBE_TIMEOUT).is_ok()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{collections::HashSet, net::TcpListener};
+    use serde_json::json;
+
+    use super::*;
+    use crate::jwt;
+    use crate::test_support::{self, MockBackend};
+
+    // Ports of the replicas picked over a few rounds
+    fn picked_ports(registry: &ServiceRegistry) -> HashSet<u16> {
+        (0..4).map(|_| registry.pick(ServiceType::Log, None).port).collect()
+    }
+
+    #[test]
+    fn unhealthy_replica_is_skipped_until_it_recovers() {
+        let up = TcpListener::bind("127.0.0.1:0").unwrap();
+        let flaky = TcpListener::bind("127.0.0.1:0").unwrap();
+        let (up_addr, flaky_addr) = (up.local_addr().unwrap(), flaky.local_addr().unwrap());
+        let config = test_support::config(&[("LOG_SERVICE_URL", &format!("http://{},http://{}", up_addr, flaky_addr))]);
+        let registry = ServiceRegistry::new(&config);
+
+        drop(flaky);
+        registry.probe_all(&config);
+        assert_eq!(picked_ports(&registry), HashSet::from([up_addr.port()]));
+
+        let _recovered = TcpListener::bind(flaky_addr).unwrap();
+        registry.probe_all(&config);
+        assert_eq!(picked_ports(&registry), HashSet::from([up_addr.port(), flaky_addr.port()]));
+    }
+
+    #[tokio::test]
+    async fn wait_succeeds_once_the_backend_is_reachable() {
+        let addr = TcpListener::bind("127.0.0.1:0").unwrap().local_addr().unwrap();
+        let config = Arc::new(test_support::config(&[("LOG_SERVICE_URL", &format!("http://{}", addr))]));
+
+        let waiting = tokio::spawn(wait_for_backend(config, Duration::from_secs(10)));
+        tokio::time::sleep(WAIT_INITIAL_BACKOFF / 2).await;
+        assert!(!waiting.is_finished());
+
+        let _backend = TcpListener::bind(addr).unwrap();
+        assert!(waiting.await.unwrap());
+    }
+
+    #[tokio::test]
+    async fn wait_gives_up_after_the_timeout() {
+        let addr = TcpListener::bind("127.0.0.1:0").unwrap().local_addr().unwrap();
+        let config = Arc::new(test_support::config(&[("LOG_SERVICE_URL", &format!("http://{}", addr))]));
+
+        let started = std::time::Instant::now();
+        assert!(!wait_for_backend(config, Duration::from_millis(300)).await);
+        assert!(started.elapsed() < WAIT_INITIAL_BACKOFF * 2);
+    }
+
+    #[test]
+    fn sticky_subject_keeps_its_replica_and_moves_only_while_it_is_down() {
+        let mut listeners: Vec<_> = (0..3).map(|_| Some(TcpListener::bind("127.0.0.1:0").unwrap())).collect();
+        let addrs: Vec<_> = listeners.iter().flatten().map(|listener| listener.local_addr().unwrap()).collect();
+        let urls: Vec<_> = addrs.iter().map(|addr| format!("http://{}", addr)).collect();
+        let config = test_support::config(&[("LOG_SERVICE_URL", &urls.join(","))]);
+        let registry = ServiceRegistry::new(&config);
+        let preferred = |subject: &str| registry.pick(ServiceType::Log, Some(subject)).port;
+        let subjects: Vec<String> = (0..32).map(|i| format!("user-{}", i)).collect();
+        let before: Vec<u16> = subjects.iter().map(|subject| preferred(subject)).collect();
+
+        let alice = preferred("alice");
+        assert!((0..5).all(|_| preferred("alice") == alice));
+        assert!(before.iter().collect::<HashSet<_>>().len() > 1, "subjects all map to one replica");
+
+        // alice's replica goes down: she moves, and nobody else on a live replica does
+        let down = addrs.iter().position(|addr| addr.port() == alice).unwrap();
+        listeners[down] = None;
+        registry.probe_all(&config);
+        let rerouted = preferred("alice");
+        assert_ne!(rerouted, alice);
+        assert!((0..5).all(|_| preferred("alice") == rerouted));
+        for (subject, port) in subjects.iter().zip(&before) {
+            if *port != alice {
+                assert_eq!(preferred(subject), *port, "{} moved", subject);
+            }
+        }
+
+        // Back on its preferred replica once that recovers
+        listeners[down] = Some(TcpListener::bind(addrs[down]).unwrap());
+        registry.probe_all(&config);
+        assert_eq!(preferred("alice"), alice);
+    }
+
+    #[tokio::test]
+    async fn sticky_service_sends_a_user_to_one_replica_until_it_fails() {
+        let replicas = [MockBackend::json(&json!({})), MockBackend::json(&json!({}))];
+        let config = test_support::config(&[
+            ("LOG_SERVICE_URL", &format!("{},{}", replicas[0].url(), replicas[1].url())),
+            ("LOG_SERVICE_STICKY", "true"),
+        ]);
+        let token = jwt::issue_jwt(&config.jwt, "user-1", "alice", &[], None).unwrap();
+        let (router, state) = test_support::app(config);
+        let call = || test_support::send(&router, test_support::get("/api/service/log", &[("Authorization", &test_support::bearer(&token))]));
+        let served = || replicas.iter().map(|replica| replica.requests().len()).collect::<Vec<_>>();
+
+        for _ in 0..4 {
+            assert_eq!(call().await.status, 200);
+        }
+        let preferred = served().iter().position(|count| *count == 4).expect("requests split across replicas");
+
+        state.registry.services[&ServiceType::Log].replicas[preferred].healthy.store(false, Ordering::Relaxed);
+        assert_eq!(call().await.status, 200);
+        assert_eq!(served()[1 - preferred], 1);
+    }
+}
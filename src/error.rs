@@ -1,38 +1,133 @@
-use axum::{http::StatusCode, response::{IntoResponse, Response}, Json};
+use std::{any::Any, sync::Arc, time::Duration};
+use axum::{http::{Request, StatusCode}, middleware::Next, response::{IntoResponse, Response}, Extension, Json};
+use serde::Serialize;
 use serde_json::json;
+use crate::api_response::ResponseStatus;
+use crate::clock::{Clock, SharedClock, SystemClock};
+use crate::config::Config;
 use thiserror::Error;
 
 #[derive(Error, Debug)]
 pub enum AppError {
     #[error("Authentication error: {0}")] Authentication(String),
     #[error("WebAuthn error: {0}")] WebAuthn(#[from] webauthn_rs::prelude::WebauthnError),
+    #[error("Cancelled by user: {0}")] UserCancelled(String),
+    #[error("Duplicate credential: {0}")] DuplicateCredential(String),
     #[error("JWT error: {0}")] Jwt(#[from] jsonwebtoken::errors::Error),
-    #[error("HTTP client error: {0}")] HttpClient(#[from] reqwest::Error),
+    #[error("Invalid backend response: {0}")] InvalidResponse(#[from] crate::http_parser::ParseError),
+    #[error("Backend response failed schema validation: {}", .0.join("; "))] SchemaViolation(Vec<String>),
+    #[error("Bad request: {0}")] BadRequest(String),
+    #[error("Forbidden: {0}")] Forbidden(String),
+    #[error("Not found: {0}")] NotFound(String),
+    #[error("Method not allowed: {0}")] MethodNotAllowed(String),
+    #[error("Request headers too large: {0}")] HeaderTooLarge(String),
+    #[error("Timeout: {0}")] Timeout(String),
+    #[error("Rate limited: {message}")] RateLimited { message: String, retry_after: Duration },
+    #[error("Service unavailable: {0}")] ServiceUnavailable(String),
+    #[error("Down for maintenance")] Maintenance { retry_after: Duration },
     #[error("Internal server error: {0}")] Internal(String),
 }
 
+// Machine-readable error codes returned in the code field of error responses
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "SCREAMING_SNAKE_CASE")]
+pub enum ErrorCode {
+    AuthError,
+    WebauthnError,
+    ChallengeMismatch,
+    OriginMismatch,
+    UserNotPresent,
+    UserNotVerified,
+    CredentialCompromised,
+    MalformedCredential,
+    UserCancelled,
+    DuplicateCredential,
+    JwtError,
+    InvalidBackendResponse,
+    SchemaValidationFailed,
+    BadRequest,
+    Forbidden,
+    NotFound,
+    MethodNotAllowed,
+    HeaderTooLarge,
+    TimeoutError,
+    RateLimited,
+    ServiceUnavailable,
+    Maintenance,
+    InternalError,
+    ResponseBuildError,
+}
+
 impl IntoResponse for AppError {
     fn into_response(self) -> Response {
+        // Clients told to back off learn when to retry, in whole seconds
+        let retry_after = match &self {
+            AppError::RateLimited { retry_after, .. } | AppError::Maintenance { retry_after } => Some(retry_after.as_secs().max(1)),
+            _ => None,
+        };
+
         // Determine status code and error message based on error type
         let (status, error_message, error_code) = match self {
             AppError::Authentication(msg) => {
-                (StatusCode::UNAUTHORIZED, msg, "AUTH_ERROR")
+                (StatusCode::UNAUTHORIZED, msg, ErrorCode::AuthError)
             },
             AppError::WebAuthn(e) => {
+                // The raw error stays in the log; clients get a stable code to act on
                 tracing::error!("WebAuthn error: {}", e);
-                (StatusCode::BAD_REQUEST, e.to_string(), "WEBAUTHN_ERROR")
+                let (code, message) = webauthn_error_code(&e);
+                let status = if code == ErrorCode::DuplicateCredential { StatusCode::CONFLICT } else { StatusCode::BAD_REQUEST };
+                (status, message.to_string(), code)
+            },
+            AppError::UserCancelled(msg) => {
+                (StatusCode::BAD_REQUEST, msg, ErrorCode::UserCancelled)
+            },
+            AppError::DuplicateCredential(msg) => {
+                (StatusCode::CONFLICT, msg, ErrorCode::DuplicateCredential)
             },
             AppError::Jwt(e) => {
                 tracing::error!("JWT error: {}", e);
-                (StatusCode::UNAUTHORIZED, e.to_string(), "JWT_ERROR")
+                (StatusCode::UNAUTHORIZED, e.to_string(), ErrorCode::JwtError)
+            },
+            AppError::InvalidResponse(e) => {
+                tracing::error!("Invalid backend response: {}", e);
+                (StatusCode::BAD_GATEWAY, "Invalid response from backend service".to_string(), ErrorCode::InvalidBackendResponse)
+            },
+            AppError::SchemaViolation(errors) => {
+                tracing::error!("Backend response failed schema validation: {}", errors.join("; "));
+                (StatusCode::BAD_GATEWAY, format!("Backend response failed schema validation: {}", errors.join("; ")), ErrorCode::SchemaValidationFailed)
+            },
+            AppError::BadRequest(msg) => {
+                (StatusCode::BAD_REQUEST, msg, ErrorCode::BadRequest)
+            },
+            AppError::Forbidden(msg) => {
+                (StatusCode::FORBIDDEN, msg, ErrorCode::Forbidden)
+            },
+            AppError::NotFound(msg) => {
+                (StatusCode::NOT_FOUND, msg, ErrorCode::NotFound)
+            },
+            AppError::MethodNotAllowed(msg) => {
+                (StatusCode::METHOD_NOT_ALLOWED, msg, ErrorCode::MethodNotAllowed)
+            },
+            AppError::HeaderTooLarge(msg) => {
+                (StatusCode::REQUEST_HEADER_FIELDS_TOO_LARGE, msg, ErrorCode::HeaderTooLarge)
+            },
+            AppError::Timeout(msg) => {
+                tracing::warn!("Timeout: {}", msg);
+                (StatusCode::GATEWAY_TIMEOUT, msg, ErrorCode::TimeoutError)
+            },
+            AppError::RateLimited { message, .. } => {
+                (StatusCode::TOO_MANY_REQUESTS, message, ErrorCode::RateLimited)
+            },
+            AppError::ServiceUnavailable(msg) => {
+                tracing::warn!("Service unavailable: {}", msg);
+                (StatusCode::SERVICE_UNAVAILABLE, msg, ErrorCode::ServiceUnavailable)
             },
-            AppError::HttpClient(e) => {
-                tracing::error!("HTTP client error: {}", e);
-                (StatusCode::BAD_GATEWAY, "Unable to communicate with backend service".to_string(), "HTTP_CLIENT_ERROR")
+            AppError::Maintenance { .. } => {
+                (StatusCode::SERVICE_UNAVAILABLE, "The gateway is down for maintenance; please try again later".to_string(), ErrorCode::Maintenance)
             },
             AppError::Internal(e) => {
                 tracing::error!("Internal server error: {}", e);
-                (StatusCode::INTERNAL_SERVER_ERROR, "Internal server error".to_string(), "INTERNAL_ERROR")
+                (StatusCode::INTERNAL_SERVER_ERROR, "Internal server error".to_string(), ErrorCode::InternalError)
             },
         };
 
@@ -49,29 +144,232 @@ impl IntoResponse for AppError {
             builder = builder.header(key, value);
         }
 
+        if let Some(secs) = retry_after {
+            builder = builder.header("Retry-After", secs);
+        }
+
         // Add HSTS header in production
         if std::env::var("ENVIRONMENT").unwrap_or_default() == "production" {
             builder = builder.header("Strict-Transport-Security", "max-age=31536000; includeSubDomains");
         }
 
         // Build error response body
-        let body = Json(json!({
-            "status": "error",
+        let mut body = json!({
+            "status": ResponseStatus::Error,
             "code": error_code,
             "message": error_message,
-            "timestamp": chrono::Utc::now().to_rfc3339()
-        }));
+            "timestamp": timestamp()
+        });
+        if let Some(secs) = retry_after {
+            body["retry_after_secs"] = json!(secs);
+        }
+        let body = Json(body);
 
         // Build final response
         builder.body(body.into_response().into_body())
             .unwrap_or_else(|_| {
                 (StatusCode::INTERNAL_SERVER_ERROR, Json(json!({
-                    "status": "error",
-                    "code": "RESPONSE_BUILD_ERROR",
+                    "status": ResponseStatus::Error,
+                    "code": ErrorCode::ResponseBuildError,
                     "message": "Failed to build error response"
                 }))).into_response()
             })
     }
 }
 
+// Client code and message for a WebAuthn verification failure
+fn webauthn_error_code(error: &webauthn_rs::prelude::WebauthnError) -> (ErrorCode, &'static str) {
+    use webauthn_rs::prelude::WebauthnError as E;
+    match error {
+        E::MismatchedChallenge | E::ChallengeNotFound => (ErrorCode::ChallengeMismatch, "Challenge does not match or has expired; start the ceremony again"),
+        E::InvalidRPOrigin | E::InvalidRPIDHash => (ErrorCode::OriginMismatch, "Credential was created for a different origin"),
+        E::UserNotPresent => (ErrorCode::UserNotPresent, "Authenticator did not confirm user presence"),
+        E::UserNotVerified => (ErrorCode::UserNotVerified, "Authenticator did not verify the user"),
+        E::CredentialPossibleCompromise => (ErrorCode::CredentialCompromised, "Credential may be compromised"),
+        E::CredentialAlreadyExists | E::CredentialExcludedFromRequest => (ErrorCode::DuplicateCredential, "Credential is already registered"),
+        E::InvalidClientDataType | E::ParseJSONFailure(_) => (ErrorCode::MalformedCredential, "Malformed credential response"),
+        _ => (ErrorCode::WebauthnError, "WebAuthn verification failed"),
+    }
+}
+
+tokio::task_local! {
+    // Clock of the request being served, for its error responses
+    static REQUEST_CLOCK: SharedClock;
+}
+
+// Serve the request with its error responses timestamped by the configured clock
+pub async fn timestamp_errors<B>(Extension(config): Extension<Arc<Config>>, request: Request<B>, next: Next<B>) -> Response {
+    REQUEST_CLOCK.scope(Arc::clone(&config.clock), next.run(request)).await
+}
+
+// Error response timestamp by the request's clock; the system clock outside a request
+fn timestamp() -> String {
+    REQUEST_CLOCK.try_with(|clock| clock.utc())
+        .unwrap_or_else(|_| SystemClock.utc())
+        .to_rfc3339()
+}
+
+// Convert a handler panic into the standard error response
+pub fn handle_panic(clock: &SharedClock, err: Box<dyn Any + Send + 'static>) -> Response {
+    let incident_id = uuid::Uuid::new_v4().to_string();
+    let detail = err.downcast_ref::<String>().map(String::as_str)
+        .or_else(|| err.downcast_ref::<&str>().copied())
+        .unwrap_or("unknown panic");
+
+    tracing::error!("Handler panicked (incident {}): {}", incident_id, detail);
+
+    (StatusCode::INTERNAL_SERVER_ERROR, Json(json!({
+        "status": ResponseStatus::Error,
+        "code": ErrorCode::InternalError,
+        "message": "Internal server error",
+        "incident_id": incident_id,
+        "timestamp": clock.utc().to_rfc3339()
+    }))).into_response()
+}
+
 pub type AppResult<T> = Result<T, AppError>;
+
+#[cfg(test)]
+mod tests {
+    use axum::{routing::get, Router};
+    use tower_http::catch_panic::CatchPanicLayer;
+
+    use super::*;
+    use crate::clock::TestClock;
+    use crate::test_support;
+
+    async fn panicking() -> &'static str {
+        panic!("malformed input")
+    }
+
+    #[tokio::test]
+    async fn panic_becomes_json_500_with_incident_id() {
+        let clock: SharedClock = TestClock::new();
+        let router = Router::new()
+            .route("/panic", get(panicking))
+            .layer(CatchPanicLayer::custom({
+                let clock = Arc::clone(&clock);
+                move |err| handle_panic(&clock, err)
+            }));
+
+        let response = test_support::send(&router, test_support::get("/panic", &[])).await;
+
+        assert_eq!(response.status, StatusCode::INTERNAL_SERVER_ERROR);
+        let body = response.json();
+        assert_eq!(body["status"], "error");
+        assert_eq!(body["code"], "INTERNAL_ERROR");
+        assert_eq!(body["message"], "Internal server error");
+        let incident_id = body["incident_id"].as_str().expect("incident id");
+        assert!(uuid::Uuid::parse_str(incident_id).is_ok());
+        assert_eq!(body["timestamp"], clock.utc().to_rfc3339());
+    }
+
+    #[tokio::test]
+    async fn error_timestamps_come_from_the_configured_clock() {
+        let clock = TestClock::new();
+        clock.advance(Duration::from_secs(86_400));
+        let (router, _) = test_support::app(test_support::config(&[("ADMIN_TOKEN", "admin-secret")]).with_clock(clock.clone()));
+
+        let response = test_support::send(&router, test_support::get("/admin/credentials", &[])).await;
+
+        assert_eq!(response.status, StatusCode::UNAUTHORIZED);
+        assert_eq!(response.json()["timestamp"], clock.utc().to_rfc3339());
+    }
+
+    #[test]
+    fn error_codes_serialize_to_their_wire_strings() {
+        let expected = [
+            (ErrorCode::AuthError, "AUTH_ERROR"),
+            (ErrorCode::WebauthnError, "WEBAUTHN_ERROR"),
+            (ErrorCode::ChallengeMismatch, "CHALLENGE_MISMATCH"),
+            (ErrorCode::OriginMismatch, "ORIGIN_MISMATCH"),
+            (ErrorCode::UserNotPresent, "USER_NOT_PRESENT"),
+            (ErrorCode::UserNotVerified, "USER_NOT_VERIFIED"),
+            (ErrorCode::CredentialCompromised, "CREDENTIAL_COMPROMISED"),
+            (ErrorCode::MalformedCredential, "MALFORMED_CREDENTIAL"),
+            (ErrorCode::UserCancelled, "USER_CANCELLED"),
+            (ErrorCode::DuplicateCredential, "DUPLICATE_CREDENTIAL"),
+            (ErrorCode::JwtError, "JWT_ERROR"),
+            (ErrorCode::InvalidBackendResponse, "INVALID_BACKEND_RESPONSE"),
+            (ErrorCode::SchemaValidationFailed, "SCHEMA_VALIDATION_FAILED"),
+            (ErrorCode::BadRequest, "BAD_REQUEST"),
+            (ErrorCode::Forbidden, "FORBIDDEN"),
+            (ErrorCode::NotFound, "NOT_FOUND"),
+            (ErrorCode::MethodNotAllowed, "METHOD_NOT_ALLOWED"),
+            (ErrorCode::HeaderTooLarge, "HEADER_TOO_LARGE"),
+            (ErrorCode::TimeoutError, "TIMEOUT_ERROR"),
+            (ErrorCode::RateLimited, "RATE_LIMITED"),
+            (ErrorCode::ServiceUnavailable, "SERVICE_UNAVAILABLE"),
+            (ErrorCode::Maintenance, "MAINTENANCE"),
+            (ErrorCode::InternalError, "INTERNAL_ERROR"),
+            (ErrorCode::ResponseBuildError, "RESPONSE_BUILD_ERROR"),
+        ];
+        for (code, wire) in expected {
+            assert_eq!(serde_json::to_value(code).unwrap(), wire);
+        }
+    }
+
+    // Status, Retry-After header and JSON body of an error response
+    async fn render(error: AppError) -> (StatusCode, Option<String>, serde_json::Value) {
+        let response = error.into_response();
+        let status = response.status();
+        let retry_after = response.headers().get("Retry-After").map(|value| value.to_str().unwrap().to_string());
+        let body = hyper::body::to_bytes(response.into_body()).await.unwrap();
+        (status, retry_after, serde_json::from_slice(&body).unwrap())
+    }
+
+    #[tokio::test]
+    async fn error_responses_carry_their_code() {
+        let (status, retry_after, body) = render(AppError::Authentication("no token".to_string())).await;
+        assert_eq!(status, StatusCode::UNAUTHORIZED);
+        assert_eq!((body["status"].as_str(), body["code"].as_str()), (Some("error"), Some("AUTH_ERROR")));
+        assert_eq!(retry_after, None);
+        assert!(body.get("retry_after_secs").is_none());
+    }
+
+    #[tokio::test]
+    async fn rate_limited_response_says_when_to_retry() {
+        let (status, retry_after, body) = render(AppError::RateLimited {
+            message: "Too many pending challenges".to_string(),
+            retry_after: Duration::from_secs(90),
+        }).await;
+
+        assert_eq!(status, StatusCode::TOO_MANY_REQUESTS);
+        assert_eq!(retry_after.as_deref(), Some("90"));
+        assert_eq!(body["code"], "RATE_LIMITED");
+        assert_eq!(body["message"], "Too many pending challenges");
+        assert_eq!(body["retry_after_secs"], 90);
+
+        // Less than a second left still asks for a whole second
+        let (_, retry_after, body) = render(AppError::RateLimited { message: String::new(), retry_after: Duration::from_millis(200) }).await;
+        assert_eq!((retry_after.as_deref(), body["retry_after_secs"].as_u64()), (Some("1"), Some(1)));
+    }
+
+    #[tokio::test]
+    async fn maintenance_response_says_when_to_retry() {
+        let (status, retry_after, body) = render(AppError::Maintenance { retry_after: Duration::from_secs(300) }).await;
+
+        assert_eq!(status, StatusCode::SERVICE_UNAVAILABLE);
+        assert_eq!(retry_after.as_deref(), Some("300"));
+        assert_eq!(body["code"], "MAINTENANCE");
+        assert_eq!(body["retry_after_secs"], 300);
+    }
+
+    #[tokio::test]
+    async fn webauthn_errors_map_to_client_codes() {
+        use webauthn_rs::prelude::WebauthnError as E;
+        for (error, status, code) in [
+            (E::MismatchedChallenge, StatusCode::BAD_REQUEST, "CHALLENGE_MISMATCH"),
+            (E::InvalidRPOrigin, StatusCode::BAD_REQUEST, "ORIGIN_MISMATCH"),
+            (E::UserNotVerified, StatusCode::BAD_REQUEST, "USER_NOT_VERIFIED"),
+            (E::CredentialAlreadyExists, StatusCode::CONFLICT, "DUPLICATE_CREDENTIAL"),
+            (E::AttestationNotSupported, StatusCode::BAD_REQUEST, "WEBAUTHN_ERROR"),
+        ] {
+            let raw = error.to_string();
+            let (actual_status, _, body) = render(AppError::WebAuthn(error)).await;
+            assert_eq!((actual_status, body["code"].as_str()), (status, Some(code)));
+            // The raw webauthn-rs message stays in the log
+            assert_ne!(body["message"], raw);
+        }
+    }
+}
@@ -1,63 +1,372 @@
-use std::env;
-use axum::Json;
+use std::{sync::Arc, time::{Duration, Instant}};
+use axum::{
+    body::Bytes,
+    extract::{Path, RawQuery},
+    http::{header, HeaderMap, Method, StatusCode},
+    response::{IntoResponse, Response},
+    Extension, Json,
+};
 use serde_json::Value;
+use tracing::Instrument;
 
-use crate::error::AppResult;
+use crate::admin;
+use crate::auth::AuthToken;
+use crate::cache::{self, CacheKey};
+use crate::config::Config;
+use crate::deadline::Deadline;
+use crate::discovery::ServiceRegistry;
+use crate::error::{AppError, AppResult};
+use crate::jwt;
+use crate::service::{Scheme, ServiceType};
 use crate::tls::get_tls_info;
-use crate::http_client::send_request;
-use crate::api_response::{ApiResponse, ApiResponseBuilder, is_authenticated, ensure_auth_consistency, determine_response_status};
+use crate::http_client::{send_request, BackendRequest, HttpMethod};
+use crate::limiter::BackendLimiter;
+use crate::api_response::{ApiResponseBuilder, ResponseStatus, is_authenticated, ensure_auth_consistency, determine_response_status};
 
-// Handle API request
-pub async fn handle_request(headers: axum::http::HeaderMap) -> AppResult<Json<ApiResponse>> {
-    // Get authorization header
-    let auth = headers.get("Authorization")
+// Bound on the original body echoed when JSON extraction leaves nothing
+const MAX_RAW_RESPONSE_BYTES: usize = 4096;
+
+// Inbound request to forward to a backend service
+pub struct ServiceRequest {
+    pub method: Method,
+    pub auth: Option<String>,
+    pub query: Option<String>,
+    pub headers: HeaderMap,
+    pub body: Bytes,
+}
+
+// Timing breakdown of a proxied request
+struct RequestTimings {
+    started: Instant,
+    handshake: Duration,
+    backend: Duration,
+}
+
+impl RequestTimings {
+    // Warn when the whole request took longer than the threshold
+    fn warn_if_slow(&self, service: ServiceType, threshold: Duration) {
+        let total = self.started.elapsed();
+        if total > threshold {
+            tracing::warn!(
+                "Slow request to {} service: {} ms total (handshake {} ms, backend {} ms)",
+                service.name(), total.as_millis(), self.handshake.as_millis(), self.backend.as_millis()
+            );
+        }
+    }
+}
+
+// Body text for the raw_response field. When extraction leaves nothing useful the
+// original body is shown instead, truncated to MAX_RAW_RESPONSE_BYTES.
+fn raw_response_text(extracted: &str, raw_body: &[u8]) -> String {
+    if !extracted.is_empty() {
+        return extracted.to_string();
+    }
+
+    let original = String::from_utf8_lossy(&raw_body[..raw_body.len().min(MAX_RAW_RESPONSE_BYTES)]).into_owned();
+    if raw_body.len() > MAX_RAW_RESPONSE_BYTES {
+        format!("{}... ({} bytes truncated)", original, raw_body.len() - MAX_RAW_RESPONSE_BYTES)
+    } else {
+        original
+    }
+}
+
+// Merge request metadata into a proxy_info object
+fn with_meta(mut info: Value, meta: &Value) -> Value {
+    if let (Some(info), Some(meta)) = (info.as_object_mut(), meta.as_object()) {
+        info.extend(meta.iter().map(|(key, value)| (key.clone(), value.clone())));
+    }
+    info
+}
+
+// Handle API request for the default service
+#[allow(clippy::too_many_arguments)]
+pub async fn handle_request(
+    Extension(config): Extension<Arc<Config>>,
+    Extension(limiter): Extension<Arc<BackendLimiter>>,
+    Extension(registry): Extension<Arc<ServiceRegistry>>,
+    method: Method,
+    AuthToken(auth): AuthToken,
+    RawQuery(query): RawQuery,
+    headers: HeaderMap,
+    body: Bytes,
+) -> AppResult<Response> {
+    let request = ServiceRequest { method, auth, query, headers, body };
+    handle_service_request(&config, &limiter, &registry, config.default_service, request).await
+}
+
+// Handle API request for the service named in the path
+#[allow(clippy::too_many_arguments)]
+pub async fn handle_service_by_path(
+    Extension(config): Extension<Arc<Config>>,
+    Extension(limiter): Extension<Arc<BackendLimiter>>,
+    Extension(registry): Extension<Arc<ServiceRegistry>>,
+    Path(service): Path<String>,
+    method: Method,
+    AuthToken(auth): AuthToken,
+    RawQuery(query): RawQuery,
+    headers: HeaderMap,
+    body: Bytes,
+) -> AppResult<Response> {
+    let service_type = service.parse::<ServiceType>()
+        .map_err(|_| AppError::NotFound(format!("Unknown service: {}", service)))?;
+
+    let request = ServiceRequest { method, auth, query, headers, body };
+    handle_service_request(&config, &limiter, &registry, service_type, request).await
+}
+
+// Forward request to the given backend service within a request span
+pub async fn handle_service_request(
+    config: &Config,
+    limiter: &BackendLimiter,
+    registry: &ServiceRegistry,
+    service: ServiceType,
+    request: ServiceRequest,
+) -> AppResult<Response> {
+    // Reuse the caller's request id when given so logs correlate across hops
+    let request_id = request.headers.get("X-Request-Id")
         .and_then(|h| h.to_str().ok())
-        .unwrap_or_default()
-        .to_string();
+        .map(str::to_string)
+        .unwrap_or_else(|| uuid::Uuid::new_v4().to_string());
+
+    let span = tracing::info_span!(
+        "service_request",
+        service = service.name(),
+        request_id = %request_id,
+        host = tracing::field::Empty,
+        port = tracing::field::Empty,
+    );
+
+    forward_request(config, limiter, registry, service, request)
+        .instrument(span)
+        .await
+}
+
+// Check that the caller may reach the service: services requiring authentication
+// refuse anonymous requests, and high-value services also need a recent step-up
+// assertion. Log streams go through the same gate.
+pub fn authorize(config: &Config, service: ServiceType, auth: Option<&str>, headers: &HeaderMap) -> AppResult<()> {
+    let step_up = config.step_up_services.contains(&service);
+    if step_up || config.auth_required_services.contains(&service) {
+        let token = auth
+            .ok_or_else(|| AppError::Authentication(format!("Authentication required for {} service", service.name())))?;
+        let claims = jwt::verify_jwt(&config.jwt, token)?;
+        jwt::verify_client(&claims, headers)?;
+        config.claims_validators.validate(&claims, service)?;
+        if step_up {
+            jwt::require_step_up(&config.jwt, &claims, config.step_up_max_age)?;
+        }
+    }
+    Ok(())
+}
+
+async fn forward_request(
+    config: &Config,
+    limiter: &BackendLimiter,
+    registry: &ServiceRegistry,
+    service: ServiceType,
+    request: ServiceRequest,
+) -> AppResult<Response> {
+    let ServiceRequest { method, mut auth, query, headers, body } = request;
+    let method = HttpMethod::try_from(&method)?;
+    let mut timings = RequestTimings { started: Instant::now(), handshake: Duration::ZERO, backend: Duration::ZERO };
+
+    // Service-to-service callers may present an API key instead of a JWT; the
+    // backend then receives a gateway-issued token for the key, scoped to the service
+    if auth.is_none() {
+        if let Some(key_id) = config.api_keys.authenticate(&headers, service)? {
+            tracing::info!("Authenticated {} for {} service", key_id, service);
+            let scopes = config.service_scopes.get(&service).map_or(&[][..], Vec::as_slice);
+            auth = Some(jwt::issue_jwt(&config.jwt, &key_id, &key_id, scopes, None)?);
+        }
+    }
+
+    tracing::info!("Forwarding request to {} service with {} auth token",
+                   service.name(), if auth.is_none() { "no" } else { "a" });
+
+    authorize(config, service, auth.as_deref(), &headers)?;
 
-    tracing::info!("Forwarding request with {} auth token", if auth.is_empty() { "no" } else { "a" });
+    // Short-circuit if the request deadline has already passed
+    let deadline = Deadline::from_headers(&headers, config.timeout_for(service), &config.clock);
+    if deadline.as_ref().is_some_and(Deadline::is_exceeded) {
+        return Err(AppError::Timeout("Request deadline exceeded".to_string()));
+    }
 
-    // Parse proxy URL
-    let proxy_url = env::var("QUANTUM_SAFE_PROXY_URL")
-        .unwrap_or_else(|_| "https://localhost:8443".to_string());
+    // Serve idempotent requests from cache when possible
+    let path = match &query {
+        Some(query) => format!("{}?{}", service.path(), query),
+        None => service.path().to_string(),
+    };
+    let cache_key = if method == HttpMethod::Get { CacheKey::for_request(&config.jwt, service, &path, auth.as_deref()) } else { None };
+    if let Some(cached) = cache_key.as_ref().and_then(cache::get) {
+        tracing::info!("Serving {} service response from cache", service.name());
+        return Ok(Json(cached).into_response());
+    }
+
+    // Wait for a backend slot so traffic spikes can't spawn unbounded OpenSSL processes
+    let _permit = limiter.acquire(service, deadline.as_ref()).await?;
+
+    // Resolve backend target, skipping replicas that failed their readiness probe.
+    // Sticky services keep each user on the same replica.
+    let subject = auth.as_deref()
+        .filter(|_| config.sticky_services.contains(&service))
+        .and_then(|token| jwt::verify_jwt(&config.jwt, token).ok())
+        .map(|claims| claims.sub);
+    let target = registry.pick(service, subject.as_deref());
+    config.host_policy.check(&target.host)?;
+    let span = tracing::Span::current();
+    span.record("host", target.host.as_str());
+    span.record("port", target.port);
 
-    let url_parts: Vec<&str> = proxy_url.trim_start_matches("https://").split(':').collect();
-    let host = url_parts.get(0).copied().unwrap_or("localhost");
-    let port = url_parts.get(1).and_then(|p| p.parse::<u16>().ok()).unwrap_or(443);
+    // Admins may request a verbose handshake trace for this request only
+    let debug_tls = headers.get("X-Debug-TLS").and_then(|h| h.to_str().ok()) == Some("true");
+    let verbose = debug_tls && admin::is_admin(config, &headers);
+    if debug_tls && !verbose {
+        tracing::warn!("Ignoring X-Debug-TLS header without a valid admin token");
+    }
 
-    // Get TLS info
-    let tls_info = get_tls_info(host, port)
-        .unwrap_or_else(|e| serde_json::json!({"error": format!("TLS info unavailable: {}", e)}));
+    // Send request. The exchange blocks on OpenSSL or a socket, so it runs on the
+    // blocking pool while this request holds its permit.
+    let is_write = !method.is_read_only();
+    let mut cache_ttl = None;
+    config.body_logger.log_request(service, auth.is_some(), &body);
+    let backend_started = Instant::now();
+    let call = {
+        let span = tracing::info_span!("backend_call", method = method.as_str());
+        let tls = config.tls_for(service).clone();
+        let target = target.clone();
+        let path = path.clone();
+        let host_header = config.host_headers.get(&service).cloned();
+        let auth = auth.clone();
+        let auth_template = config.auth_templates[&service].clone();
+        let content_type = headers.get(header::CONTENT_TYPE).and_then(|h| h.to_str().ok()).map(str::to_string);
+        let body = body.clone();
+        let deadline = deadline.clone();
+        let (header_limits, retry, tcp) = (config.header_limits, config.retry, config.tcp);
+        move || span.in_scope(|| {
+            let request = BackendRequest {
+                method,
+                path: &path,
+                host_header: host_header.as_deref(),
+                auth: auth.as_deref(),
+                auth_template: &auth_template,
+                content_type: content_type.as_deref(),
+                body: &body,
+                deadline: deadline.as_ref(),
+                header_limits,
+                retry,
+                tcp,
+            };
+            send_request(&tls, &target, &request)
+        })
+    };
+    let (result, retries) = tokio::task::spawn_blocking(call)
+        .await
+        .map_err(|e| AppError::Internal(format!("Backend call failed: {}", e)))?;
+    timings.backend = backend_started.elapsed();
+
+    // A request refused before sending (oversized or injected headers) is the client's error
+    let result = match result {
+        Err(e @ (AppError::HeaderTooLarge(_) | AppError::BadRequest(_))) => return Err(e),
+        result => result,
+    };
+    if let Ok(http_response) = &result {
+        config.body_logger.log_response(service, http_response.status.code, &http_response.raw_body);
+    }
 
-    // Send request
-    let auth_ref = if auth.is_empty() { None } else { Some(auth.as_str()) };
-    let response = match send_request(host, port, "/api", auth_ref) {
+    // Return non-JSON responses to write requests, media types the service is expected
+    // to return, and binary (non-UTF-8) bodies as-is, so the bytes arrive intact
+    if let Ok(http_response) = &result {
+        let expected = config.expected_content_types.get(&service)
+            .is_some_and(|types| http_response.has_media_type(types));
+        if (!http_response.is_json() && (is_write || expected)) || http_response.is_binary() {
+            timings.warn_if_slow(service, config.slow_request);
+            let status = StatusCode::from_u16(http_response.status.code).unwrap_or(StatusCode::BAD_GATEWAY);
+            let fallback = if http_response.is_binary() { "application/octet-stream" } else { "text/plain" };
+            let content_type = http_response.header("Content-Type").unwrap_or(fallback).to_string();
+            return Ok((status, [(header::CONTENT_TYPE, content_type)], http_response.raw_body.clone()).into_response());
+        }
+    }
+
+    // Get TLS info only after the real request, so a failed info handshake never downgrades its result
+    let handshake_started = Instant::now();
+    let tls_info = tracing::info_span!("tls_handshake").in_scope(|| match target.scheme {
+        Scheme::Https if deadline.as_ref().is_some_and(Deadline::is_exceeded) => serde_json::json!({"available": false}),
+        Scheme::Https => match get_tls_info(config.tls_for(service), &target.host, target.port, deadline.as_ref().and_then(Deadline::remaining), verbose) {
+            Ok(info) if info.connection == "success" => serde_json::json!(info),
+            // The info handshake failed on its own; the request above still stands
+            Ok(info) => {
+                tracing::warn!("TLS info unavailable: {}", info.connection);
+                serde_json::json!({"available": false})
+            },
+            Err(e) => {
+                tracing::warn!("TLS info unavailable: {}", e);
+                serde_json::json!({"available": false})
+            },
+        },
+        Scheme::Http => serde_json::json!({"connection": "plaintext", "pqc_enabled": false}),
+    });
+    timings.handshake = handshake_started.elapsed();
+
+    // How the request was served, merged into proxy_info
+    let proxy_meta = serde_json::json!({
+        "backend_latency_ms": timings.backend.as_millis() as u64,
+        "retry_count": retries,
+        "target_host": format!("{}:{}", target.host, target.port),
+        "transport": match target.scheme { Scheme::Https => "openssl", Scheme::Http => "native" },
+    });
+
+    let backend_status = result.as_ref().ok().map(|http_response| http_response.status.code);
+    let response = match result {
         Ok(http_response) => {
-            // Parse JSON response
-            match serde_json::from_str::<Value>(&http_response.body) {
+            let mut proxy_info = with_meta(http_response.status.to_json(), &proxy_meta);
+            if !config.surface_response_headers.is_empty() {
+                proxy_info["headers"] = http_response.surfaced_headers(&config.surface_response_headers);
+            }
+
+            // Parse JSON response; bodiless responses (204, headers only) stand in
+            // the configured placeholder
+            let empty = http_response.raw_body.is_empty();
+            let parsed = if empty {
+                Ok(config.empty_body_response.clone())
+            } else {
+                serde_json::from_str::<Value>(&http_response.body)
+            };
+            match parsed {
                 Ok(backend_json) => {
+                    // Reject successful responses that don't match the service's schema
+                    if let Some(schema) = config.response_schemas.get(&service) {
+                        if !http_response.status.is_error() && !empty {
+                            schema.validate(&backend_json).map_err(AppError::SchemaViolation)?;
+                        }
+                    }
+
                     // Check authentication status and ensure consistency
-                    let auth_status = is_authenticated(&auth);
+                    let auth_status = is_authenticated(auth.as_deref());
                     let modified_json = ensure_auth_consistency(&backend_json, auth_status);
                     let status = determine_response_status(&backend_json, http_response.status.code);
 
+                    // Only successful responses are cacheable
+                    if (200..300).contains(&http_response.status.code) && status == ResponseStatus::Success {
+                        cache_ttl = http_response.cache_max_age();
+                    }
+
                     // Build API response
                     ApiResponseBuilder::new()
                         .status(status)
                         .backend_response(modified_json)
-                        .proxy_info(http_response.status.to_json())
+                        .proxy_info(proxy_info)
                         .tls_info(tls_info)
                         .build()
                 },
                 Err(_) => {
                     // Cannot parse as JSON, create JSON object with raw response
                     ApiResponseBuilder::new()
-                        .status(if http_response.status.is_error() { "error" } else { "warning" })
+                        .status(if http_response.status.is_error() { ResponseStatus::Error } else { ResponseStatus::Warning })
                         .backend_response(serde_json::json!({
-                            "raw_response": http_response.body,
+                            "raw_response": raw_response_text(&http_response.body, &http_response.raw_body),
                             "parse_error": "Failed to parse response as JSON"
                         }))
-                        .proxy_info(http_response.status.to_json())
+                        .proxy_info(proxy_info)
                         .tls_info(tls_info)
                         .build()
                 }
@@ -68,13 +377,692 @@ pub async fn handle_request(headers: axum::http::HeaderMap) -> AppResult<Json<Ap
 
             // Build error response
             ApiResponseBuilder::new()
-                .status("error")
+                .status(ResponseStatus::Error)
                 .backend_response(serde_json::json!({"message": format!("Proxy error: {}", e)}))
-                .proxy_info(serde_json::json!({"status_line": "Error", "error": e.to_string()}))
+                .proxy_info(with_meta(serde_json::json!({"status_line": "Error", "error": e.to_string()}), &proxy_meta))
                 .tls_info(tls_info)
                 .build()
         }
     };
 
-    Ok(Json(response))
+    timings.warn_if_slow(service, config.slow_request);
+
+    // Cache response for the backend-declared max age
+    if let (Some(key), Some(max_age)) = (cache_key, cache_ttl) {
+        cache::put(key, &response, max_age);
+    }
+
+    // Optionally mirror backend errors in the gateway status code, keeping the envelope
+    let status = backend_status
+        .filter(|code| config.propagate_backend_status && *code >= 400)
+        .and_then(|code| StatusCode::from_u16(code).ok())
+        .unwrap_or(StatusCode::OK);
+
+    Ok((status, Json(response)).into_response())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{sync::{mpsc, Mutex}, time::Duration};
+    use axum::http::Method;
+    use serde_json::json;
+
+    use crate::error::AppError;
+    use crate::test_support::{self, CapturedLogs, MockBackend, StubOpenssl};
+
+    #[tokio::test]
+    async fn default_service_routes_verify_to_that_service() {
+        let users = MockBackend::json(&json!({"users": []}));
+        let logs = MockBackend::json(&json!({"logs": []}));
+        let (router, _) = test_support::app(test_support::config(&[
+            ("DEFAULT_SERVICE", "users"),
+            ("USER_SERVICE_URL", &users.url()),
+            ("LOG_SERVICE_URL", &logs.url()),
+        ]));
+
+        let response = test_support::send(&router, test_support::get("/api/auth/verify", &[])).await;
+
+        assert_eq!(response.status, 200);
+        assert_eq!(response.json()["backend_response"]["users"], json!([]));
+        assert_eq!(users.request_lines(), ["GET /api/users HTTP/1.1"]);
+        assert!(logs.requests().is_empty());
+    }
+
+    // Value of a header in a raw forwarded request
+    fn forwarded_header(raw: &[u8], name: &str) -> Option<String> {
+        let prefix = format!("{}:", name.to_ascii_lowercase());
+        String::from_utf8_lossy(raw).lines()
+            .take_while(|line| !line.is_empty())
+            .find(|line| line.to_ascii_lowercase().starts_with(&prefix))
+            .map(|line| line[prefix.len()..].trim().to_string())
+    }
+
+    #[tokio::test]
+    async fn exceeded_deadline_fails_without_contacting_backend() {
+        let logs = MockBackend::json(&json!({}));
+        let (router, _) = test_support::app(test_support::config(&[("LOG_SERVICE_URL", &logs.url())]));
+
+        let response = test_support::send(&router, test_support::get("/api/service/log", &[("X-Request-Deadline", "0")])).await;
+
+        assert_eq!(response.status, 504);
+        assert_eq!(response.json()["code"], "TIMEOUT_ERROR");
+        assert!(logs.requests().is_empty());
+    }
+
+    #[tokio::test]
+    async fn deadline_is_forwarded_with_remaining_budget() {
+        let logs = MockBackend::json(&json!({}));
+        let (router, _) = test_support::app(test_support::config(&[("LOG_SERVICE_URL", &logs.url())]));
+
+        let response = test_support::send(&router, test_support::get("/api/service/log", &[("X-Request-Deadline", "5000")])).await;
+
+        assert_eq!(response.status, 200);
+        let requests = logs.requests();
+        let remaining: u64 = forwarded_header(&requests[0], "X-Request-Deadline")
+            .expect("deadline header forwarded")
+            .parse()
+            .expect("remaining milliseconds");
+        assert!(remaining > 0 && remaining <= 5000, "remaining budget {}", remaining);
+    }
+
+    #[tokio::test]
+    async fn plain_http_backend_uses_native_transport() {
+        let logs = MockBackend::json(&json!({"ok": true}));
+        let (router, _) = test_support::app(test_support::config(&[("LOG_SERVICE_URL", &logs.url())]));
+
+        let body = test_support::send(&router, test_support::get("/api/service/log", &[])).await.json();
+
+        assert_eq!(body["proxy_info"]["transport"], "native");
+        assert_eq!(body["tls_info"]["connection"], "plaintext");
+        assert_eq!(logs.request_lines(), ["GET /api HTTP/1.1"]);
+    }
+
+    #[tokio::test]
+    async fn https_backend_uses_openssl_transport() {
+        // Nothing listens on the discard port, so the handshake never starts
+        let (router, _) = test_support::app(test_support::config(&[("LOG_SERVICE_URL", "https://127.0.0.1:9"), ("BACKEND_CONNECT_RETRIES", "0")]));
+
+        let body = test_support::send(&router, test_support::get("/api/service/log", &[])).await.json();
+
+        assert_eq!(body["proxy_info"]["transport"], "openssl");
+        assert_eq!(body["status"], "error");
+    }
+
+    // Body of a raw forwarded request
+    fn forwarded_body(raw: &[u8]) -> &[u8] {
+        let end = raw.windows(4).position(|w| w == b"\r\n\r\n").expect("request head");
+        &raw[end + 4..]
+    }
+
+    #[tokio::test]
+    async fn form_post_is_forwarded_byte_for_byte() {
+        let users = MockBackend::respond(test_support::http_response(201, "text/plain", b"created"));
+        let (router, _) = test_support::app(test_support::config(&[("USER_SERVICE_URL", &users.url())]));
+        let form = "name=alice&note=a%20b%26c";
+
+        let response = test_support::send(&router, test_support::request(
+            Method::POST, "/api/service/users", &[("Content-Type", "application/x-www-form-urlencoded")], form,
+        )).await;
+
+        assert_eq!(response.status, 201);
+        assert_eq!(&response.body[..], b"created");
+        let requests = users.requests();
+        assert_eq!(forwarded_header(&requests[0], "Content-Type").as_deref(), Some("application/x-www-form-urlencoded"));
+        assert_eq!(forwarded_body(&requests[0]), form.as_bytes());
+    }
+
+    #[tokio::test]
+    async fn body_emptied_by_json_extraction_is_shown_unmodified() {
+        // Every line looks like a chunk size, so extract_json keeps nothing
+        let body = "cafe\n  beef  \n";
+        let logs = MockBackend::respond(test_support::http_response(200, "text/plain", body.as_bytes()));
+        let (router, _) = test_support::app(test_support::config(&[("LOG_SERVICE_URL", &logs.url())]));
+
+        let response = test_support::send(&router, test_support::get("/api/service/log", &[])).await;
+
+        let backend = &response.json()["backend_response"];
+        assert_eq!(backend["raw_response"], body);
+        assert_eq!(backend["parse_error"], "Failed to parse response as JSON");
+    }
+
+    #[test]
+    fn echoed_original_body_is_bounded() {
+        let body = vec![b'0'; super::MAX_RAW_RESPONSE_BYTES + 10];
+
+        let text = super::raw_response_text("", &body);
+
+        assert!(text.starts_with(&"0".repeat(super::MAX_RAW_RESPONSE_BYTES)));
+        assert!(text.ends_with("... (10 bytes truncated)"));
+        assert_eq!(super::raw_response_text("{}", &body), "{}");
+    }
+
+    #[tokio::test]
+    async fn binary_response_is_passed_through_unmangled() {
+        let image: Vec<u8> = [&b"\x89PNG\r\n\x1a\n"[..], &[0x00, 0xff, 0xfe, 0xc3, 0x28, 0x80]].concat();
+        let logs = MockBackend::respond(test_support::http_response(200, "image/png", &image));
+        let untyped = MockBackend::respond([&format!("HTTP/1.1 200 OK\r\nContent-Length: {}\r\nConnection: close\r\n\r\n", image.len()).into_bytes()[..], &image].concat());
+        let (router, _) = test_support::app(test_support::config(&[("LOG_SERVICE_URL", &logs.url()), ("USER_SERVICE_URL", &untyped.url())]));
+
+        let response = test_support::send(&router, test_support::get("/api/service/log", &[])).await;
+        assert_eq!(response.status, 200);
+        assert_eq!(response.headers["content-type"], "image/png");
+        assert_eq!(&response.body[..], &image[..]);
+
+        let response = test_support::send(&router, test_support::get("/api/service/users", &[])).await;
+        assert_eq!(response.headers["content-type"], "application/octet-stream");
+        assert_eq!(&response.body[..], &image[..]);
+    }
+
+    #[tokio::test]
+    async fn binary_upload_is_forwarded_byte_for_byte() {
+        let users = MockBackend::respond(test_support::http_response(204, "text/plain", b""));
+        let (router, _) = test_support::app(test_support::config(&[("USER_SERVICE_URL", &users.url())]));
+        let upload: Vec<u8> = (0..=255).chain([0xff, 0xfe, 0x00, b'\r', b'\n']).collect();
+
+        let response = test_support::send(&router, test_support::request(
+            Method::PUT, "/api/service/users", &[("Content-Type", "application/octet-stream")], upload.clone(),
+        )).await;
+
+        assert_eq!(response.status, 204);
+        let requests = users.requests();
+        assert!(requests[0].starts_with(b"PUT /api/users HTTP/1.1\r\n"));
+        assert_eq!(forwarded_header(&requests[0], "Content-Length"), Some(upload.len().to_string()));
+        assert_eq!(forwarded_body(&requests[0]), &upload[..]);
+    }
+
+    #[tokio::test]
+    async fn service_name_is_normalized() {
+        let users = MockBackend::json(&json!({}));
+        let (router, _) = test_support::app(test_support::config(&[("USER_SERVICE_URL", &users.url())]));
+
+        for uri in ["/api/service/Users", "/api/service/users/", "/api/service/USERS"] {
+            let response = test_support::send(&router, test_support::get(uri, &[])).await;
+            assert_eq!(response.status, 200, "{}", uri);
+        }
+
+        assert_eq!(users.request_lines(), ["GET /api/users HTTP/1.1"; 3]);
+    }
+
+    #[tokio::test]
+    async fn unknown_service_is_not_found() {
+        let (router, _) = test_support::app(test_support::config(&[]));
+
+        let response = test_support::send(&router, test_support::get("/api/service/billing", &[])).await;
+
+        assert_eq!(response.status, 404);
+        assert_eq!(response.json()["code"], "NOT_FOUND");
+    }
+
+    #[tokio::test(flavor = "multi_thread", worker_threads = 2)]
+    async fn request_beyond_the_limit_queues_for_a_backend_slot() {
+        // The backend holds each request until the test releases it
+        let (release, released) = mpsc::channel::<()>();
+        let released = Mutex::new(released);
+        let logs = MockBackend::start(move |_| {
+            released.lock().unwrap().recv().ok();
+            test_support::http_response(200, "application/json", b"{}")
+        });
+        let (router, state) = test_support::app(test_support::config(&[
+            ("LOG_SERVICE_URL", &logs.url()),
+            ("MAX_CONCURRENT_BACKEND", "1"),
+        ]));
+
+        let first = tokio::spawn(test_support::send(&router, test_support::get("/api/service/log", &[])));
+        wait_until(|| logs.requests().len() == 1).await;
+        let second = tokio::spawn(test_support::send(&router, test_support::get("/api/service/log", &[])));
+
+        // The second request waits for the permit instead of opening a connection
+        tokio::time::sleep(Duration::from_millis(200)).await;
+        assert_eq!(logs.requests().len(), 1);
+        assert_eq!(state.limiter.in_flight(), 1);
+
+        release.send(()).unwrap();
+        assert_eq!(first.await.unwrap().status, 200);
+        wait_until(|| logs.requests().len() == 2).await;
+        release.send(()).unwrap();
+        assert_eq!(second.await.unwrap().status, 200);
+        assert_eq!(state.limiter.in_flight(), 0);
+    }
+
+    #[tokio::test(flavor = "multi_thread", worker_threads = 2)]
+    async fn request_that_cannot_get_a_slot_in_time_is_rejected() {
+        let (release, released) = mpsc::channel::<()>();
+        let released = Mutex::new(released);
+        let logs = MockBackend::start(move |_| {
+            released.lock().unwrap().recv().ok();
+            test_support::http_response(200, "application/json", b"{}")
+        });
+        let (router, _) = test_support::app(test_support::config(&[
+            ("LOG_SERVICE_URL", &logs.url()),
+            ("MAX_CONCURRENT_BACKEND", "1"),
+            ("BACKEND_QUEUE_TIMEOUT_MS", "50"),
+        ]));
+
+        let first = tokio::spawn(test_support::send(&router, test_support::get("/api/service/log", &[])));
+        wait_until(|| logs.requests().len() == 1).await;
+        let second = test_support::send(&router, test_support::get("/api/service/log", &[])).await;
+
+        assert_eq!(second.status, 503);
+        release.send(()).unwrap();
+        assert_eq!(first.await.unwrap().status, 200);
+        assert_eq!(logs.requests().len(), 1);
+    }
+
+    // Poll until the condition holds, failing after a few seconds
+    async fn wait_until(condition: impl Fn() -> bool) {
+        for _ in 0..500 {
+            if condition() {
+                return;
+            }
+            tokio::time::sleep(Duration::from_millis(10)).await;
+        }
+        panic!("condition not reached");
+    }
+
+    #[tokio::test]
+    async fn slow_backend_triggers_the_slow_request_warning() {
+        let logs = MockBackend::start(|_| {
+            std::thread::sleep(Duration::from_millis(50));
+            test_support::http_response(200, "application/json", b"{}")
+        });
+        let (router, _) = test_support::app(test_support::config(&[("LOG_SERVICE_URL", &logs.url()), ("SLOW_REQUEST_MS", "10")]));
+        let (captured, _guard) = CapturedLogs::start();
+
+        test_support::send(&router, test_support::get("/api/service/log", &[])).await;
+
+        let output = captured.contents();
+        assert!(output.contains("Slow request to log service"), "{}", output);
+        assert!(output.contains("(handshake "), "{}", output);
+    }
+
+    #[tokio::test]
+    async fn fast_backend_logs_no_slow_request_warning() {
+        let logs = MockBackend::json(&json!({}));
+        let (router, _) = test_support::app(test_support::config(&[("LOG_SERVICE_URL", &logs.url()), ("SLOW_REQUEST_MS", "60000")]));
+        let (captured, _guard) = CapturedLogs::start();
+
+        test_support::send(&router, test_support::get("/api/service/log", &[])).await;
+
+        assert!(!captured.contents().contains("Slow request"));
+    }
+
+    // Router for an HTTPS log service answered by the stub
+    fn https_app(stub: &StubOpenssl, vars: &[(&str, &str)]) -> axum::Router {
+        let path = stub.path();
+        let vars = [&[("LOG_SERVICE_URL", "https://127.0.0.1:8443"), ("OPENSSL_PATH", path.as_str())], vars].concat();
+        test_support::app(test_support::config(&vars)).0
+    }
+
+    #[tokio::test]
+    async fn debug_tls_header_with_admin_token_captures_the_handshake_trace() {
+        let stub = StubOpenssl::responding(&test_support::http_response(200, "application/json", b"{}"));
+        let router = https_app(&stub, &[("ADMIN_TOKEN", "admin-secret")]);
+
+        let body = test_support::send(&router, test_support::get("/api/service/log", &[
+            ("X-Debug-TLS", "true"),
+            ("X-Admin-Token", "admin-secret"),
+        ])).await.json();
+
+        let connections = stub.connections();
+        assert!(connections[1].ends_with("-msg -debug"), "{:?}", connections);
+        assert!(body["tls_info"]["debug_trace"].as_str().unwrap().contains("X25519MLKEM768"));
+    }
+
+    #[tokio::test]
+    async fn tls_info_stays_brief_without_the_debug_header_or_admin_token() {
+        for headers in [&[][..], &[("X-Debug-TLS", "true")][..], &[("X-Debug-TLS", "true"), ("X-Admin-Token", "wrong-secret")][..]] {
+            let stub = StubOpenssl::responding(&test_support::http_response(200, "application/json", b"{}"));
+            let router = https_app(&stub, &[("ADMIN_TOKEN", "admin-secret")]);
+
+            let body = test_support::send(&router, test_support::get("/api/service/log", headers)).await.json();
+
+            let connections = stub.connections();
+            assert!(connections[1].ends_with(" -msg"), "{:?}", connections);
+            assert_eq!(body["tls_info"]["protocol"], "TLSv1.3");
+            assert!(body["tls_info"].get("debug_trace").is_none(), "{:?}", headers);
+        }
+    }
+
+    #[tokio::test]
+    async fn anonymous_payment_request_is_rejected_locally() {
+        let payment = MockBackend::json(&json!({}));
+        let logs = MockBackend::json(&json!({"logs": []}));
+        let (router, _) = test_support::app(test_support::config(&[
+            ("PAYMENT_SERVICE_URL", &payment.url()),
+            ("LOG_SERVICE_URL", &logs.url()),
+            ("ALLOW_INSECURE_BACKENDS", "true"),
+        ]));
+
+        let rejected = test_support::send(&router, test_support::get("/api/service/payment", &[])).await;
+        let forwarded = test_support::send(&router, test_support::get("/api/service/log", &[])).await;
+
+        assert_eq!(rejected.status, 401);
+        assert_eq!(rejected.json()["message"], "Authentication required for payment service");
+        assert!(payment.requests().is_empty());
+        assert_eq!(forwarded.status, 200);
+        assert_eq!(logs.request_lines(), ["GET /api HTTP/1.1"]);
+    }
+
+    #[tokio::test]
+    async fn supported_methods_are_forwarded_as_sent() {
+        let users = MockBackend::json(&json!({}));
+        let (router, _) = test_support::app(test_support::config(&[("USER_SERVICE_URL", &users.url())]));
+
+        for method in [Method::PUT, Method::PATCH, Method::DELETE] {
+            let response = test_support::send(&router, test_support::request(method.clone(), "/api/service/users", &[], "")).await;
+            assert_eq!(response.status, 200, "{}", method);
+        }
+
+        assert_eq!(users.request_lines(), [
+            "PUT /api/users HTTP/1.1",
+            "PATCH /api/users HTTP/1.1",
+            "DELETE /api/users HTTP/1.1",
+        ]);
+    }
+
+    #[tokio::test]
+    async fn unsupported_method_is_rejected_before_openssl_runs() {
+        let stub = StubOpenssl::responding(&test_support::http_response(200, "application/json", b"{}"));
+        let router = https_app(&stub, &[]);
+
+        for method in [Method::TRACE, Method::from_bytes(b"PURGE").unwrap()] {
+            let response = test_support::send(&router, test_support::request(method.clone(), "/api/service/log", &[], "")).await;
+            assert_eq!(response.status, 405, "{}", method);
+            assert_eq!(response.json()["code"], "METHOD_NOT_ALLOWED");
+        }
+
+        assert!(stub.invocations().is_empty(), "{:?}", stub.invocations());
+    }
+
+    #[tokio::test]
+    async fn request_span_carries_service_and_request_id() {
+        let logs = MockBackend::json(&json!({}));
+        let (router, _) = test_support::app(test_support::config(&[("LOG_SERVICE_URL", &logs.url())]));
+        let (captured, _guard) = CapturedLogs::start();
+
+        test_support::send(&router, test_support::get("/api/service/log", &[("X-Request-Id", "req-42")])).await;
+
+        let output = captured.contents();
+        let forwarding = output.lines().find(|line| line.contains("Forwarding request")).expect("forwarding logged");
+        assert!(forwarding.contains(r#"service_request{service="log" request_id=req-42}"#), "{}", output);
+    }
+
+    #[tokio::test]
+    async fn oversized_forwarded_headers_are_rejected_with_431() {
+        let logs = MockBackend::json(&json!({}));
+        let (router, _) = test_support::app(test_support::config(&[("LOG_SERVICE_URL", &logs.url()), ("MAX_FORWARD_HEADER_BYTES", "64")]));
+        let token = test_support::bearer(&"x".repeat(100));
+
+        let response = test_support::send(&router, test_support::get("/api/service/log", &[("Authorization", &token)])).await;
+
+        assert_eq!(response.status, 431);
+        assert_eq!(response.json()["code"], "HEADER_TOO_LARGE");
+        assert!(logs.requests().is_empty());
+    }
+
+    #[tokio::test]
+    async fn token_is_forwarded_in_the_configured_auth_header() {
+        let logs = MockBackend::json(&json!({}));
+        let users = MockBackend::json(&json!({}));
+        let (router, _) = test_support::app(test_support::config(&[
+            ("LOG_SERVICE_URL", &logs.url()),
+            ("LOG_SERVICE_AUTH_HEADER", "X-Service-Token"),
+            ("LOG_SERVICE_AUTH_FORMAT", "token={token}"),
+            ("USER_SERVICE_URL", &users.url()),
+        ]));
+
+        for uri in ["/api/service/log", "/api/service/users"] {
+            test_support::send(&router, test_support::get(uri, &[("Authorization", "Bearer abc123")])).await;
+        }
+
+        let custom = &logs.requests()[0];
+        assert_eq!(forwarded_header(custom, "X-Service-Token").as_deref(), Some("token=abc123"));
+        assert_eq!(forwarded_header(custom, "Authorization"), None);
+        assert_eq!(forwarded_header(&users.requests()[0], "Authorization").as_deref(), Some("Bearer abc123"));
+    }
+
+    #[tokio::test]
+    async fn failed_tls_info_handshake_keeps_the_successful_response() {
+        // The real request (-ign_eof) succeeds; the separate info handshake fails
+        let stub = StubOpenssl::responding(&test_support::http_response(200, "application/json", br#"{"logs": []}"#))
+            .script("case \"$*\" in *-ign_eof*) ;; *) echo 'handshake failure' >&2; exit 1;; esac");
+        let router = https_app(&stub, &[]);
+
+        let response = test_support::send(&router, test_support::get("/api/service/log", &[])).await;
+
+        assert_eq!(response.status, 200);
+        let body = response.json();
+        assert_eq!(body["status"], "success");
+        assert_eq!(body["backend_response"]["logs"], json!([]));
+        assert_eq!(body["tls_info"], json!({"available": false}));
+    }
+
+    #[tokio::test]
+    async fn proxy_info_reports_timing_retries_target_and_transport() {
+        let logs = MockBackend::json(&json!({}));
+        let (router, _) = test_support::app(test_support::config(&[("LOG_SERVICE_URL", &logs.url())]));
+
+        let proxy_info = test_support::send(&router, test_support::get("/api/service/log", &[])).await.json()["proxy_info"].clone();
+
+        assert_eq!(proxy_info["status_code"], 200);
+        assert!(proxy_info["backend_latency_ms"].is_u64(), "{}", proxy_info);
+        assert_eq!(proxy_info["retry_count"], 0);
+        assert_eq!(proxy_info["target_host"], logs.addr.to_string());
+        assert_eq!(proxy_info["transport"], "native");
+    }
+
+    // Backend that drops the connection after part of a response
+    const FAILS_MID_REQUEST: &str = r#"
+case "$*" in *-ign_eof*) printf 'HTTP/1.1 200 OK\r\n'; echo 'read:errno=104' >&2; exit 1;; esac
+"#;
+
+    // Backend that cannot be reached at all
+    const REFUSES_CONNECTIONS: &str = r#"
+case "$*" in *-ign_eof*) echo 'connect:errno=111' >&2; exit 1;; esac
+"#;
+
+    // Number of times the request itself was sent to the backend
+    fn attempts(stub: &StubOpenssl) -> usize {
+        stub.connections().iter().filter(|args| args.contains("-ign_eof")).count()
+    }
+
+    #[tokio::test]
+    async fn post_is_not_retried_after_a_mid_request_failure() {
+        let stub = StubOpenssl::new(b"", "").script(FAILS_MID_REQUEST);
+        let router = https_app(&stub, &[("BACKEND_REQUEST_RETRIES", "2")]);
+
+        let response = test_support::send(&router, test_support::json_request(Method::POST, "/api/service/log", &[], &json!({"event": 1}))).await;
+
+        assert_eq!(response.json()["status"], "error", "{:?}", response.json());
+        assert_eq!(attempts(&stub), 1);
+    }
+
+    #[tokio::test]
+    async fn request_negotiating_tls_1_2_is_rejected_under_a_1_3_minimum() {
+        let response = test_support::http_response(200, "application/json", br#"{"logs": []}"#);
+        let tls12 = test_support::HANDSHAKE_SUMMARY.replace("TLSv1.3", "TLSv1.2");
+
+        let stub = StubOpenssl::new(&response, &tls12);
+        let router = https_app(&stub, &[("BACKEND_REQUEST_RETRIES", "2")]);
+        let body = test_support::send(&router, test_support::get("/api/service/log", &[])).await.json();
+        assert_eq!(body["status"], "error", "{:?}", body);
+        assert!(body.to_string().contains("TLS downgrade: negotiated TLSv1.2 but at least TLSv1.3 is required"), "{:?}", body);
+        assert_eq!(attempts(&stub), 1);
+
+        let stub = StubOpenssl::new(&response, &tls12);
+        let router = https_app(&stub, &[("TLS_MIN_VERSION", "1.2")]);
+        let body = test_support::send(&router, test_support::get("/api/service/log", &[])).await.json();
+        assert_eq!(body["status"], "success", "{:?}", body);
+    }
+
+    #[tokio::test]
+    async fn get_is_retried_after_a_mid_request_failure() {
+        let stub = StubOpenssl::new(b"", "").script(FAILS_MID_REQUEST);
+        let router = https_app(&stub, &[("BACKEND_REQUEST_RETRIES", "2")]);
+
+        let response = test_support::send(&router, test_support::get("/api/service/log", &[])).await;
+
+        assert_eq!(response.json()["status"], "error", "{:?}", response.json());
+        assert_eq!(attempts(&stub), 3);
+    }
+
+    #[tokio::test]
+    async fn post_is_retried_when_the_connection_cannot_be_set_up() {
+        let stub = StubOpenssl::new(b"", "").script(REFUSES_CONNECTIONS);
+        let router = https_app(&stub, &[("BACKEND_CONNECT_RETRIES", "2"), ("BACKEND_REQUEST_RETRIES", "0")]);
+
+        test_support::send(&router, test_support::json_request(Method::POST, "/api/service/log", &[], &json!({"event": 1}))).await;
+
+        assert_eq!(attempts(&stub), 3);
+    }
+
+    #[tokio::test]
+    async fn backend_error_status_is_mirrored_only_under_the_flag() {
+        let logs = MockBackend::respond(test_support::http_response(404, "application/json", br#"{"error":"no such log"}"#));
+
+        for (propagate, expected) in [("true", 404), ("false", 200)] {
+            let (router, _) = test_support::app(test_support::config(&[
+                ("LOG_SERVICE_URL", &logs.url()),
+                ("PROPAGATE_BACKEND_STATUS", propagate),
+            ]));
+
+            let response = test_support::send(&router, test_support::get("/api/service/log", &[])).await;
+
+            assert_eq!(response.status, expected, "PROPAGATE_BACKEND_STATUS={}", propagate);
+            // The envelope is the same either way
+            let body = response.json();
+            assert_eq!(body["proxy_info"]["status_code"], 404);
+            assert_eq!(body["backend_response"]["error"], "no such log");
+        }
+    }
+
+    #[tokio::test]
+    async fn each_service_gets_its_own_timeout() {
+        let slow = MockBackend::start(|_| {
+            std::thread::sleep(Duration::from_millis(300));
+            test_support::http_response(200, "application/json", b"{}")
+        });
+        let (router, _) = test_support::app(test_support::config(&[
+            ("LOG_SERVICE_URL", &slow.url()),
+            ("PAYMENT_SERVICE_URL", &slow.url()),
+            ("ALLOW_INSECURE_BACKENDS", "true"),
+            ("REQUIRE_AUTH_PER_SERVICE", ""),
+            ("REQUEST_TIMEOUT_MS", "5000"),
+            ("LOG_SERVICE_TIMEOUT_MS", "100"),
+            ("PAYMENT_SERVICE_TIMEOUT_MS", "2000"),
+        ]));
+
+        let log = test_support::send(&router, test_support::get("/api/service/log", &[])).await;
+        assert_eq!(log.json()["status"], "error");
+        assert!(log.json()["proxy_info"]["error"].as_str().unwrap().starts_with("Timeout"), "{}", log.json());
+
+        let payment = test_support::send(&router, test_support::get("/api/service/payment", &[])).await;
+        assert_eq!(payment.json()["status"], "success", "{}", payment.json());
+    }
+
+    #[tokio::test]
+    async fn host_header_override_is_forwarded_to_the_proxy_address() {
+        let proxy = MockBackend::json(&json!({}));
+        let (router, _) = test_support::app(test_support::config(&[
+            ("LOG_SERVICE_URL", &proxy.url()),
+            ("LOG_SERVICE_HOST_HEADER", "logs.internal"),
+        ]));
+
+        test_support::send(&router, test_support::get("/api/service/log", &[("Host", "gateway.example")])).await;
+
+        // The request reached the proxy's address, naming the logical service
+        let requests = proxy.requests();
+        assert_eq!(requests.len(), 1);
+        assert_eq!(forwarded_header(&requests[0], "Host").as_deref(), Some("logs.internal"));
+    }
+
+    #[test]
+    fn host_header_override_with_line_breaks_is_rejected() {
+        let result = test_support::try_config(&[("LOG_SERVICE_HOST_HEADER", "logs.internal\r\nX-Injected: 1")]);
+        assert!(matches!(result, Err(AppError::Internal(msg)) if msg.contains("LOG_SERVICE_HOST_HEADER")));
+    }
+
+    const XML: &[u8] = br#"<?xml version="1.0"?><logs><entry id="1">started &amp; ok</entry></logs>"#;
+
+    #[tokio::test]
+    async fn expected_xml_response_is_passed_through_untouched() {
+        let logs = MockBackend::respond(test_support::http_response(200, "application/xml; charset=utf-8", XML));
+        let (router, _) = test_support::app(test_support::config(&[
+            ("LOG_SERVICE_URL", &logs.url()),
+            ("LOG_SERVICE_CONTENT_TYPES", "application/xml, text/csv"),
+        ]));
+
+        let response = test_support::send(&router, test_support::get("/api/service/log", &[])).await;
+
+        assert_eq!(response.status, 200);
+        assert_eq!(response.headers["content-type"], "application/xml; charset=utf-8");
+        assert_eq!(response.body, XML);
+    }
+
+    #[tokio::test]
+    async fn unexpected_xml_response_is_wrapped_in_the_envelope() {
+        let logs = MockBackend::respond(test_support::http_response(200, "application/xml", XML));
+        let (router, _) = test_support::app(test_support::config(&[("LOG_SERVICE_URL", &logs.url())]));
+
+        let response = test_support::send(&router, test_support::get("/api/service/log", &[])).await;
+
+        assert_eq!(response.headers["content-type"], "application/json");
+        assert!(response.json()["proxy_info"].is_object());
+    }
+
+    #[tokio::test]
+    async fn only_allowlisted_response_headers_are_surfaced() {
+        let body = br#"{"ok":true}"#;
+        let logs = MockBackend::respond([
+            format!("HTTP/1.1 200 OK\r\nContent-Type: application/json\r\nServer: nginx/1.25.3\r\nX-Internal-Route: pod-7\r\nX-RateLimit-Remaining: 42\r\nContent-Length: {}\r\nConnection: close\r\n\r\n", body.len()).as_bytes(),
+            body,
+        ].concat());
+        let (router, _) = test_support::app(test_support::config(&[
+            ("LOG_SERVICE_URL", &logs.url()),
+            ("SURFACE_RESPONSE_HEADERS", "x-ratelimit-remaining, Connection"),
+        ]));
+
+        let response = test_support::send(&router, test_support::get("/api/service/log", &[])).await;
+
+        // Hop-by-hop headers stay hidden even when listed
+        assert_eq!(response.json()["proxy_info"]["headers"], json!({"x-ratelimit-remaining": "42"}));
+    }
+
+    #[tokio::test]
+    async fn no_response_headers_are_surfaced_by_default() {
+        let logs = MockBackend::json(&json!({"ok": true}));
+        let (router, _) = test_support::app(test_support::config(&[("LOG_SERVICE_URL", &logs.url())]));
+
+        let response = test_support::send(&router, test_support::get("/api/service/log", &[])).await;
+
+        assert!(response.json()["proxy_info"].get("headers").is_none());
+    }
+
+    #[tokio::test]
+    async fn payment_requires_a_recent_step_up() {
+        let payments = MockBackend::json(&json!({"charged": true}));
+        let (router, _) = test_support::app(test_support::config(&[
+            ("PAYMENT_SERVICE_URL", &payments.url()),
+            ("ALLOW_INSECURE_BACKENDS", "true"),
+            ("STEP_UP_REQUIRED_SERVICES", "payment"),
+        ]));
+        let mut passkey = test_support::register(&router, "alice").await;
+        let token = test_support::login_token(&router, "alice", &mut passkey).await;
+        let auth = test_support::bearer(&token);
+
+        let without = test_support::send(&router, test_support::request(Method::POST, "/api/service/payment", &[("Authorization", &auth)], "{}")).await;
+        assert_eq!(without.status, 401);
+        assert!(payments.requests().is_empty());
+
+        let step_up = test_support::send(&router, test_support::request(Method::POST, "/auth/step-up", &[("Authorization", &auth)], "")).await;
+        let credential = passkey.assert(&step_up.json()["public_key"]);
+        let verified = test_support::send(&router, test_support::json_request(Method::POST, "/auth/verify-step-up", &[("Authorization", &auth)], &json!({"credential": credential}))).await;
+        assert_eq!(verified.status, 200, "{}", String::from_utf8_lossy(&verified.body));
+        let elevated = test_support::bearer(verified.json()["token"].as_str().unwrap());
+
+        let with = test_support::send(&router, test_support::request(Method::POST, "/api/service/payment", &[("Authorization", &elevated)], "{}")).await;
+        assert_eq!(with.status, 200);
+        assert_eq!(with.json()["backend_response"]["charged"], true);
+        assert_eq!(payments.requests().len(), 1);
+    }
 }
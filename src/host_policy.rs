@@ -0,0 +1,110 @@
+use std::net::IpAddr;
+use crate::error::{AppError, AppResult};
+
+// Cloud metadata endpoints reachable by name
+const BLOCKED_HOSTNAMES: [&str; 2] = ["metadata.google.internal", "metadata"];
+
+// Backend hosts the gateway may connect to. Link-local addresses (which include the
+// 169.254.169.254 metadata service) and metadata hostnames are refused unless listed
+// exactly; with an allowlist, nothing else is permitted either.
+#[derive(Debug, Clone, Default)]
+pub struct HostPolicy {
+    // Exact hosts, or "*.example.com" to allow any subdomain
+    allow: Vec<String>,
+}
+
+impl HostPolicy {
+    // Parse a comma-separated allowlist
+    pub fn parse(value: &str) -> Self {
+        let allow = value.split(',')
+            .map(|pattern| pattern.trim().trim_start_matches('[').trim_end_matches(']').to_ascii_lowercase())
+            .filter(|pattern| !pattern.is_empty())
+            .collect();
+        Self { allow }
+    }
+
+    // Reject a connect target the policy does not permit
+    pub fn check(&self, host: &str) -> AppResult<()> {
+        let host = host.trim_start_matches('[').trim_end_matches(']').to_ascii_lowercase();
+        if self.allow.contains(&host) {
+            return Ok(());
+        }
+
+        let listed = self.allow.is_empty() || self.allow.iter()
+            .filter_map(|pattern| pattern.strip_prefix("*."))
+            .any(|suffix| host.strip_suffix(suffix).is_some_and(|rest| rest.ends_with('.')));
+
+        if blocked(&host) || !listed {
+            tracing::warn!(target: "audit", "Refusing to connect to backend host {}", host);
+            return Err(AppError::Forbidden(format!("Backend host {} is not allowed", host)));
+        }
+        Ok(())
+    }
+}
+
+// Link-local and metadata targets, the usual SSRF destinations
+fn blocked(host: &str) -> bool {
+    if BLOCKED_HOSTNAMES.contains(&host.trim_end_matches('.')) {
+        return true;
+    }
+
+    match host.parse::<IpAddr>() {
+        Ok(IpAddr::V4(ip)) => ip.is_link_local(),
+        Ok(IpAddr::V6(ip)) => match ip.to_ipv4_mapped() {
+            Some(ip) => ip.is_link_local(),
+            // fe80::/10, and the EC2 IPv6 metadata address
+            None => (ip.segments()[0] & 0xffc0) == 0xfe80 || ip.segments() == [0xfd00, 0xec2, 0, 0, 0, 0, 0, 0x254],
+        },
+        Err(_) => false,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use super::*;
+    use crate::test_support::{self, MockBackend};
+
+    #[test]
+    fn metadata_and_link_local_hosts_are_refused_by_default() {
+        let policy = HostPolicy::default();
+
+        for host in ["169.254.169.254", "metadata.google.internal", "metadata.", "[fe80::1]", "::ffff:169.254.169.254", "fd00:ec2::254"] {
+            assert!(policy.check(host).is_err(), "{} was allowed", host);
+        }
+        assert!(policy.check("logs.internal").is_ok());
+        assert!(policy.check("10.0.0.5").is_ok());
+    }
+
+    #[test]
+    fn allowlist_permits_listed_hosts_and_subdomains_only() {
+        let policy = HostPolicy::parse("logs.example.com, *.svc.cluster.local, 169.254.169.254");
+
+        assert!(policy.check("LOGS.example.com").is_ok());
+        assert!(policy.check("users.svc.cluster.local").is_ok());
+        assert!(policy.check("169.254.169.254").is_ok());
+        assert!(policy.check("svc.cluster.local").is_err());
+        assert!(policy.check("evilsvc.cluster.local").is_err());
+        assert!(policy.check("payments.example.com").is_err());
+    }
+
+    #[tokio::test]
+    async fn disallowed_backend_is_rejected_before_connecting() {
+        let logs = MockBackend::json(&json!({"logs": []}));
+        let config = |allowlist: &str| test_support::config(&[
+            ("LOG_SERVICE_URL", &logs.url()),
+            ("BACKEND_HOST_ALLOWLIST", allowlist),
+        ]);
+
+        let (denied, _) = test_support::app(config("logs.example.com"));
+        let response = test_support::send(&denied, test_support::get("/api/service/log", &[])).await;
+        assert_eq!(response.status, 403, "{}", String::from_utf8_lossy(&response.body));
+        assert!(logs.requests().is_empty());
+
+        let (permitted, _) = test_support::app(config("127.0.0.1"));
+        let response = test_support::send(&permitted, test_support::get("/api/service/log", &[])).await;
+        assert_eq!(response.status, 200, "{}", String::from_utf8_lossy(&response.body));
+        assert_eq!(logs.requests().len(), 1);
+    }
+}
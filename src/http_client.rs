@@ -1,7 +1,12 @@
-use reqwest::Client;
+use std::{io::{ErrorKind, Read, Write}, net::{TcpStream, ToSocketAddrs}, thread, time::Duration};
+use axum::http::Method;
 use serde_json::Value;
+use socket2::{SockRef, TcpKeepalive};
+use crate::deadline::{Deadline, DEADLINE_HEADER};
 use crate::error::{AppError, AppResult};
-use crate::tls::TlsConfig;
+use crate::http_parser::parse_http_response;
+use crate::service::{BackendTarget, Scheme};
+use crate::tls::{describe_tls_failure, verify_handshake, TlsConfig};
 
 // HTTP status structure
 #[derive(Debug, Clone)]
@@ -11,16 +16,6 @@ pub struct HttpStatus {
 }
 
 impl HttpStatus {
-    // Parse HTTP status from status line
-    pub fn from_status_line(status_line: &str) -> Self {
-        let code = status_line.split_whitespace()
-            .nth(1)
-            .and_then(|s| s.parse::<u16>().ok())
-            .unwrap_or(200);
-
-        Self { code, line: status_line.to_string() }
-    }
-
     // Check if status code is error (4xx, 5xx)
     pub fn is_error(&self) -> bool { self.code >= 400 }
 
@@ -30,22 +25,71 @@ impl HttpStatus {
     }
 }
 
+// Connection-level headers that describe the hop to the backend, not the response
+const HOP_BY_HOP_HEADERS: [&str; 8] = [
+    "connection", "keep-alive", "proxy-authenticate", "proxy-authorization",
+    "te", "trailer", "transfer-encoding", "upgrade",
+];
+
 // HTTP response structure
 #[derive(Debug)]
 pub struct HttpResponse {
     pub status: HttpStatus,
+    pub headers: Vec<(String, String)>,
+    // Extracted JSON text, empty for non-UTF-8 bodies
     pub body: String,
+    pub raw_body: Vec<u8>,
 }
 
-// Create PQC TLS client
-pub fn create_pqc_client() -> AppResult<Client> {
-    let config = TlsConfig::new();
-    tracing::info!("Using OpenSSL: {}, cert: {}, key: {}, CA: {}",
-                  config.openssl, config.cert, config.key, config.ca);
+impl HttpResponse {
+    // Get header value (case-insensitive)
+    pub fn header(&self, name: &str) -> Option<&str> {
+        self.headers.iter()
+            .find(|(key, _)| key.eq_ignore_ascii_case(name))
+            .map(|(_, value)| value.as_str())
+    }
+
+    // Check if backend declared a JSON body (or no content type at all)
+    pub fn is_json(&self) -> bool {
+        self.header("Content-Type").is_none_or(|ct| ct.to_ascii_lowercase().contains("json"))
+    }
 
-    reqwest::ClientBuilder::new()
-        .build()
-        .map_err(|e| AppError::Internal(format!("HTTP client error: {}", e)))
+    // Check if the body is not valid UTF-8, so its text form would be mangled
+    pub fn is_binary(&self) -> bool {
+        std::str::from_utf8(&self.raw_body).is_err()
+    }
+
+    // Allowlisted headers as a JSON object; hop-by-hop headers are never included
+    pub fn surfaced_headers(&self, allow: &[String]) -> Value {
+        let headers = self.headers.iter()
+            .filter(|(name, _)| !HOP_BY_HOP_HEADERS.iter().any(|hop| name.eq_ignore_ascii_case(hop)))
+            .filter(|(name, _)| allow.iter().any(|allowed| name.eq_ignore_ascii_case(allowed)))
+            .map(|(name, value)| (name.to_ascii_lowercase(), Value::String(value.clone())))
+            .collect();
+        Value::Object(headers)
+    }
+
+    // Check if the declared media type is one of the given types, ignoring parameters
+    pub fn has_media_type(&self, types: &[String]) -> bool {
+        let Some(content_type) = self.header("Content-Type") else { return false };
+        let media_type = content_type.split(';').next().unwrap_or_default().trim();
+        types.iter().any(|expected| expected.eq_ignore_ascii_case(media_type))
+    }
+
+    // Get cache lifetime from Cache-Control max-age
+    pub fn cache_max_age(&self) -> Option<Duration> {
+        let cache_control = self.header("Cache-Control")?.to_ascii_lowercase();
+        let directives: Vec<&str> = cache_control.split(',').map(str::trim).collect();
+
+        if directives.iter().any(|d| *d == "no-store" || *d == "no-cache") {
+            return None;
+        }
+
+        directives.iter()
+            .find_map(|d| d.strip_prefix("max-age=")?.parse::<u64>().ok())
+            .filter(|secs| *secs > 0)
+            .map(Duration::from_secs)
+    }
 }
 
 // Extract JSON from HTTP response
@@ -61,21 +105,241 @@ pub fn extract_json(raw: &str) -> String {
     raw.lines()
         .filter(|line| {
             let trimmed = line.trim();
-            !trimmed.is_empty() && !trimmed.chars().all(|c| c.is_digit(16) || c.is_whitespace())
+            !trimmed.is_empty() && !trimmed.chars().all(|c| c.is_ascii_hexdigit() || c.is_whitespace())
         })
         .collect::<Vec<&str>>()
         .join("\n")
 }
 
-// Send HTTP request and get response
-pub fn send_request(host: &str, port: u16, path: &str, auth: Option<&str>) -> AppResult<HttpResponse> {
+// Methods the gateway forwards; anything else never reaches the raw request line
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum HttpMethod {
+    Get,
+    Head,
+    Post,
+    Put,
+    Patch,
+    Delete,
+    Options,
+}
+
+impl HttpMethod {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            HttpMethod::Get => "GET",
+            HttpMethod::Head => "HEAD",
+            HttpMethod::Post => "POST",
+            HttpMethod::Put => "PUT",
+            HttpMethod::Patch => "PATCH",
+            HttpMethod::Delete => "DELETE",
+            HttpMethod::Options => "OPTIONS",
+        }
+    }
+
+    // Check if the method is read-only (GET, HEAD)
+    pub fn is_read_only(&self) -> bool {
+        matches!(self, HttpMethod::Get | HttpMethod::Head)
+    }
+
+    // Check if repeating the method has the same effect as sending it once
+    pub fn is_idempotent(&self) -> bool {
+        !matches!(self, HttpMethod::Post | HttpMethod::Patch)
+    }
+}
+
+impl TryFrom<&Method> for HttpMethod {
+    type Error = AppError;
+
+    fn try_from(method: &Method) -> AppResult<Self> {
+        match *method {
+            Method::GET => Ok(HttpMethod::Get),
+            Method::HEAD => Ok(HttpMethod::Head),
+            Method::POST => Ok(HttpMethod::Post),
+            Method::PUT => Ok(HttpMethod::Put),
+            Method::PATCH => Ok(HttpMethod::Patch),
+            Method::DELETE => Ok(HttpMethod::Delete),
+            Method::OPTIONS => Ok(HttpMethod::Options),
+            _ => Err(AppError::MethodNotAllowed(format!("Method {} is not supported", method))),
+        }
+    }
+}
+
+// Backend retry policy. Connection setup fails before any request bytes reach the
+// backend and is always safe to retry; a failure mid-request is only retried for
+// idempotent methods.
+#[derive(Debug, Clone, Copy)]
+pub struct RetryPolicy {
+    pub connect_retries: u32,
+    pub request_retries: u32,
+}
+
+// Socket options for native (plain TCP) backend connections
+#[derive(Debug, Clone, Copy)]
+pub struct TcpOptions {
+    // Idle time before keepalive probes start, so intermediaries keep idle connections open
+    pub keepalive: Option<Duration>,
+    // Disable Nagle's algorithm so small requests are sent immediately
+    pub nodelay: bool,
+}
+
+impl TcpOptions {
+    fn apply(&self, stream: &TcpStream) -> std::io::Result<()> {
+        stream.set_nodelay(self.nodelay)?;
+        if let Some(idle) = self.keepalive {
+            SockRef::from(stream).set_tcp_keepalive(&TcpKeepalive::new().with_time(idle))?;
+        }
+        Ok(())
+    }
+}
+
+// Pause between attempts
+const RETRY_DELAY: Duration = Duration::from_millis(100);
+
+// Stage at which a backend exchange failed
+enum SendFailure {
+    // Before the request was sent
+    Connect(AppError),
+    // After the request may have reached the backend
+    Request(AppError),
+    // Not worth retrying, e.g. an invalid request or an exhausted deadline
+    Final(AppError),
+}
+
+// OpenSSL diagnostics for failures before the handshake completes
+const CONNECT_FAILURE_MARKERS: [&str; 5] = ["connect:errno", "BIO_lookup", "Connection refused", "handshake failure", "certificate verify failed"];
+
+// Limits on client-supplied headers copied into the backend request
+#[derive(Debug, Clone, Copy)]
+pub struct HeaderLimits {
+    pub max_count: usize,
+    pub max_bytes: usize,
+}
+
+impl HeaderLimits {
+    // Reject header injection and oversized header sets before they reach the raw request
+    fn check(&self, headers: &[(&str, &str)]) -> AppResult<()> {
+        if let Some((name, _)) = headers.iter().find(|(_, value)| value.contains(['\r', '\n'])) {
+            return Err(AppError::BadRequest(format!("Invalid {} header value", name)));
+        }
+        if headers.len() > self.max_count {
+            return Err(AppError::HeaderTooLarge(format!(
+                "Too many forwarded headers ({} > {})", headers.len(), self.max_count
+            )));
+        }
+
+        // Count each header as it is serialized: "Name: value\r\n"
+        let total: usize = headers.iter().map(|(name, value)| name.len() + value.len() + 4).sum();
+        if total > self.max_bytes {
+            return Err(AppError::HeaderTooLarge(format!(
+                "Forwarded headers too large ({} > {} bytes)", total, self.max_bytes
+            )));
+        }
+
+        Ok(())
+    }
+}
+
+// How the client's token is forwarded to a backend
+#[derive(Debug, Clone)]
+pub struct AuthTemplate {
+    pub header: String,
+    // Value template where {token} is the client's bearer token
+    pub format: String,
+}
+
+impl AuthTemplate {
+    // Render the forwarded header value for a bearer token
+    pub fn render(&self, token: &str) -> String {
+        self.format.replace("{token}", token)
+    }
+}
+
+// Outgoing backend request
+pub struct BackendRequest<'a> {
+    pub method: HttpMethod,
+    pub path: &'a str,
+    // Host header when it differs from the connect target
+    pub host_header: Option<&'a str>,
+    pub auth: Option<&'a str>,
+    pub auth_template: &'a AuthTemplate,
+    pub content_type: Option<&'a str>,
+    pub body: &'a [u8],
+    pub deadline: Option<&'a Deadline>,
+    pub header_limits: HeaderLimits,
+    pub retry: RetryPolicy,
+    pub tcp: TcpOptions,
+}
+
+// Send HTTP request and get response, along with the number of retries it took
+pub fn send_request(tls: &TlsConfig, target: &BackendTarget, request: &BackendRequest) -> (AppResult<HttpResponse>, u32) {
+    let (mut connect_retries, mut request_retries) = (0, 0);
+    loop {
+        let retries = connect_retries + request_retries;
+        let failure = match send_once(tls, target, request) {
+            Ok(raw) => return (parse_http_response(&raw, request.method).map_err(AppError::from), retries),
+            Err(failure) => failure,
+        };
+
+        match failure {
+            SendFailure::Connect(e) if connect_retries < request.retry.connect_retries => {
+                tracing::warn!("Connecting to {}:{} failed, retrying: {}", target.host, target.port, e);
+                connect_retries += 1;
+            },
+            SendFailure::Request(e) if request.method.is_idempotent() && request_retries < request.retry.request_retries => {
+                tracing::warn!("{} {} failed, retrying: {}", request.method.as_str(), request.path, e);
+                request_retries += 1;
+            },
+            SendFailure::Connect(e) | SendFailure::Request(e) | SendFailure::Final(e) => return (Err(e), retries),
+        }
+        thread::sleep(RETRY_DELAY);
+    }
+}
+
+// Make a single attempt at the exchange, returning the raw response
+fn send_once(tls: &TlsConfig, target: &BackendTarget, request: &BackendRequest) -> Result<Vec<u8>, SendFailure> {
+    let (req, timeout) = build_request(target, request).map_err(SendFailure::Final)?;
+
+    // Execute request over the transport matching the URL scheme
+    match target.scheme {
+        Scheme::Https => send_tls(tls, target, &req, timeout),
+        Scheme::Http => send_plain(target, &req, timeout, request.tcp),
+    }
+}
+
+// Serialize the request, with the remaining deadline as its timeout
+fn build_request(target: &BackendTarget, request: &BackendRequest) -> AppResult<(Vec<u8>, Option<Duration>)> {
     // Build HTTP request
-    let mut req = format!("GET {} HTTP/1.1\r\nHost: {}\r\n", path, host);
+    let host = request.host_header.unwrap_or(&target.host);
+    let mut req = format!("{} {} HTTP/1.1\r\nHost: {}\r\n", request.method.as_str(), request.path, host);
 
-    if let Some(token) = auth {
-        req.push_str(&format!("Authorization: {}\r\n", token));
+    // Forward client headers, with the request body as-is under its content type
+    let auth = request.auth.map(|token| request.auth_template.render(token));
+    let forwarded: Vec<(&str, &str)> = [
+        (request.auth_template.header.as_str(), auth.as_deref()),
+        ("Content-Type", request.content_type),
+    ]
+    .into_iter()
+    .filter_map(|(name, value)| value.map(|value| (name, value)))
+    .collect();
+    request.header_limits.check(&forwarded)?;
+    for (name, value) in &forwarded {
+        req.push_str(&format!("{}: {}\r\n", name, value));
+    }
+    if !request.body.is_empty() || !request.method.is_read_only() {
+        req.push_str(&format!("Content-Length: {}\r\n", request.body.len()));
     }
 
+    // Forward remaining time budget so the backend gives up together with us
+    let timeout = match request.deadline {
+        Some(deadline) => {
+            let remaining = deadline.remaining()
+                .ok_or_else(|| AppError::Timeout("Request deadline exceeded".to_string()))?;
+            req.push_str(&format!("{}: {}\r\n", DEADLINE_HEADER, remaining.as_millis()));
+            Some(remaining)
+        },
+        None => None,
+    };
+
     req.push_str(concat!(
         "X-Content-Type-Options: nosniff\r\n",
         "X-Frame-Options: DENY\r\n",
@@ -83,28 +347,199 @@ pub fn send_request(host: &str, port: u16, path: &str, auth: Option<&str>) -> Ap
         "Connection: close\r\n\r\n"
     ));
 
-    // Execute request
-    let output = TlsConfig::new().run(host, port, &["-quiet"], Some(req.as_bytes()))?;
+    let mut req = req.into_bytes();
+    req.extend_from_slice(request.body);
+
+    Ok((req, timeout))
+}
+
+// s_client options for a request: the response alone on stdout, with the handshake
+// summary and message trace on stderr for verify_handshake. -ign_eof keeps the
+// connection open after the request is written, as -quiet would.
+const REQUEST_ARGS: [&str; 5] = ["-brief", "-ign_eof", "-msg", "-msgfile", "/dev/stderr"];
+
+// Send raw request through OpenSSL PQC mTLS
+fn send_tls(tls: &TlsConfig, target: &BackendTarget, req: &[u8], timeout: Option<Duration>) -> Result<Vec<u8>, SendFailure> {
+    let output = tls.clone()
+        .with_timeout(timeout)
+        .run_resuming(&target.host, target.port, &REQUEST_ARGS, Some(req))
+        .map_err(|e| match e {
+            AppError::Timeout(_) => SendFailure::Final(e),
+            e => SendFailure::Request(e),
+        })?;
 
     if !output.status.success() {
-        return Err(AppError::Internal(format!(
-            "TLS connection failed: {}",
-            String::from_utf8_lossy(&output.stderr)
-        )));
+        let stderr = String::from_utf8_lossy(&output.stderr);
+        let error = AppError::Internal(describe_tls_failure(&stderr));
+        let before_request = output.stdout.is_empty()
+            && CONNECT_FAILURE_MARKERS.iter().any(|marker| stderr.contains(marker));
+        return Err(if before_request { SendFailure::Connect(error) } else { SendFailure::Request(error) });
     }
 
-    // Parse response
-    let stdout = String::from_utf8_lossy(&output.stdout);
-    let parts: Vec<&str> = stdout.splitn(2, "\r\n\r\n").collect();
+    // A downgraded or renegotiated handshake is refused outright, never retried
+    verify_handshake(tls, &target.host, target.port, &String::from_utf8_lossy(&output.stderr))
+        .map_err(SendFailure::Final)?;
+    Ok(output.stdout)
+}
+
+// Send raw request over plain TCP
+fn send_plain(target: &BackendTarget, req: &[u8], timeout: Option<Duration>, tcp: TcpOptions) -> Result<Vec<u8>, SendFailure> {
+    tracing::warn!("Sending plaintext request to {}:{}", target.host, target.port);
+
+    let io_err = |e: std::io::Error| AppError::Internal(format!("TCP connection failed: {}", e));
+    let connect_err = |e| SendFailure::Connect(io_err(e));
+    // A socket timeout means the request's budget is spent, as with an OpenSSL timeout
+    let request_err = |e: std::io::Error| match e.kind() {
+        ErrorKind::WouldBlock | ErrorKind::TimedOut => SendFailure::Final(AppError::Timeout(format!("Backend did not respond: {}", e))),
+        _ => SendFailure::Request(io_err(e)),
+    };
+    let addr = (target.host.as_str(), target.port).to_socket_addrs().map_err(connect_err)?
+        .next()
+        .ok_or_else(|| SendFailure::Connect(AppError::Internal(format!("Cannot resolve {}", target.host))))?;
+
+    let mut stream = match timeout {
+        Some(timeout) => TcpStream::connect_timeout(&addr, timeout),
+        None => TcpStream::connect(addr),
+    }.map_err(connect_err)?;
+    stream.set_read_timeout(timeout).map_err(connect_err)?;
+    stream.set_write_timeout(timeout).map_err(connect_err)?;
+    tcp.apply(&stream).map_err(connect_err)?;
+
+    stream.write_all(req).map_err(request_err)?;
+    let mut raw = Vec::new();
+    stream.read_to_end(&mut raw).map_err(request_err)?;
+
+    Ok(raw)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::service::ServiceType;
+
+    const LIMITS: HeaderLimits = HeaderLimits { max_count: 2, max_bytes: 64 };
 
-    if parts.len() < 2 {
-        return Err(AppError::Internal("Invalid HTTP response".to_string()));
+    #[test]
+    fn header_count_beyond_the_limit_is_rejected() {
+        assert!(LIMITS.check(&[("A", "1"), ("B", "2")]).is_ok());
+
+        let result = LIMITS.check(&[("A", "1"), ("B", "2"), ("C", "3")]);
+        assert!(matches!(result, Err(AppError::HeaderTooLarge(msg)) if msg.contains("Too many")));
+    }
+
+    #[test]
+    fn header_bytes_beyond_the_limit_are_rejected() {
+        // Serialized as "Name: value\r\n"; 4 + 56 + 4 bytes fills the budget exactly
+        assert!(LIMITS.check(&[("Name", &"v".repeat(56))]).is_ok());
+
+        let result = LIMITS.check(&[("Name", &"v".repeat(57))]);
+        assert!(matches!(result, Err(AppError::HeaderTooLarge(msg)) if msg.contains("65 > 64")));
+    }
+
+    #[test]
+    fn header_values_with_line_breaks_are_rejected() {
+        for value in ["Bearer x\r\nX-Injected: 1", "Bearer x\nGET /admin HTTP/1.1", "text/plain\r"] {
+            let result = LIMITS.check(&[("Authorization", value)]);
+            assert!(matches!(result, Err(AppError::BadRequest(_))), "{:?}", value);
+        }
+    }
+
+    // Connected native socket, with the listener kept alive alongside it
+    fn connected_stream() -> (TcpStream, std::net::TcpListener) {
+        let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+        let stream = TcpStream::connect(listener.local_addr().unwrap()).unwrap();
+        (stream, listener)
+    }
+
+    #[test]
+    fn socket_options_are_applied_to_native_connections() {
+        let (stream, _listener) = connected_stream();
+        TcpOptions { keepalive: Some(Duration::from_secs(30)), nodelay: true }.apply(&stream).unwrap();
+        assert!(stream.nodelay().unwrap());
+        assert!(SockRef::from(&stream).keepalive().unwrap());
+
+        let (stream, _listener) = connected_stream();
+        TcpOptions { keepalive: None, nodelay: false }.apply(&stream).unwrap();
+        assert!(!stream.nodelay().unwrap());
+        assert!(!SockRef::from(&stream).keepalive().unwrap());
+    }
+
+    #[test]
+    fn socket_options_are_read_from_the_environment() {
+        let defaults = crate::test_support::config(&[]).tcp;
+        assert_eq!((defaults.keepalive, defaults.nodelay), (None, true));
+
+        let tuned = crate::test_support::config(&[("TCP_KEEPALIVE_SECS", "30"), ("TCP_NODELAY", "false")]).tcp;
+        assert_eq!((tuned.keepalive, tuned.nodelay), (Some(Duration::from_secs(30)), false));
+    }
+
+    // Send a POST with a token through whichever transport the target's scheme selects
+    fn send_to(config: &crate::config::Config, url: &str) -> (AppResult<HttpResponse>, u32) {
+        let target = BackendTarget::parse(url).unwrap();
+        let request = BackendRequest {
+            method: HttpMethod::Post,
+            path: "/api/logs",
+            host_header: None,
+            auth: Some("abc123"),
+            auth_template: &config.auth_templates[&ServiceType::Log],
+            content_type: Some("application/json"),
+            body: br#"{"event":1}"#,
+            deadline: None,
+            header_limits: config.header_limits,
+            retry: config.retry,
+            tcp: config.tcp,
+        };
+        send_request(config.tls_for(ServiceType::Log), &target, &request)
+    }
+
+    #[test]
+    fn both_transports_send_the_same_request_and_parse_the_same_response() {
+        let response = crate::test_support::http_response(201, "application/json", br#"{"id":7}"#);
+        let plain = crate::test_support::MockBackend::respond(response.clone());
+        let stub = crate::test_support::StubOpenssl::new(&response, "");
+        let path = stub.path();
+        let config = crate::test_support::config(&[("OPENSSL_PATH", &path)]);
+
+        let (over_tcp, _) = send_to(&config, &plain.url());
+        let (over_tls, _) = send_to(&config, "https://127.0.0.1:8443");
+
+        for response in [over_tcp.unwrap(), over_tls.unwrap()] {
+            assert_eq!(response.status.code, 201);
+            assert_eq!(response.header("content-type"), Some("application/json"));
+            assert_eq!(response.body, r#"{"id":7}"#);
+        }
+        let sent = String::from_utf8_lossy(&plain.requests()[0]).to_string();
+        assert!(sent.starts_with("POST /api/logs HTTP/1.1\r\n") && sent.contains("Authorization: Bearer abc123"), "{}", sent);
+        assert!(sent.ends_with(r#"{"event":1}"#));
+        assert!(stub.connections()[0].contains("-connect 127.0.0.1:8443"));
     }
 
-    let status_line = parts[0].lines().next().unwrap_or("HTTP/1.1 200 OK").to_string();
+    #[test]
+    fn both_transports_report_an_unreachable_backend() {
+        let closed = std::net::TcpListener::bind("127.0.0.1:0").unwrap().local_addr().unwrap();
+        let stub = crate::test_support::StubOpenssl::new(b"", "").script("echo 'connect:errno=111' >&2; exit 1");
+        let path = stub.path();
+        let config = crate::test_support::config(&[("OPENSSL_PATH", &path), ("BACKEND_CONNECT_RETRIES", "1")]);
+
+        let (over_tcp, tcp_retries) = send_to(&config, &format!("http://{}", closed));
+        let (over_tls, tls_retries) = send_to(&config, "https://127.0.0.1:8443");
+
+        assert!(over_tcp.is_err() && over_tls.is_err());
+        assert_eq!((tcp_retries, tls_retries), (1, 1));
+    }
 
-    Ok(HttpResponse {
-        status: HttpStatus::from_status_line(&status_line),
-        body: extract_json(parts[1])
-    })
+    #[test]
+    fn proxy_failing_certificate_verification_is_refused_before_the_request() {
+        let stderr = crate::test_support::verify_failure("num=18:self-signed certificate");
+        let stub = crate::test_support::StubOpenssl::new(b"", "").script(&format!("printf '%s' '{}' >&2; exit 1", stderr));
+        let path = stub.path();
+        let config = crate::test_support::config(&[("OPENSSL_PATH", &path), ("BACKEND_CONNECT_RETRIES", "1")]);
+
+        let (result, retries) = send_to(&config, "https://127.0.0.1:8443");
+
+        let message = result.unwrap_err().to_string();
+        assert!(message.contains("self-signed certificate") && message.contains("check CA_CERT_PATH"), "{}", message);
+        // Nothing was sent, so the failure counts as a connect failure
+        assert_eq!(retries, 1);
+    }
 }
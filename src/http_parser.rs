@@ -0,0 +1,346 @@
+use std::fmt;
+use crate::http_client::{extract_json, HttpMethod, HttpResponse, HttpStatus};
+
+// Why a backend response could not be parsed
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ParseError {
+    // No blank line ends the header block
+    MissingHeaderTerminator,
+    // First line is not `HTTP/x.y <3-digit code> [reason]`
+    InvalidStatusLine(String),
+    InvalidContentLength(String),
+    InvalidChunk,
+    // Connection closed before the declared body arrived
+    Truncated,
+}
+
+impl fmt::Display for ParseError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ParseError::MissingHeaderTerminator => write!(f, "missing header terminator"),
+            ParseError::InvalidStatusLine(line) => write!(f, "invalid status line: {:?}", line),
+            ParseError::InvalidContentLength(value) => write!(f, "invalid Content-Length: {:?}", value),
+            ParseError::InvalidChunk => write!(f, "invalid chunked encoding"),
+            ParseError::Truncated => write!(f, "truncated body"),
+        }
+    }
+}
+
+impl std::error::Error for ParseError {}
+
+// Connection buffer that may hold several pipelined responses
+pub struct ResponseBuffer {
+    data: Vec<u8>,
+    closed: bool,
+}
+
+impl ResponseBuffer {
+    // Create buffer holding everything read from an already closed connection
+    pub fn closed(data: Vec<u8>) -> Self {
+        Self { data, closed: true }
+    }
+
+    // Bytes not yet consumed by a response
+    pub fn remaining(&self) -> &[u8] {
+        &self.data
+    }
+
+    // Take the next complete response, leaving any following bytes buffered.
+    // Returns None while more data is needed.
+    pub fn next_response(&mut self, method: HttpMethod) -> Result<Option<HttpResponse>, ParseError> {
+        match take_response(&self.data, self.closed, method)? {
+            Some((response, consumed)) => {
+                self.data.drain(..consumed);
+                Ok(Some(response))
+            },
+            None => Ok(None),
+        }
+    }
+}
+
+// Parse the single response on a closed connection
+pub fn parse_http_response(bytes: &[u8], method: HttpMethod) -> Result<HttpResponse, ParseError> {
+    let mut buffer = ResponseBuffer::closed(bytes.to_vec());
+    let response = buffer.next_response(method)?.ok_or(ParseError::MissingHeaderTerminator)?;
+
+    // Bytes past the framed response belong to no request we sent
+    if !buffer.remaining().is_empty() {
+        tracing::warn!("Discarding {} unexpected bytes after backend response", buffer.remaining().len());
+    }
+
+    Ok(response)
+}
+
+fn find(haystack: &[u8], needle: &[u8]) -> Option<usize> {
+    haystack.windows(needle.len()).position(|window| window == needle)
+}
+
+// Find the end of the header block, accepting lone-LF line endings.
+// Returns the header length and the separator length.
+fn find_header_end(buf: &[u8]) -> Option<(usize, usize)> {
+    let crlf = find(buf, b"\r\n\r\n").map(|pos| (pos, 4));
+    let lf = find(buf, b"\n\n").map(|pos| (pos, 2));
+
+    match (crlf, lf) {
+        (Some(a), Some(b)) => Some(if a.0 <= b.0 { a } else { b }),
+        (a, b) => a.or(b),
+    }
+}
+
+// Read one line ending in LF or CRLF, returning it and the bytes consumed
+fn read_line(buf: &[u8]) -> Option<(&[u8], usize)> {
+    let end = buf.iter().position(|b| *b == b'\n')?;
+    let line = buf[..end].strip_suffix(b"\r").unwrap_or(&buf[..end]);
+    Some((line, end + 1))
+}
+
+// Strictly parse the status line
+fn parse_status_line(line: &str) -> Result<HttpStatus, ParseError> {
+    let invalid = || ParseError::InvalidStatusLine(line.chars().take(64).collect());
+    let mut parts = line.splitn(3, ' ');
+
+    let version = parts.next().unwrap_or_default();
+    let code = parts.next().unwrap_or_default();
+    if !version.starts_with("HTTP/") || code.len() != 3 {
+        return Err(invalid());
+    }
+
+    let code = code.parse::<u16>().map_err(|_| invalid())?;
+    if !(100..600).contains(&code) {
+        return Err(invalid());
+    }
+
+    Ok(HttpStatus { code, line: line.to_string() })
+}
+
+// Parse one response from the front of the buffer, returning it with the bytes consumed
+fn take_response(buf: &[u8], closed: bool, method: HttpMethod) -> Result<Option<(HttpResponse, usize)>, ParseError> {
+    let (head_end, separator) = match find_header_end(buf) {
+        Some(end) => end,
+        // A closed connection may end right after the headers of a bodiless response;
+        // it is only malformed when there is no status line at all
+        None if closed && buf.starts_with(b"HTTP/") => (buf.len(), 0),
+        None if closed => return Err(ParseError::MissingHeaderTerminator),
+        None => return Ok(None),
+    };
+
+    let head = String::from_utf8_lossy(&buf[..head_end]);
+    let mut lines = head.lines();
+    let status = parse_status_line(lines.next().unwrap_or_default())?;
+    let headers: Vec<(String, String)> = lines
+        .filter_map(|line| line.split_once(':'))
+        .map(|(key, value)| (key.trim().to_string(), value.trim().to_string()))
+        .collect();
+    let header = |name: &str| headers.iter()
+        .find(|(key, _)| key.eq_ignore_ascii_case(name))
+        .map(|(_, value)| value.as_str());
+
+    let body_start = head_end + separator;
+    let rest = &buf[body_start..];
+
+    // Determine body framing
+    let no_body = method == HttpMethod::Head || status.code / 100 == 1 || status.code == 204 || status.code == 304;
+    let chunked = header("Transfer-Encoding").is_some_and(|te| te.to_ascii_lowercase().contains("chunked"));
+
+    let (body, consumed) = if no_body {
+        (Vec::new(), 0)
+    } else if chunked {
+        match decode_chunked(rest)? {
+            Some(decoded) => decoded,
+            None if closed => return Err(ParseError::Truncated),
+            None => return Ok(None),
+        }
+    } else if let Some(length) = header("Content-Length") {
+        let length = length.parse::<usize>().map_err(|_| ParseError::InvalidContentLength(length.to_string()))?;
+        if rest.len() < length {
+            return if closed { Err(ParseError::Truncated) } else { Ok(None) };
+        }
+        (rest[..length].to_vec(), length)
+    } else if closed {
+        // Body delimited by connection close
+        (rest.to_vec(), rest.len())
+    } else {
+        return Ok(None);
+    };
+
+    // Only valid UTF-8 bodies are treated as text; binary bodies stay available as bytes
+    let body_text = std::str::from_utf8(&body).map(extract_json).unwrap_or_default();
+    let response = HttpResponse {
+        status,
+        body: body_text,
+        raw_body: body,
+        headers,
+    };
+
+    Ok(Some((response, body_start + consumed)))
+}
+
+// Decode chunked body, returning it with the bytes consumed, or None if incomplete
+fn decode_chunked(buf: &[u8]) -> Result<Option<(Vec<u8>, usize)>, ParseError> {
+    let mut body = Vec::new();
+    let mut pos = 0;
+
+    loop {
+        let Some((size_line, used)) = read_line(&buf[pos..]) else { return Ok(None) };
+        let size_line = std::str::from_utf8(size_line).map_err(|_| ParseError::InvalidChunk)?;
+        let size_hex = size_line.split(';').next().unwrap_or_default().trim();
+        let size = usize::from_str_radix(size_hex, 16).map_err(|_| ParseError::InvalidChunk)?;
+        pos += used;
+
+        if size == 0 {
+            // Skip trailers up to the terminating empty line
+            loop {
+                let Some((line, used)) = read_line(&buf[pos..]) else { return Ok(None) };
+                pos += used;
+                if line.is_empty() {
+                    return Ok(Some((body, pos)));
+                }
+            }
+        }
+
+        let end = pos.checked_add(size).ok_or(ParseError::InvalidChunk)?;
+        if buf.len() < end {
+            return Ok(None);
+        }
+        body.extend_from_slice(&buf[pos..end]);
+        pos = end;
+
+        // Chunk data is followed by CRLF (or a lone LF)
+        match read_line(&buf[pos..]) {
+            Some(([], used)) => pos += used,
+            Some(_) => return Err(ParseError::InvalidChunk),
+            None => return Ok(None),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse(raw: &[u8]) -> Result<HttpResponse, ParseError> {
+        parse_http_response(raw, HttpMethod::Get)
+    }
+
+    #[test]
+    fn back_to_back_responses_are_read_separately() {
+        let raw = b"HTTP/1.1 200 OK\r\nContent-Length: 14\r\n\r\n{\"first\":true}\
+HTTP/1.1 201 Created\r\nTransfer-Encoding: chunked\r\n\r\n5\r\n{\"a\":\r\n2\r\n1}\r\n0\r\n\r\n";
+        let mut buffer = ResponseBuffer::closed(raw.to_vec());
+
+        let first = buffer.next_response(HttpMethod::Get).unwrap().unwrap();
+        assert_eq!(first.status.code, 200);
+        assert_eq!(first.raw_body, b"{\"first\":true}");
+
+        let second = buffer.next_response(HttpMethod::Get).unwrap().unwrap();
+        assert_eq!(second.status.code, 201);
+        assert_eq!(second.raw_body, b"{\"a\":1}");
+        assert!(buffer.remaining().is_empty());
+    }
+
+    #[test]
+    fn incomplete_response_on_open_connection_waits_for_more() {
+        let partial = b"HTTP/1.1 200 OK\r\nContent-Length: 10\r\n\r\nabc";
+        assert!(take_response(partial, false, HttpMethod::Get).unwrap().is_none());
+        assert!(take_response(b"HTTP/1.1 200 OK\r\n", false, HttpMethod::Get).unwrap().is_none());
+        assert!(take_response(b"HTTP/1.1 200 OK\r\nTransfer-Encoding: chunked\r\n\r\n5\r\nab", false, HttpMethod::Get).unwrap().is_none());
+    }
+
+    #[test]
+    fn content_length_frames_the_body() {
+        let response = parse(b"HTTP/1.1 200 OK\r\nContent-Length: 2\r\n\r\n{}trailing").unwrap();
+        assert_eq!(response.raw_body, b"{}");
+        assert_eq!(response.body, "{}");
+    }
+
+    #[test]
+    fn chunked_body_is_decoded_with_extensions_and_trailers() {
+        let response = parse(b"HTTP/1.1 200 OK\r\nTransfer-Encoding: chunked\r\n\r\n3;ext=1\r\n{\"a\r\n4\r\n\":1}\r\n0\r\nX-Trailer: y\r\n\r\n").unwrap();
+        assert_eq!(response.raw_body, b"{\"a\":1}");
+    }
+
+    #[test]
+    fn head_response_has_no_body() {
+        let raw = b"HTTP/1.1 200 OK\r\nContent-Length: 5\r\n\r\n";
+        let response = parse_http_response(raw, HttpMethod::Head).unwrap();
+        assert!(response.raw_body.is_empty());
+    }
+
+    #[test]
+    fn lone_lf_separators_are_accepted() {
+        let response = parse(b"HTTP/1.1 200 OK\nContent-Type: application/json\n\n{\"ok\":true}").unwrap();
+        assert_eq!(response.status.code, 200);
+        assert_eq!(response.header("content-type"), Some("application/json"));
+        assert_eq!(response.body, "{\"ok\":true}");
+    }
+
+    #[test]
+    fn binary_body_is_kept_as_bytes() {
+        let body = [0xff, 0x00, 0xfe, 0x80];
+        let raw = [&b"HTTP/1.1 200 OK\r\nContent-Length: 4\r\n\r\n"[..], &body].concat();
+        let response = parse(&raw).unwrap();
+        assert_eq!(response.raw_body, body);
+        assert!(response.body.is_empty());
+        assert!(response.is_binary());
+    }
+
+    #[test]
+    fn bodiless_responses_parse_as_success() {
+        for raw in [
+            &b"HTTP/1.1 204 No Content\r\nDate: Mon, 01 Jan 2024 00:00:00 GMT\r\n\r\n"[..],
+            b"HTTP/1.1 204 No Content\r\n",
+            b"HTTP/1.1 200 OK\r\nContent-Length: 0\r\n\r\n",
+            b"HTTP/1.1 200 OK\r\nContent-Type: application/json\r\n",
+        ] {
+            let response = parse(raw).unwrap_or_else(|e| panic!("{:?}: {:?}", String::from_utf8_lossy(raw), e));
+            assert!(!response.status.is_error());
+            assert!(response.raw_body.is_empty() && response.body.is_empty(), "{:?}", String::from_utf8_lossy(raw));
+        }
+    }
+
+    #[test]
+    fn body_delimited_by_close() {
+        let response = parse(b"HTTP/1.0 200 OK\r\n\r\nhello").unwrap();
+        assert_eq!(response.raw_body, b"hello");
+    }
+
+    #[test]
+    fn missing_header_terminator() {
+        assert_eq!(parse(b"not an http response").unwrap_err(), ParseError::MissingHeaderTerminator);
+        assert_eq!(parse(b"").unwrap_err(), ParseError::MissingHeaderTerminator);
+    }
+
+    #[test]
+    fn invalid_status_line() {
+        for raw in [&b"HTTP/1.1 abc OK\r\n\r\n"[..], b"HTTP/1.1 20 OK\r\n\r\n", b"HTTP/1.1 999 Bad\r\n\r\n", b"SIP/2.0 200 OK\r\n\r\n"] {
+            assert!(matches!(parse(raw), Err(ParseError::InvalidStatusLine(_))), "{:?}", String::from_utf8_lossy(raw));
+        }
+    }
+
+    #[test]
+    fn invalid_content_length() {
+        let err = parse(b"HTTP/1.1 200 OK\r\nContent-Length: ten\r\n\r\n").unwrap_err();
+        assert_eq!(err, ParseError::InvalidContentLength("ten".to_string()));
+    }
+
+    #[test]
+    fn invalid_chunk() {
+        let bad_size = b"HTTP/1.1 200 OK\r\nTransfer-Encoding: chunked\r\n\r\nzz\r\n";
+        assert_eq!(parse(bad_size).unwrap_err(), ParseError::InvalidChunk);
+
+        let missing_crlf = b"HTTP/1.1 200 OK\r\nTransfer-Encoding: chunked\r\n\r\n2\r\nabcd\r\n0\r\n\r\n";
+        assert_eq!(parse(missing_crlf).unwrap_err(), ParseError::InvalidChunk);
+
+        let overflow = b"HTTP/1.1 200 OK\r\nTransfer-Encoding: chunked\r\n\r\nffffffffffffffff\r\nab";
+        assert_eq!(parse(overflow).unwrap_err(), ParseError::InvalidChunk);
+    }
+
+    #[test]
+    fn truncated_body() {
+        let short = b"HTTP/1.1 200 OK\r\nContent-Length: 10\r\n\r\nabc";
+        assert_eq!(parse(short).unwrap_err(), ParseError::Truncated);
+
+        let unterminated = b"HTTP/1.1 200 OK\r\nTransfer-Encoding: chunked\r\n\r\n5\r\nab";
+        assert_eq!(parse(unterminated).unwrap_err(), ParseError::Truncated);
+    }
+}
@@ -0,0 +1,105 @@
+use std::{fs, path::PathBuf, sync::{Arc, RwLock}};
+use axum::{http::{HeaderMap, StatusCode}, response::{Html, IntoResponse, Response}, Extension, Json};
+
+use crate::admin;
+use crate::api_response::ResponseStatus;
+use crate::config::Config;
+use crate::error::{AppError, AppResult};
+
+// Served when the index file is missing, without revealing where it was looked up
+const PLACEHOLDER: &str = "<!DOCTYPE html>\n<html><head><title>PasskeyMesh Gateway</title></head>\
+<body><h1>PasskeyMesh Gateway</h1><p>The gateway is running, but no index page is installed.</p></body></html>\n";
+
+// Index page cached in memory, reloadable at runtime
+pub struct IndexPage {
+    path: PathBuf,
+    base_path: String,
+    html: RwLock<Option<String>>,
+}
+
+impl IndexPage {
+    pub fn load(path: impl Into<PathBuf>, base_path: &str) -> Self {
+        let page = Self { path: path.into(), base_path: base_path.to_string(), html: RwLock::new(None) };
+        page.reload();
+        page
+    }
+
+    // Re-read the index file, returning whether it was found
+    pub fn reload(&self) -> bool {
+        let html = match fs::read_to_string(&self.path) {
+            Ok(html) => Some(self.with_base(html)),
+            Err(err) => {
+                tracing::warn!("Index page {} unavailable, serving placeholder: {}", self.path.display(), err);
+                None
+            }
+        };
+
+        let found = html.is_some();
+        if let Ok(mut cached) = self.html.write() {
+            *cached = html;
+        }
+        found
+    }
+
+    // Resolve the page's relative URLs against the base path, wherever it is served from
+    fn with_base(&self, html: String) -> String {
+        let base = format!("<base href=\"{}/\">", self.base_path);
+        match html.find("<head>") {
+            Some(pos) => format!("{}{}{}", &html[..pos + "<head>".len()], base, &html[pos + "<head>".len()..]),
+            None => html,
+        }
+    }
+}
+
+// Serve index.html page
+pub async fn serve_index(Extension(page): Extension<Arc<IndexPage>>) -> Response {
+    match page.html.read().ok().and_then(|html| html.clone()) {
+        Some(html) => Html(html).into_response(),
+        None => (StatusCode::NOT_FOUND, Html(PLACEHOLDER)).into_response(),
+    }
+}
+
+// Reload the index page from disk (admin only)
+pub async fn reload_index(
+    Extension(config): Extension<Arc<Config>>,
+    Extension(page): Extension<Arc<IndexPage>>,
+    headers: HeaderMap,
+) -> AppResult<Json<serde_json::Value>> {
+    admin::require_admin(&config, &headers)?;
+
+    if !page.reload() {
+        return Err(AppError::NotFound("Index page not found".to_string()));
+    }
+
+    Ok(Json(serde_json::json!({"status": ResponseStatus::Success, "message": "Index page reloaded"})))
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::test_support;
+
+    #[tokio::test]
+    async fn missing_index_serves_the_placeholder_without_the_path() {
+        let (router, _) = test_support::app(test_support::config(&[("INDEX_HTML_PATH", "/srv/secret-location/index.html")]));
+
+        let response = test_support::send(&router, test_support::get("/", &[])).await;
+
+        assert_eq!(response.status, 404);
+        let page = String::from_utf8_lossy(&response.body);
+        assert!(page.contains("no index page is installed"), "{}", page);
+        assert!(!page.contains("secret-location"), "{}", page);
+    }
+
+    #[tokio::test]
+    async fn index_is_served_from_the_configured_path() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("index.html");
+        std::fs::write(&path, "<html><head></head><body>hello</body></html>").unwrap();
+        let (router, _) = test_support::app(test_support::config(&[("INDEX_HTML_PATH", path.to_str().unwrap())]));
+
+        let response = test_support::send(&router, test_support::get("/", &[])).await;
+
+        assert_eq!(response.status, 200);
+        assert!(String::from_utf8_lossy(&response.body).contains("hello"));
+    }
+}
@@ -1,21 +1,41 @@
-use chrono::{Duration, Utc};
-use jsonwebtoken::{encode, EncodingKey, Header};
-use once_cell::sync::Lazy;
+use axum::http::{header, HeaderMap};
+use chrono::Duration;
+use jsonwebtoken::{decode, encode, errors::ErrorKind, Algorithm, DecodingKey, EncodingKey, Header, Validation};
 use serde::{Deserialize, Serialize};
+use crate::clock::SharedClock;
 use crate::error::{AppError, AppResult};
 
-// JWT environment variables
-static JWT_SECRET: Lazy<String> = Lazy::new(|| {
-    std::env::var("JWT_SECRET").expect("Missing JWT_SECRET environment variable")
-});
+// Signing algorithm of issued tokens, the only one accepted on verification
+pub const JWT_ALGORITHM: Algorithm = Algorithm::HS256;
 
-static JWT_ISSUER: Lazy<String> = Lazy::new(|| {
-    std::env::var("JWT_ISSUER").unwrap_or_else(|_| "passkeymesh-gateway".to_string())
-});
+// Token type of every issued token
+pub const TOKEN_TYPE: &str = "access";
 
-static JWT_AUDIENCE: Lazy<String> = Lazy::new(|| {
-    std::env::var("JWT_AUDIENCE").unwrap_or_else(|_| "backend-service".to_string())
-});
+// Authentication method reference recorded by a step-up assertion
+pub const AMR_WEBAUTHN: &str = "webauthn";
+
+// JWT configuration
+#[derive(Debug, Clone)]
+pub struct JwtConfig {
+    pub secret: String,
+    pub issuer: String,
+    // Issuers whose tokens are accepted; defaults to our own issuer
+    pub accepted_issuers: Vec<String>,
+    pub audience: String,
+    // Allowed clock skew when validating exp/nbf. A larger leeway tolerates unsynced
+    // clocks but keeps expired tokens usable for longer.
+    pub leeway_secs: u64,
+    // Backdate iat/nbf of issued tokens so validators with no leeway accept them
+    // even when our clock runs slightly ahead; exp still counts from real now
+    pub issue_backdate_secs: u64,
+    // Scopes granted to user tokens issued at login
+    pub user_scopes: Vec<String>,
+    // Bind login tokens to the User-Agent of the WebAuthn ceremony. Tokens then stop
+    // working when the browser's User-Agent changes (e.g. after an update).
+    pub bind_to_client: bool,
+    // Time source for issued claims and step-up recency
+    pub clock: SharedClock,
+}
 
 // JWT claims structure
 #[derive(Debug, Serialize, Deserialize)]
@@ -24,24 +44,268 @@ pub struct Claims {
     pub name: String, // Username
     pub exp: usize,   // Expiration time
     pub iat: usize,   // Issued at
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub nbf: Option<usize>, // Not before
     pub iss: String,  // Issuer
     pub aud: String,  // Audience
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub amr: Vec<String>, // Authentication methods, set on step-up tokens
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub auth_time: Option<usize>, // When the step-up assertion was verified
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub typ: Option<String>, // Token type
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub scope: Option<String>, // Space-delimited scopes
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub cnf: Option<ClientBinding>, // Client the token is bound to
+    #[serde(flatten)]
+    pub extra: serde_json::Map<String, serde_json::Value>, // Claims left to deployment validators, e.g. roles
+}
+
+// Confirmation claim tying a token to the client that performed the ceremony
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ClientBinding {
+    #[serde(rename = "ua#S256")]
+    pub user_agent_sha256: String,
+}
+
+impl ClientBinding {
+    // Binding for the User-Agent of a request
+    pub fn from_headers(headers: &HeaderMap) -> Self {
+        let user_agent = headers.get(header::USER_AGENT).map_or(&b""[..], |value| value.as_bytes());
+        let digest = openssl::sha::sha256(user_agent).iter().map(|b| format!("{:02x}", b)).collect();
+        Self { user_agent_sha256: digest }
+    }
 }
 
-// Issue JWT token for user
-pub fn issue_jwt(user_id: &str, username: &str) -> AppResult<String> {
-    let now = Utc::now();
+// Issue JWT token for user, granting the given scopes and optionally bound to a client
+pub fn issue_jwt(config: &JwtConfig, user_id: &str, username: &str, scopes: &[String], cnf: Option<ClientBinding>) -> AppResult<String> {
+    issue(config, user_id, username, scopes, cnf, Duration::hours(24), None)
+}
+
+// Issue a short-lived elevated token after a fresh passkey assertion; it expires
+// when the assertion stops counting as recent
+pub fn issue_step_up_jwt(config: &JwtConfig, user_id: &str, username: &str, cnf: Option<ClientBinding>, max_age: std::time::Duration) -> AppResult<String> {
+    let max_age = Duration::from_std(max_age).map_err(|e| AppError::Internal(format!("Invalid step-up max age: {}", e)))?;
+    issue(config, user_id, username, &config.user_scopes, cnf, max_age, Some(config.clock.utc().timestamp() as usize))
+}
+
+fn issue(
+    config: &JwtConfig,
+    user_id: &str,
+    username: &str,
+    scopes: &[String],
+    cnf: Option<ClientBinding>,
+    lifetime: Duration,
+    auth_time: Option<usize>,
+) -> AppResult<String> {
+    let now = config.clock.utc();
+    let issued = (now - Duration::seconds(config.issue_backdate_secs as i64)).timestamp() as usize;
 
     encode(
-        &Header::default(),
+        &Header::new(JWT_ALGORITHM),
         &Claims {
             sub: user_id.to_string(),
             name: username.to_string(),
-            exp: (now + Duration::hours(24)).timestamp() as usize,
-            iat: now.timestamp() as usize,
-            iss: JWT_ISSUER.to_string(),
-            aud: JWT_AUDIENCE.to_string(),
+            exp: (now + lifetime).timestamp() as usize,
+            iat: issued,
+            nbf: (config.issue_backdate_secs > 0).then_some(issued),
+            iss: config.issuer.clone(),
+            aud: config.audience.clone(),
+            amr: auth_time.map(|_| vec![AMR_WEBAUTHN.to_string()]).unwrap_or_default(),
+            auth_time,
+            typ: Some(TOKEN_TYPE.to_string()),
+            scope: (!scopes.is_empty()).then(|| scopes.join(" ")),
+            cnf,
+            extra: Default::default(),
         },
-        &EncodingKey::from_secret(JWT_SECRET.as_bytes()),
+        &EncodingKey::from_secret(config.secret.as_bytes()),
     ).map_err(AppError::Jwt)
 }
+
+// Verify JWT token and return its claims
+pub fn verify_jwt(config: &JwtConfig, token: &str) -> AppResult<Claims> {
+    let mut validation = Validation::new(JWT_ALGORITHM);
+    validation.leeway = config.leeway_secs;
+    validation.set_issuer(&config.accepted_issuers);
+    validation.set_audience(&[config.audience.as_str()]);
+    // exp is still required, but checked below against our clock
+    validation.validate_exp = false;
+
+    let claims = decode::<Claims>(token, &DecodingKey::from_secret(config.secret.as_bytes()), &validation)
+        .map(|data| data.claims)
+        .map_err(AppError::Jwt)?;
+
+    let now = config.clock.utc().timestamp().max(0) as u64;
+    if (claims.exp as u64) < now.saturating_sub(config.leeway_secs) {
+        return Err(AppError::Jwt(ErrorKind::ExpiredSignature.into()));
+    }
+    Ok(claims)
+}
+
+// Reject a bound token presented by a different client
+pub fn verify_client(claims: &Claims, headers: &HeaderMap) -> AppResult<()> {
+    match &claims.cnf {
+        Some(binding) if *binding != ClientBinding::from_headers(headers) => {
+            tracing::warn!(target: "audit", "Rejected token for {} from a different client", claims.sub);
+            Err(AppError::Authentication("Token is bound to a different client".to_string()))
+        },
+        _ => Ok(()),
+    }
+}
+
+// Require a passkey assertion within max_age, as recorded by a step-up token
+pub fn require_step_up(config: &JwtConfig, claims: &Claims, max_age: std::time::Duration) -> AppResult<()> {
+    if !claims.amr.iter().any(|method| method == AMR_WEBAUTHN) {
+        return Err(AppError::Authentication("Step-up authentication required".to_string()));
+    }
+
+    let now = config.clock.utc().timestamp() as usize;
+    let recent = claims.auth_time.is_some_and(|auth_time| now.saturating_sub(auth_time) as u64 <= max_age.as_secs());
+    if !recent {
+        return Err(AppError::Authentication("Step-up authentication has expired".to_string()));
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::Utc;
+
+    use super::*;
+    use crate::test_support;
+
+    fn jwt_config(vars: &[(&str, &str)]) -> JwtConfig {
+        test_support::config(vars).jwt
+    }
+
+    // Token for our own issuer and audience that expired the given number of seconds ago
+    fn expired_token(config: &JwtConfig, expired_secs: i64) -> String {
+        let exp = (Utc::now() - Duration::seconds(expired_secs)).timestamp() as usize;
+        let claims = Claims {
+            sub: "user-1".to_string(),
+            name: "alice".to_string(),
+            exp,
+            iat: exp - 3600,
+            nbf: None,
+            iss: config.issuer.clone(),
+            aud: config.audience.clone(),
+            amr: Vec::new(),
+            auth_time: None,
+            typ: Some(TOKEN_TYPE.to_string()),
+            scope: None,
+            cnf: None,
+            extra: Default::default(),
+        };
+        encode(&Header::new(JWT_ALGORITHM), &claims, &EncodingKey::from_secret(config.secret.as_bytes())).unwrap()
+    }
+
+    #[test]
+    fn token_just_past_exp_verifies_within_leeway() {
+        let config = jwt_config(&[("JWT_LEEWAY_SECS", "30")]);
+        assert!(verify_jwt(&config, &expired_token(&config, 10)).is_ok());
+    }
+
+    #[test]
+    fn token_well_past_exp_is_rejected() {
+        let config = jwt_config(&[("JWT_LEEWAY_SECS", "30")]);
+        assert!(verify_jwt(&config, &expired_token(&config, 120)).is_err());
+    }
+
+    #[test]
+    fn zero_leeway_rejects_any_expired_token() {
+        let config = jwt_config(&[("JWT_LEEWAY_SECS", "0")]);
+        assert!(verify_jwt(&config, &expired_token(&config, 10)).is_err());
+    }
+
+    #[test]
+    fn issued_tokens_carry_the_access_type_and_scopes() {
+        let config = jwt_config(&[("JWT_SCOPES", "logs:read, users:read")]);
+
+        let login = verify_jwt(&config, &issue_jwt(&config, "user-1", "alice", &config.user_scopes, None).unwrap()).unwrap();
+        let step_up = issue_step_up_jwt(&config, "user-1", "alice", None, std::time::Duration::from_secs(60)).unwrap();
+        let unscoped = verify_jwt(&config, &issue_jwt(&config, "user-1", "alice", &[], None).unwrap()).unwrap();
+
+        assert_eq!(login.typ.as_deref(), Some(TOKEN_TYPE));
+        assert_eq!(login.scope.as_deref(), Some("logs:read users:read"));
+        assert_eq!(verify_jwt(&config, &step_up).unwrap().scope, login.scope);
+        assert_eq!(unscoped.typ.as_deref(), Some(TOKEN_TYPE));
+        assert_eq!(unscoped.scope, None);
+    }
+
+    #[test]
+    fn issued_at_is_backdated_while_expiry_counts_from_now() {
+        let config = jwt_config(&[("JWT_ISSUE_BACKDATE_SECS", "5")]);
+        let before = Utc::now().timestamp() as usize;
+
+        let claims = verify_jwt(&config, &issue_jwt(&config, "user-1", "alice", &[], None).unwrap()).unwrap();
+
+        let after = Utc::now().timestamp() as usize;
+        assert!((before - 5..=after - 5).contains(&claims.iat), "iat {} not in {}..={}", claims.iat, before - 5, after - 5);
+        assert_eq!(claims.nbf, Some(claims.iat));
+        assert!((before + 24 * 3600..=after + 24 * 3600).contains(&claims.exp));
+    }
+
+    #[test]
+    fn issued_at_is_now_without_backdate() {
+        let config = jwt_config(&[]);
+        let before = Utc::now().timestamp() as usize;
+
+        let claims = verify_jwt(&config, &issue_jwt(&config, "user-1", "alice", &[], None).unwrap()).unwrap();
+
+        assert!(claims.iat >= before);
+        assert_eq!(claims.nbf, None);
+    }
+
+    // Token issued under the given JWT_ISSUER
+    fn token_from(issuer: &str) -> String {
+        issue_jwt(&jwt_config(&[("JWT_ISSUER", issuer)]), "user-1", "alice", &[], None).unwrap()
+    }
+
+    #[test]
+    fn only_the_configured_issuer_is_accepted_by_default() {
+        let config = jwt_config(&[("JWT_ISSUER", "tenant-a")]);
+        assert_eq!(config.accepted_issuers, ["tenant-a"]);
+
+        assert!(verify_jwt(&config, &token_from("tenant-a")).is_ok());
+        assert!(verify_jwt(&config, &token_from("tenant-b")).is_err());
+    }
+
+    #[test]
+    fn accepted_issuers_list_replaces_the_default() {
+        let config = jwt_config(&[("JWT_ISSUER", "tenant-a"), ("JWT_ACCEPTED_ISSUERS", "tenant-b, tenant-c")]);
+
+        assert_eq!(verify_jwt(&config, &token_from("tenant-b")).unwrap().iss, "tenant-b");
+        assert!(verify_jwt(&config, &token_from("tenant-c")).is_ok());
+        assert!(verify_jwt(&config, &token_from("tenant-a")).is_err());
+        assert!(verify_jwt(&config, &token_from("unknown")).is_err());
+    }
+
+    #[test]
+    fn step_up_is_required_and_must_be_recent() {
+        let config = jwt_config(&[]);
+        let max_age = std::time::Duration::from_secs(300);
+        let login = verify_jwt(&config, &issue_jwt(&config, "user-1", "alice", &[], None).unwrap()).unwrap();
+        let mut step_up = verify_jwt(&config, &issue_step_up_jwt(&config, "user-1", "alice", None, max_age).unwrap()).unwrap();
+
+        assert!(require_step_up(&config, &login, max_age).is_err());
+        assert!(require_step_up(&config, &step_up, max_age).is_ok());
+
+        step_up.auth_time = step_up.auth_time.map(|auth_time| auth_time - 301);
+        let err = require_step_up(&config, &step_up, max_age).unwrap_err();
+        assert!(err.to_string().contains("expired"), "{}", err);
+    }
+
+    #[test]
+    fn token_expires_when_the_clock_passes_exp_and_leeway() {
+        let clock = crate::clock::TestClock::new();
+        let config = test_support::config(&[("JWT_LEEWAY_SECS", "30")]).with_clock(clock.clone()).jwt;
+        let token = issue_jwt(&config, "user-1", "alice", &[], None).unwrap();
+
+        // Tokens live 24 hours
+        clock.advance(std::time::Duration::from_secs(24 * 3600 + 30));
+        assert!(verify_jwt(&config, &token).is_ok());
+        clock.advance(std::time::Duration::from_secs(1));
+        assert!(matches!(verify_jwt(&config, &token), Err(AppError::Jwt(e)) if *e.kind() == ErrorKind::ExpiredSignature));
+    }
+}
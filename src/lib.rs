@@ -0,0 +1,41 @@
+// User and challenge stores use std::sync::Mutex, taken and released without awaiting
+// in between; a guard held across an await would block a runtime worker on contention
+#![deny(clippy::await_holding_lock)]
+
+pub mod webauthn;
+pub mod admin;
+pub mod api_key;
+pub mod app;
+pub mod assets;
+pub mod auth;
+pub mod batch;
+pub mod body_log;
+pub mod certgen;
+pub mod challenge;
+pub mod claims;
+pub mod client_ip;
+pub mod clock;
+pub mod config;
+pub mod deadline;
+pub mod discovery;
+pub mod error;
+pub mod host_policy;
+pub mod jwt;
+pub mod tls;
+pub mod http_client;
+pub mod http_parser;
+pub mod api_response;
+pub mod handler;
+pub mod index;
+pub mod pagination;
+pub mod schema;
+pub mod cache;
+pub mod service;
+pub mod stream;
+pub mod limiter;
+pub mod maintenance;
+pub mod metrics;
+pub mod version;
+
+#[cfg(test)]
+mod test_support;
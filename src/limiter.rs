@@ -0,0 +1,66 @@
+use std::{collections::HashMap, sync::Arc, time::Duration};
+use tokio::sync::{OwnedSemaphorePermit, Semaphore};
+
+use crate::config::Config;
+use crate::deadline::Deadline;
+use crate::error::{AppError, AppResult};
+use crate::service::ServiceType;
+
+// Bounds concurrent backend handshakes, globally and per service
+pub struct BackendLimiter {
+    global: Arc<Semaphore>,
+    global_limit: usize,
+    per_service: HashMap<ServiceType, Arc<Semaphore>>,
+    queue_timeout: Duration,
+}
+
+// Held for the duration of a backend call
+pub struct BackendPermit {
+    _global: OwnedSemaphorePermit,
+    _service: Option<OwnedSemaphorePermit>,
+}
+
+impl BackendLimiter {
+    pub fn new(config: &Config) -> Self {
+        let per_service = config.max_concurrent_backend_per_service
+            .map(|limit| ServiceType::ALL.iter()
+                .map(|service| (*service, Arc::new(Semaphore::new(limit))))
+                .collect())
+            .unwrap_or_default();
+
+        Self {
+            global: Arc::new(Semaphore::new(config.max_concurrent_backend)),
+            global_limit: config.max_concurrent_backend,
+            per_service,
+            queue_timeout: config.backend_queue_timeout,
+        }
+    }
+
+    // Wait for a permit, giving up at the queue timeout or request deadline
+    pub async fn acquire(&self, service: ServiceType, deadline: Option<&Deadline>) -> AppResult<BackendPermit> {
+        let wait = deadline.and_then(Deadline::remaining)
+            .map_or(self.queue_timeout, |remaining| remaining.min(self.queue_timeout));
+
+        let permits = async {
+            let service_permit = match self.per_service.get(&service) {
+                Some(semaphore) => Some(Arc::clone(semaphore).acquire_owned().await),
+                None => None,
+            }.transpose();
+            let global_permit = Arc::clone(&self.global).acquire_owned().await;
+            (global_permit, service_permit)
+        };
+
+        match tokio::time::timeout(wait, permits).await {
+            Ok((Ok(global), Ok(service_permit))) => Ok(BackendPermit { _global: global, _service: service_permit }),
+            Ok(_) => Err(AppError::Internal("Backend limiter closed".to_string())),
+            Err(_) => Err(AppError::ServiceUnavailable(format!(
+                "Too many concurrent backend requests for {} service", service.name()
+            ))),
+        }
+    }
+
+    // Number of backend calls currently in flight
+    pub fn in_flight(&self) -> usize {
+        self.global_limit - self.global.available_permits()
+    }
+}
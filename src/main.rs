@@ -1,19 +1,10 @@
-mod webauthn;
-mod error;
-mod jwt;
-mod tls;
-mod http_client;
-mod api_response;
-mod handler;
-
-use axum::{routing::get, Router, Extension, response::Html, http::StatusCode};
-use std::{sync::Arc, net::SocketAddr};
-use tokio::fs;
-use tower_http::{cors::CorsLayer, trace::TraceLayer};
+use std::{net::SocketAddr, sync::Arc, time::Duration};
 use tracing_subscriber::{layer::SubscriberExt, util::SubscriberInitExt};
-use webauthn_rs::prelude::*;
-use url::Url;
 use dotenv::dotenv;
+use passkeymesh_gateway::{app, cache, certgen, config, discovery, service, tls, webauthn};
+
+// Upper bound on the startup TLS self-test handshake
+const SELFTEST_TIMEOUT: Duration = Duration::from_secs(10);
 
 #[tokio::main]
 async fn main() -> Result<(), Box<dyn std::error::Error>> {
@@ -26,64 +17,90 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
         .with(tracing_subscriber::fmt::layer())
         .init();
 
+    // `--gen-certs [dir]` writes test certificates and exits
+    let args: Vec<String> = std::env::args().collect();
+    if let Some(pos) = args.iter().position(|arg| arg == "--gen-certs") {
+        let dir = args.get(pos + 1).map(String::as_str).unwrap_or("certs");
+        let tls = tls::TlsConfig::from_vars(&|key| std::env::var(key).ok())?;
+        certgen::generate(&tls.openssl, std::path::Path::new(dir))?;
+        return Ok(());
+    }
+
     tracing::info!("Starting PasskeyMesh Gateway...");
 
-    // Configure WebAuthn
-    let port = std::env::var("PORT").unwrap_or_else(|_| "3000".to_string())
-        .parse::<u16>().unwrap_or(3001);
-
-    let webauthn = Arc::new(
-        WebauthnBuilder::new("localhost", &Url::parse(&format!("http://localhost:{}", port)).unwrap())
-            .expect("Invalid configuration")
-            .rp_name("PasskeyMesh Gateway")
-            .build()
-            .expect("Invalid configuration")
-    );
-
-    // Initialize PQC mTLS HTTP client
-    let _ = http_client::create_pqc_client()?;
-
-    // Configure CORS
-    let cors = CorsLayer::new()
-        .allow_origin([format!("http://localhost:{}", port).parse().unwrap()])
-        .allow_methods(vec![
-            axum::http::Method::GET,
-            axum::http::Method::POST,
-            axum::http::Method::OPTIONS,
-        ])
-        .allow_headers(vec![
-            axum::http::header::AUTHORIZATION,
-            axum::http::header::CONTENT_TYPE,
-        ])
-        .allow_credentials(true);
-
-    // Create routes
-    let app = Router::new()
-        .route("/", get(serve_index))
-        .nest("/auth", webauthn::routes(Arc::clone(&webauthn)))
-        .route("/api/auth/verify", get(handler::handle_request).post(handler::handle_request))
-        .layer(Extension(Arc::clone(&webauthn)))
-        .layer(cors)
-        .layer(TraceLayer::new_for_http());
+    // Load configuration
+    let config = Arc::new(config::Config::from_env()?);
+    cache::init(config.response_cache_size, std::sync::Arc::clone(&config.clock));
+    if config.tls.insecure_skip_verify {
+        tracing::warn!("INSECURE_SKIP_VERIFY is enabled: proxy certificates will NOT be verified; never use this outside development");
+    }
+
+    let state = app::AppState::new(&config);
+    if state.maintenance.is_enabled() {
+        tracing::warn!("Starting in maintenance mode: /api requests are rejected with 503");
+    }
+
+    // Track backend replica health in the background when enabled
+    if let Some(interval) = config.health_check_interval {
+        discovery::spawn_health_checks(Arc::clone(&state.registry), Arc::clone(&config), interval);
+    }
+
+    // Expired challenge states are swept in the background
+    webauthn::spawn_state_sweeper(state.challenge_states.clone(), config.state_sweep_interval);
+
+    // All backend traffic goes through the OpenSSL transport in http_client
+    tracing::info!("Using OpenSSL: {}, cert: {}, key: {}, CA: {}",
+                   config.tls.openssl, config.tls.cert, config.tls.key, config.tls.ca);
+
+    // Show operators up front whether PQC actually negotiates with the default backend
+    if config.startup_tls_selftest {
+        if let Err(e) = tls_selftest(&config) {
+            if config.strict_selftest {
+                return Err(format!("TLS self-test failed: {}", e).into());
+            }
+            tracing::warn!("TLS self-test failed: {}", e);
+        }
+    }
+
+    let app = app::router(Arc::clone(&config), &state);
+
+    // Hold off accepting traffic until the backend can serve it
+    if let Some(timeout) = config.wait_for_backend {
+        if !discovery::wait_for_backend(Arc::clone(&config), timeout).await {
+            tracing::warn!("Backend not ready after {}s; starting anyway", timeout.as_secs());
+        }
+    }
 
     // Start server
-    let addr = SocketAddr::from(([0, 0, 0, 0], port));
+    let addr = SocketAddr::from(([0, 0, 0, 0], config.port));
     tracing::info!("Server listening on {}", addr);
 
     axum::Server::bind(&addr)
-        .serve(app.into_make_service())
+        .serve(app.into_make_service_with_connect_info::<SocketAddr>())
         .await?;
 
     Ok(())
 }
 
-// Serve index.html page
-async fn serve_index() -> Result<Html<String>, (StatusCode, String)> {
-    match fs::read_to_string("index.html").await {
-        Ok(content) => Ok(Html(content)),
-        Err(err) => {
-            tracing::error!("Failed to read index.html: {}", err);
-            Err((StatusCode::INTERNAL_SERVER_ERROR, format!("Failed to read index.html: {}", err)))
-        }
+// Handshake once with the default service's backend and log the negotiated parameters.
+// Fails when the handshake does not succeed or falls back to classical key exchange.
+fn tls_selftest(config: &config::Config) -> Result<(), String> {
+    let service = config.default_service;
+    let target = config.service_targets[&service].first().ok_or("no backend configured")?;
+    if target.scheme != service::Scheme::Https {
+        tracing::info!("TLS self-test skipped: {} service backend is plain HTTP", service);
+        return Ok(());
     }
+
+    let info = tls::get_tls_info(config.tls_for(service), &target.host, target.port, Some(SELFTEST_TIMEOUT), false)
+        .map_err(|e| e.to_string())?;
+    tracing::info!("TLS self-test against {}:{}: {}", target.host, target.port, tls::summarize(&info));
+
+    if info.connection != "success" {
+        return Err(info.connection);
+    }
+    if !info.pqc_enabled {
+        return Err("classical key exchange negotiated".to_string());
+    }
+    Ok(())
 }
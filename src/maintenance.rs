@@ -0,0 +1,139 @@
+use std::{sync::{atomic::{AtomicBool, Ordering}, Arc}, time::Duration};
+use axum::{
+    http::{HeaderMap, Request},
+    middleware::Next,
+    response::{IntoResponse, Response},
+    Extension, Json,
+};
+use serde::Deserialize;
+
+use crate::admin;
+use crate::api_response::ResponseStatus;
+use crate::config::Config;
+use crate::error::{AppError, AppResult};
+
+// Maintenance switch, set from MAINTENANCE_MODE and toggled at runtime by admins
+pub struct Maintenance {
+    enabled: AtomicBool,
+    retry_after: Duration,
+    // Also take the WebAuthn routes down, not just the proxied API
+    include_auth: bool,
+}
+
+impl Maintenance {
+    pub fn new(config: &Config) -> Self {
+        Self {
+            enabled: AtomicBool::new(config.maintenance_mode),
+            retry_after: config.maintenance_retry_after,
+            include_auth: config.maintenance_includes_auth,
+        }
+    }
+
+    pub fn is_enabled(&self) -> bool {
+        self.enabled.load(Ordering::Relaxed)
+    }
+
+    // Whether a request path is turned away while in maintenance
+    fn covers(&self, path: &str) -> bool {
+        let under = |prefix: &str| path == prefix || path.starts_with(&format!("{}/", prefix));
+        under("/api") || (self.include_auth && under("/auth"))
+    }
+}
+
+#[derive(Debug, Deserialize)]
+pub struct MaintenanceRequest {
+    pub enabled: bool,
+}
+
+// Reject proxied requests with 503 while in maintenance; health and admin routes stay up
+pub async fn guard<B>(Extension(maintenance): Extension<Arc<Maintenance>>, request: Request<B>, next: Next<B>) -> Response {
+    if maintenance.is_enabled() && maintenance.covers(request.uri().path()) {
+        return AppError::Maintenance { retry_after: maintenance.retry_after }.into_response();
+    }
+    next.run(request).await
+}
+
+// Liveness probe, answering even in maintenance mode
+pub async fn healthz(Extension(maintenance): Extension<Arc<Maintenance>>) -> Json<serde_json::Value> {
+    Json(serde_json::json!({"status": "ok", "maintenance": maintenance.is_enabled()}))
+}
+
+// Turn maintenance mode on or off (admin only)
+pub async fn set_maintenance(
+    Extension(config): Extension<Arc<Config>>,
+    Extension(maintenance): Extension<Arc<Maintenance>>,
+    headers: HeaderMap,
+    Json(req): Json<MaintenanceRequest>,
+) -> AppResult<Json<serde_json::Value>> {
+    admin::require_admin(&config, &headers)?;
+
+    maintenance.enabled.store(req.enabled, Ordering::Relaxed);
+    tracing::warn!(target: "audit", "Maintenance mode {}", if req.enabled { "enabled" } else { "disabled" });
+
+    Ok(Json(serde_json::json!({"status": ResponseStatus::Success, "maintenance": req.enabled})))
+}
+
+#[cfg(test)]
+mod tests {
+    use axum::{http::Method, Router};
+    use serde_json::json;
+
+    use crate::test_support::{self, MockBackend, TestResponse};
+
+    async fn register_start(router: &Router) -> TestResponse {
+        test_support::send(router, test_support::json_request(Method::POST, "/auth/register", &[], &json!({"username": "alice"}))).await
+    }
+
+    async fn set_maintenance(router: &Router, token: &str, enabled: bool) -> TestResponse {
+        test_support::send(router, test_support::json_request(
+            Method::PUT, "/admin/maintenance", &[("X-Admin-Token", token)], &json!({"enabled": enabled}),
+        )).await
+    }
+
+    #[tokio::test]
+    async fn api_is_unavailable_in_maintenance_while_healthz_stays_up() {
+        let logs = MockBackend::json(&json!({"logs": []}));
+        let (router, _) = test_support::app(test_support::config(&[
+            ("LOG_SERVICE_URL", &logs.url()),
+            ("MAINTENANCE_MODE", "true"),
+            ("MAINTENANCE_RETRY_AFTER_SECS", "120"),
+        ]));
+
+        for uri in ["/api/service/log", "/api/auth/verify"] {
+            let response = test_support::send(&router, test_support::get(uri, &[])).await;
+            assert_eq!(response.status, 503, "{}", uri);
+            assert_eq!(response.headers["retry-after"], "120");
+            assert_eq!(response.json()["code"], "MAINTENANCE");
+        }
+        assert!(logs.requests().is_empty());
+
+        let health = test_support::send(&router, test_support::get("/healthz", &[])).await;
+        assert_eq!(health.status, 200);
+        assert_eq!(health.json(), json!({"status": "ok", "maintenance": true}));
+        assert_eq!(register_start(&router).await.status, 200);
+    }
+
+    #[tokio::test]
+    async fn auth_routes_go_down_only_when_configured() {
+        let (router, _) = test_support::app(test_support::config(&[("MAINTENANCE_MODE", "true"), ("MAINTENANCE_INCLUDES_AUTH", "true")]));
+
+        assert_eq!(register_start(&router).await.status, 503);
+        assert_eq!(test_support::send(&router, test_support::get("/healthz", &[])).await.status, 200);
+    }
+
+    #[tokio::test]
+    async fn admin_toggles_maintenance_at_runtime() {
+        let logs = MockBackend::json(&json!({"logs": []}));
+        let (router, _) = test_support::app(test_support::config(&[("LOG_SERVICE_URL", &logs.url()), ("ADMIN_TOKEN", "admin-secret")]));
+        let call_api = || test_support::send(&router, test_support::get("/api/service/log", &[]));
+
+        assert_eq!(set_maintenance(&router, "wrong-secret", true).await.status, 401);
+        assert_eq!(call_api().await.status, 200);
+
+        assert_eq!(set_maintenance(&router, "admin-secret", true).await.json()["maintenance"], true);
+        assert_eq!(call_api().await.status, 503);
+
+        set_maintenance(&router, "admin-secret", false).await;
+        assert_eq!(call_api().await.status, 200);
+    }
+}
@@ -0,0 +1,125 @@
+use std::{fmt::Write, sync::{atomic::{AtomicU64, Ordering}, Arc}};
+use axum::{http::header, response::IntoResponse, Extension};
+
+use crate::limiter::BackendLimiter;
+use crate::webauthn::{ChallengeStates, UserStore};
+
+// Failed finish_register verifications
+pub static FAILED_REGISTRATIONS: AtomicU64 = AtomicU64::new(0);
+
+// Challenge states removed by the sweeper after expiring
+pub static SWEPT_CHALLENGES: AtomicU64 = AtomicU64::new(0);
+
+// Append a gauge in Prometheus text format
+fn write_gauge(out: &mut String, name: &str, help: &str, value: usize) {
+    let _ = writeln!(out, "# HELP {} {}", name, help);
+    let _ = writeln!(out, "# TYPE {} gauge", name);
+    let _ = writeln!(out, "{} {}", name, value);
+}
+
+// Append a counter in Prometheus text format
+fn write_counter(out: &mut String, name: &str, help: &str, value: u64) {
+    let _ = writeln!(out, "# HELP {} {}", name, help);
+    let _ = writeln!(out, "# TYPE {} counter", name);
+    let _ = writeln!(out, "{} {}", name, value);
+}
+
+// Serve Prometheus metrics
+pub async fn metrics_handler(
+    Extension(limiter): Extension<Arc<BackendLimiter>>,
+    Extension(user_store): Extension<UserStore>,
+    Extension(challenge_states): Extension<ChallengeStates>,
+) -> impl IntoResponse {
+    // Derive user and credential counts from the store so they stay accurate
+    let (users, credentials) = user_store.lock()
+        .map(|store| (store.len(), store.values().map(|u| u.credentials.len()).sum::<usize>()))
+        .unwrap_or_default();
+    let (pending_registrations, pending_authentications) = challenge_states.pending();
+
+    let mut out = String::new();
+    write_gauge(&mut out, "passkeymesh_backend_in_flight", "Backend requests currently in flight", limiter.in_flight());
+    write_gauge(&mut out, "passkeymesh_users_total", "Registered users", users);
+    write_gauge(&mut out, "passkeymesh_credentials_total", "Registered credentials", credentials);
+    write_gauge(&mut out, "passkeymesh_pending_registrations", "Outstanding registration challenges", pending_registrations);
+    write_gauge(&mut out, "passkeymesh_pending_authentications", "Outstanding authentication challenges", pending_authentications);
+    write_counter(
+        &mut out,
+        "passkeymesh_registration_failures_total",
+        "Failed registration verifications",
+        FAILED_REGISTRATIONS.load(Ordering::Relaxed),
+    );
+    write_counter(
+        &mut out,
+        "passkeymesh_challenges_swept_total",
+        "Expired challenge states removed by the sweeper",
+        SWEPT_CHALLENGES.load(Ordering::Relaxed),
+    );
+
+    ([(header::CONTENT_TYPE, "text/plain; version=0.0.4")], out)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+    use axum::{http::Method, Router};
+    use serde_json::json;
+
+    use crate::test_support::{self, TestResponse};
+
+    // Value of an unlabelled metric in the exposition output
+    fn metric(response: &TestResponse, name: &str) -> u64 {
+        let body = String::from_utf8_lossy(&response.body);
+        body.lines()
+            .find_map(|line| line.strip_prefix(name)?.strip_prefix(' '))
+            .and_then(|value| value.parse().ok())
+            .unwrap_or_else(|| panic!("{} missing from:\n{}", name, body))
+    }
+
+    async fn scrape(router: &Router) -> TestResponse {
+        test_support::send(router, test_support::get("/metrics", &[])).await
+    }
+
+    #[tokio::test]
+    async fn gauges_follow_registered_users_and_credentials() {
+        let (router, _) = test_support::app(test_support::config(&[("ADMIN_TOKEN", "admin-secret")]));
+        test_support::register(&router, "alice").await;
+
+        let after_register = scrape(&router).await;
+        assert_eq!(metric(&after_register, "passkeymesh_users_total"), 1);
+        assert_eq!(metric(&after_register, "passkeymesh_credentials_total"), 1);
+
+        // A second credential for alice, registered on another gateway and imported
+        let passkey = test_support::exported_passkey().await;
+        let import = test_support::send(&router, test_support::json_request(
+            Method::POST, "/admin/credentials/import", &[("X-Admin-Token", "admin-secret")],
+            &json!([{"username": "alice", "passkey": passkey}]),
+        )).await;
+        assert_eq!(import.json()["imported"], 1);
+
+        let after_import = scrape(&router).await;
+        assert_eq!(metric(&after_import, "passkeymesh_users_total"), 1);
+        assert_eq!(metric(&after_import, "passkeymesh_credentials_total"), 2);
+    }
+
+    #[tokio::test]
+    async fn sweeper_removes_expired_states_and_counts_them() {
+        // With no TTL every state is expired by the first sweep
+        let (router, state) = test_support::app(test_support::config(&[("CHALLENGE_TTL_SECS", "0")]));
+        let start = test_support::json_request(Method::POST, "/auth/register", &[], &json!({"username": "alice"}));
+        assert_eq!(test_support::send(&router, start).await.status, 200);
+        let before = scrape(&router).await;
+        assert_eq!(metric(&before, "passkeymesh_pending_registrations"), 1);
+
+        crate::webauthn::spawn_state_sweeper(state.challenge_states.clone(), Duration::from_millis(10));
+        tokio::time::timeout(Duration::from_secs(5), async {
+            while state.challenge_states.pending() != (0, 0) {
+                tokio::time::sleep(Duration::from_millis(5)).await;
+            }
+        }).await.expect("swept in time");
+
+        let after = scrape(&router).await;
+        assert_eq!(metric(&after, "passkeymesh_pending_registrations"), 0);
+        // Other tests may sweep concurrently, so the counter grows by at least this one
+        assert!(metric(&after, "passkeymesh_challenges_swept_total") > metric(&before, "passkeymesh_challenges_swept_total"));
+    }
+}
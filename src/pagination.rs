@@ -0,0 +1,129 @@
+use chrono::{DateTime, TimeZone, Utc};
+use serde::{Deserialize, Serialize};
+use crate::error::{AppError, AppResult};
+
+const DEFAULT_LIMIT: usize = 20;
+const MAX_LIMIT: usize = 100;
+
+// Pagination query parameters
+#[derive(Debug, Default, Deserialize)]
+pub struct PageQuery {
+    pub limit: Option<usize>,
+    pub cursor: Option<String>,
+    pub since: Option<String>,
+}
+
+// Page of results
+#[derive(Debug, Serialize)]
+pub struct Page<T> {
+    pub items: Vec<T>,
+    pub next_cursor: Option<String>,
+}
+
+// Sort key: creation time with a unique ID as tie-breaker
+type SortKey = (DateTime<Utc>, String);
+
+// Encode sort key as an opaque cursor
+fn encode_cursor((created_at, id): &SortKey) -> String {
+    format!("{}:{}", created_at.timestamp_micros(), id)
+        .bytes()
+        .map(|b| format!("{:02x}", b))
+        .collect()
+}
+
+// Decode opaque cursor into sort key
+fn decode_cursor(cursor: &str) -> AppResult<SortKey> {
+    let invalid = || AppError::BadRequest("Invalid cursor".to_string());
+
+    if cursor.len() % 2 != 0 { return Err(invalid()); }
+    let bytes = (0..cursor.len()).step_by(2)
+        .map(|i| cursor.get(i..i + 2).and_then(|hex| u8::from_str_radix(hex, 16).ok()))
+        .collect::<Option<Vec<u8>>>()
+        .ok_or_else(invalid)?;
+
+    let decoded = String::from_utf8(bytes).map_err(|_| invalid())?;
+    let (micros, id) = decoded.split_once(':').ok_or_else(invalid)?;
+    let created_at = micros.parse::<i64>().ok()
+        .and_then(|m| Utc.timestamp_micros(m).single())
+        .ok_or_else(invalid)?;
+
+    Ok((created_at, id.to_string()))
+}
+
+// Paginate items in stable (created_at, id) order
+pub fn paginate<T>(mut items: Vec<T>, sort_key: impl Fn(&T) -> SortKey, query: &PageQuery) -> AppResult<Page<T>> {
+    let limit = query.limit.unwrap_or(DEFAULT_LIMIT).clamp(1, MAX_LIMIT);
+
+    let since = query.since.as_deref()
+        .map(|s| DateTime::parse_from_rfc3339(s)
+            .map(|dt| dt.with_timezone(&Utc))
+            .map_err(|_| AppError::BadRequest("Invalid since timestamp".to_string())))
+        .transpose()?;
+    let after = query.cursor.as_deref().map(decode_cursor).transpose()?;
+
+    items.sort_by_key(|item| sort_key(item));
+    items.retain(|item| {
+        let key = sort_key(item);
+        since.is_none_or(|since| key.0 >= since) && after.as_ref().is_none_or(|after| &key > after)
+    });
+
+    let next_cursor = if items.len() > limit {
+        items.truncate(limit);
+        items.last().map(|item| encode_cursor(&sort_key(item)))
+    } else {
+        None
+    };
+
+    Ok(Page { items, next_cursor })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Items created one minute apart, oldest first
+    fn items(count: i64) -> Vec<SortKey> {
+        let start = Utc.with_ymd_and_hms(2024, 1, 1, 0, 0, 0).unwrap();
+        (0..count).map(|i| (start + chrono::Duration::minutes(i), format!("item-{}", i))).collect()
+    }
+
+    fn page(items: Vec<SortKey>, query: &PageQuery) -> Page<SortKey> {
+        paginate(items, Clone::clone, query).unwrap()
+    }
+
+    #[test]
+    fn pages_cover_every_item_once_in_order() {
+        let mut seen = Vec::new();
+        let mut cursor = None;
+        loop {
+            let query = PageQuery { limit: Some(2), cursor, since: None };
+            let Page { items, next_cursor } = page(items(5), &query);
+            seen.extend(items.into_iter().map(|(_, id)| id));
+            match next_cursor {
+                Some(next) => cursor = Some(next),
+                None => break,
+            }
+        }
+
+        assert_eq!(seen, ["item-0", "item-1", "item-2", "item-3", "item-4"]);
+    }
+
+    #[test]
+    fn since_excludes_older_items() {
+        let query = PageQuery { since: Some("2024-01-01T00:02:00Z".to_string()), ..Default::default() };
+        let ids: Vec<_> = page(items(5), &query).items.into_iter().map(|(_, id)| id).collect();
+
+        assert_eq!(ids, ["item-2", "item-3", "item-4"]);
+    }
+
+    #[test]
+    fn malformed_cursor_and_since_are_rejected() {
+        for query in [
+            PageQuery { cursor: Some("zz".to_string()), ..Default::default() },
+            PageQuery { cursor: Some("abc".to_string()), ..Default::default() },
+            PageQuery { since: Some("yesterday".to_string()), ..Default::default() },
+        ] {
+            assert!(matches!(paginate(items(1), Clone::clone, &query), Err(AppError::BadRequest(_))));
+        }
+    }
+}
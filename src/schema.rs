@@ -0,0 +1,91 @@
+use std::{fmt, fs, sync::Arc};
+use jsonschema::JSONSchema;
+use serde_json::Value;
+
+// Compiled JSON Schema that a service's backend responses must satisfy
+#[derive(Clone)]
+pub struct ResponseSchema {
+    path: String,
+    compiled: Arc<JSONSchema>,
+}
+
+impl fmt::Debug for ResponseSchema {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("ResponseSchema").field("path", &self.path).finish()
+    }
+}
+
+impl ResponseSchema {
+    // Load and compile a schema file
+    pub fn load(path: &str) -> Result<Self, String> {
+        let text = fs::read_to_string(path).map_err(|e| e.to_string())?;
+        let schema: Value = serde_json::from_str(&text).map_err(|e| e.to_string())?;
+        let compiled = JSONSchema::compile(&schema).map_err(|e| e.to_string())?;
+
+        Ok(Self { path: path.to_string(), compiled: Arc::new(compiled) })
+    }
+
+    // Validate a response body, collecting every violation
+    pub fn validate(&self, instance: &Value) -> Result<(), Vec<String>> {
+        self.compiled.validate(instance)
+            .map_err(|errors| errors.map(|e| format!("{}: {}", e.instance_path, e)).collect())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+    use tempfile::TempDir;
+
+    use crate::test_support::{self, MockBackend, TestResponse};
+
+    // Schema requiring a "logs" array of objects with a numeric id
+    fn schema_file() -> (TempDir, String) {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("logs.schema.json");
+        let schema = json!({
+            "type": "object",
+            "required": ["logs"],
+            "properties": {"logs": {"type": "array", "items": {"type": "object", "required": ["id"], "properties": {"id": {"type": "integer"}}}}},
+        });
+        std::fs::write(&path, schema.to_string()).unwrap();
+        let path = path.to_str().unwrap().to_string();
+        (dir, path)
+    }
+
+    async fn fetch_logs(backend_body: serde_json::Value) -> TestResponse {
+        let (_dir, schema) = schema_file();
+        let logs = MockBackend::json(&backend_body);
+        let (router, _) = test_support::app(test_support::config(&[
+            ("LOG_SERVICE_URL", &logs.url()),
+            ("LOG_SERVICE_RESPONSE_SCHEMA", &schema),
+        ]));
+        test_support::send(&router, test_support::get("/api/service/log", &[])).await
+    }
+
+    #[tokio::test]
+    async fn conforming_response_passes_through() {
+        let response = fetch_logs(json!({"logs": [{"id": 1}, {"id": 2}]})).await;
+
+        assert_eq!(response.status, 200);
+        assert_eq!(response.json()["backend_response"]["logs"][1]["id"], 2);
+    }
+
+    #[tokio::test]
+    async fn non_conforming_response_is_a_502_with_the_violations() {
+        let response = fetch_logs(json!({"logs": [{"id": "one"}]})).await;
+
+        assert_eq!(response.status, 502);
+        let body = response.json();
+        assert_eq!(body["code"], "SCHEMA_VALIDATION_FAILED");
+        let message = body["message"].as_str().unwrap();
+        assert!(message.contains("/logs/0/id"), "{}", message);
+        assert!(body.get("backend_response").is_none());
+    }
+
+    #[test]
+    fn unreadable_schema_fails_startup() {
+        let result = test_support::try_config(&[("LOG_SERVICE_RESPONSE_SCHEMA", "/nonexistent/schema.json")]);
+        assert!(matches!(result, Err(crate::error::AppError::Internal(msg)) if msg.contains("LOG_SERVICE_RESPONSE_SCHEMA")));
+    }
+}
@@ -0,0 +1,166 @@
+use std::{fmt, str::FromStr};
+
+// Backend URL scheme
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Scheme {
+    // PQC mTLS via OpenSSL
+    Https,
+    // Plain TCP, for local development or migration sidecars
+    Http,
+}
+
+// Resolved backend connection target
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct BackendTarget {
+    pub scheme: Scheme,
+    pub host: String,
+    pub port: u16,
+}
+
+impl BackendTarget {
+    // Parse backend target from URL, defaulting to https when no scheme is given.
+    // Returns None when no host can be found or the port is not a number.
+    pub fn parse(url: &str) -> Option<Self> {
+        let (scheme, rest, default_port) = match url.strip_prefix("http://") {
+            Some(rest) => (Scheme::Http, rest, 80),
+            None => (Scheme::Https, url.trim_start_matches("https://"), 443),
+        };
+
+        let (host, port) = match rest.trim_end_matches('/').split_once(':') {
+            Some((host, port)) => (host, port.parse::<u16>().ok()?),
+            None => (rest.trim_end_matches('/'), default_port),
+        };
+        if host.is_empty() || host.contains('/') {
+            return None;
+        }
+
+        Some(Self { scheme, host: host.to_string(), port })
+    }
+}
+
+// Backend service type
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum ServiceType {
+    Log,
+    Users,
+    Payment,
+}
+
+impl ServiceType {
+    pub const ALL: [ServiceType; 3] = [Self::Log, Self::Users, Self::Payment];
+
+    // Service name
+    pub fn name(&self) -> &'static str {
+        match self {
+            Self::Log => "log",
+            Self::Users => "users",
+            Self::Payment => "payment",
+        }
+    }
+
+    // Prefix of per-service environment variables
+    pub fn env_prefix(&self) -> &'static str {
+        match self {
+            Self::Log => "LOG",
+            Self::Users => "USER",
+            Self::Payment => "PAYMENT",
+        }
+    }
+
+    // Per-service environment variable, e.g. LOG_SERVICE_URL
+    pub fn env_var(&self, suffix: &str) -> String {
+        format!("{}_SERVICE_{}", self.env_prefix(), suffix)
+    }
+
+    // Backend path for this service
+    pub fn path(&self) -> &'static str {
+        match self {
+            Self::Log => "/api",
+            Self::Users => "/api/users",
+            Self::Payment => "/api/payments",
+        }
+    }
+}
+
+impl fmt::Display for ServiceType {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.name())
+    }
+}
+
+// Service name that matches no known service
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct UnknownService(pub String);
+
+impl fmt::Display for UnknownService {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "unknown service: {}", self.0)
+    }
+}
+
+impl std::error::Error for UnknownService {}
+
+// Parse service type from name or alias, ignoring case and trailing slashes
+impl FromStr for ServiceType {
+    type Err = UnknownService;
+
+    fn from_str(name: &str) -> Result<Self, Self::Err> {
+        match name.trim().trim_end_matches('/').to_ascii_lowercase().as_str() {
+            "log" | "logs" => Ok(Self::Log),
+            "user" | "users" => Ok(Self::Users),
+            "payment" | "payments" => Ok(Self::Payment),
+            _ => Err(UnknownService(name.to_string())),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn scheme_is_detected_from_the_url() {
+        let plain = BackendTarget::parse("http://sidecar:8080").unwrap();
+        assert_eq!((plain.scheme, plain.host.as_str(), plain.port), (Scheme::Http, "sidecar", 8080));
+
+        let tls = BackendTarget::parse("https://proxy:8443/").unwrap();
+        assert_eq!((tls.scheme, tls.host.as_str(), tls.port), (Scheme::Https, "proxy", 8443));
+    }
+
+    #[test]
+    fn missing_scheme_defaults_to_https() {
+        let target = BackendTarget::parse("proxy").unwrap();
+        assert_eq!((target.scheme, target.port), (Scheme::Https, 443));
+        assert_eq!(BackendTarget::parse("http://sidecar").unwrap().port, 80);
+    }
+
+    #[test]
+    fn malformed_urls_are_rejected() {
+        for url in ["", "https://", "http://:80", "proxy:port", "proxy/api"] {
+            assert_eq!(BackendTarget::parse(url), None, "{:?}", url);
+        }
+    }
+
+    #[test]
+    fn names_ignore_case_and_trailing_slashes() {
+        for name in ["Users", "users/", "USERS", " user "] {
+            assert_eq!(name.parse::<ServiceType>(), Ok(ServiceType::Users), "{:?}", name);
+        }
+    }
+
+    #[test]
+    fn every_variant_round_trips_through_its_name() {
+        for service in ServiceType::ALL {
+            assert_eq!(service.to_string().parse::<ServiceType>(), Ok(service));
+        }
+        for (alias, service) in [("logs", ServiceType::Log), ("user", ServiceType::Users), ("payments", ServiceType::Payment)] {
+            assert_eq!(alias.parse::<ServiceType>(), Ok(service), "{:?}", alias);
+        }
+    }
+
+    #[test]
+    fn unknown_names_are_a_parse_error() {
+        assert_eq!("billing".parse::<ServiceType>(), Err(UnknownService("billing".to_string())));
+        assert_eq!("".parse::<ServiceType>().unwrap_err().to_string(), "unknown service: ");
+    }
+}
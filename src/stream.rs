@@ -0,0 +1,187 @@
+use std::{convert::Infallible, sync::Arc, time::Duration};
+use axum::{
+    http::HeaderMap,
+    response::sse::{Event, KeepAlive, Sse},
+    Extension,
+};
+use futures_util::{stream, Stream};
+use tokio::{
+    io::{AsyncBufReadExt, BufReader, Lines},
+    process::{Child, ChildStdout},
+};
+
+use crate::auth::AuthToken;
+use crate::config::Config;
+use crate::discovery::ServiceRegistry;
+use crate::error::{AppError, AppResult};
+use crate::handler;
+use crate::limiter::{BackendLimiter, BackendPermit};
+use crate::service::{Scheme, ServiceType};
+
+// Time allowed for the backend to answer with response headers
+const HEADER_TIMEOUT: Duration = Duration::from_secs(30);
+
+// Backend connection relayed to the client; dropping it kills the OpenSSL subprocess
+// and frees the backend slot held for as long as the stream is open
+struct LogStream {
+    _child: Child,
+    _permit: BackendPermit,
+    lines: Lines<BufReader<ChildStdout>>,
+}
+
+// Relay the log service's streaming output as Server-Sent Events
+pub async fn stream_logs(
+    Extension(config): Extension<Arc<Config>>,
+    Extension(registry): Extension<Arc<ServiceRegistry>>,
+    Extension(limiter): Extension<Arc<BackendLimiter>>,
+    headers: HeaderMap,
+    AuthToken(auth): AuthToken,
+) -> AppResult<Sse<impl Stream<Item = Result<Event, Infallible>>>> {
+    let service = ServiceType::Log;
+    handler::authorize(&config, service, auth.as_deref(), &headers)?;
+
+    // A stream occupies an OpenSSL process like any backend call, for as long as it is open
+    let permit = limiter.acquire(service, None).await?;
+
+    let target = registry.pick(service, None);
+    config.host_policy.check(&target.host)?;
+    if target.scheme != Scheme::Https {
+        return Err(AppError::BadRequest("Log streaming requires an https backend".to_string()));
+    }
+
+    // HTTP/1.0 keeps the body unchunked, so it can be relayed line by line
+    let mut request = format!(
+        "GET {}/stream HTTP/1.0\r\nHost: {}\r\nAccept: text/event-stream\r\n",
+        service.path(), config.host_headers.get(&service).unwrap_or(&target.host)
+    );
+    if let Some(token) = &auth {
+        let template = &config.auth_templates[&service];
+        request.push_str(&format!("{}: {}\r\n", template.header, template.render(token)));
+    }
+    request.push_str("\r\n");
+
+    let mut child = config.tls_for(service)
+        .spawn_stream(&target.host, target.port, request.as_bytes())
+        .await?;
+    let stdout = child.stdout.take()
+        .ok_or_else(|| AppError::Internal("OpenSSL output unavailable".to_string()))?;
+    let mut log_stream = LogStream { _child: child, _permit: permit, lines: BufReader::new(stdout).lines() };
+
+    tokio::time::timeout(HEADER_TIMEOUT, read_headers(&mut log_stream.lines))
+        .await
+        .map_err(|_| AppError::Timeout("Log stream did not respond".to_string()))??;
+
+    tracing::info!("Streaming {} service output from {}:{}", service.name(), target.host, target.port);
+
+    let events = stream::unfold(log_stream, |mut state| async move {
+        loop {
+            match state.lines.next_line().await {
+                Ok(Some(line)) if line.trim().is_empty() => continue,
+                Ok(Some(line)) => return Some((Ok(Event::default().data(line)), state)),
+                Ok(None) | Err(_) => {
+                    tracing::info!("Log stream closed by backend");
+                    return None;
+                },
+            }
+        }
+    });
+
+    Ok(Sse::new(events).keep_alive(KeepAlive::default()))
+}
+
+// Consume the response headers, failing unless the backend answered 2xx
+async fn read_headers(lines: &mut Lines<BufReader<ChildStdout>>) -> AppResult<()> {
+    let closed = || AppError::ServiceUnavailable("Log stream closed before responding".to_string());
+    let io_err = |e: std::io::Error| AppError::Internal(format!("Log stream read failed: {}", e));
+
+    let status_line = lines.next_line().await.map_err(io_err)?.ok_or_else(closed)?;
+    let ok = status_line.split_whitespace().nth(1).is_some_and(|code| code.starts_with('2'));
+    if !ok {
+        return Err(AppError::ServiceUnavailable(format!("Log stream rejected: {}", status_line.trim())));
+    }
+
+    loop {
+        let line = lines.next_line().await.map_err(io_err)?.ok_or_else(closed)?;
+        if line.trim().is_empty() {
+            return Ok(());
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+    use axum::{body::{BoxBody, HttpBody}, http::header};
+    use tower::ServiceExt;
+
+    use crate::test_support::{self, StubOpenssl};
+
+    // Backend answering the stream request with one event, then two more once released
+    const STREAMING_BACKEND: &str = r#"
+case "$*" in *-quiet*)
+    printf 'HTTP/1.0 200 OK\r\nContent-Type: text/event-stream\r\n\r\n'
+    echo 'event 1'
+    while [ ! -f "$dir/release" ]; do sleep 0.02; done
+    echo 'event 2'
+    echo 'event 3'
+    exit 0;;
+esac
+"#;
+
+    // Read the body until it has delivered the text, returning everything read
+    async fn read_until(body: &mut BoxBody, text: &str) -> String {
+        let mut received = String::new();
+        while !received.contains(text) {
+            let chunk = tokio::time::timeout(Duration::from_secs(5), body.data()).await
+                .expect("event in time")
+                .expect("stream still open")
+                .expect("readable chunk");
+            received.push_str(&String::from_utf8_lossy(&chunk));
+        }
+        received
+    }
+
+    #[tokio::test(flavor = "multi_thread", worker_threads = 2)]
+    async fn backend_lines_are_relayed_as_they_arrive() {
+        let stub = StubOpenssl::new(b"", "").script(STREAMING_BACKEND);
+        let path = stub.path();
+        let (router, state) = test_support::app(test_support::config(&[
+            ("LOG_SERVICE_URL", "https://127.0.0.1:8443"),
+            ("OPENSSL_PATH", &path),
+        ]));
+
+        let response = router.oneshot(test_support::get("/api/service/logs/stream", &[])).await.unwrap();
+        assert_eq!(response.status(), 200);
+        assert_eq!(response.headers()[header::CONTENT_TYPE], "text/event-stream");
+        let mut body = response.into_body();
+
+        // The first event arrives while the backend is still holding back the rest
+        let first = read_until(&mut body, "event 1").await;
+        assert!(!first.contains("event 2"), "{}", first);
+        assert_eq!(state.limiter.in_flight(), 1);
+
+        stub.signal("release");
+        let rest = read_until(&mut body, "event 3").await;
+        assert!(rest.contains("event 2"), "{}", rest);
+
+        // The backend closing ends the stream and frees its slot
+        assert!(tokio::time::timeout(Duration::from_secs(5), body.data()).await.unwrap().is_none());
+        assert_eq!(state.limiter.in_flight(), 0);
+    }
+
+    #[tokio::test]
+    async fn stream_is_subject_to_the_step_up_gate() {
+        let stub = StubOpenssl::new(b"", "").script(STREAMING_BACKEND);
+        let path = stub.path();
+        let (router, state) = test_support::app(test_support::config(&[
+            ("LOG_SERVICE_URL", "https://127.0.0.1:8443"),
+            ("OPENSSL_PATH", &path),
+            ("STEP_UP_REQUIRED_SERVICES", "log"),
+        ]));
+
+        let response = router.oneshot(test_support::get("/api/service/logs/stream", &[])).await.unwrap();
+        assert_eq!(response.status(), 401);
+        assert!(stub.connections().is_empty());
+        assert_eq!(state.limiter.in_flight(), 0);
+    }
+}
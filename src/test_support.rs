@@ -0,0 +1,413 @@
+// Fixtures shared by the unit tests
+use std::{
+    collections::HashMap,
+    future::Future,
+    io::{Read, Write},
+    fs,
+    net::{SocketAddr, TcpListener, TcpStream},
+    path::Path,
+    sync::{Arc, Mutex},
+    thread,
+};
+use axum::{
+    body::{Body, Bytes},
+    extract::connect_info::MockConnectInfo,
+    http::{header, HeaderMap, Method, Request, StatusCode},
+    Router,
+};
+use serde_json::{json, Value};
+use tower::ServiceExt;
+use tracing_subscriber::fmt::MakeWriter;
+use url::Url;
+use webauthn_authenticator_rs::{prelude::WebauthnAuthenticator, softpasskey::SoftPasskey, softtoken::SoftToken, AuthenticatorBackend};
+use webauthn_rs::prelude::{Base64UrlSafeData, CreationChallengeResponse, RequestChallengeResponse};
+
+use crate::app::{self, AppState};
+use crate::config::Config;
+use crate::error::AppResult;
+
+pub const JWT_SECRET: &str = "test-secret";
+
+// Address requests appear to come from
+pub const CLIENT_ADDR: ([u8; 4], u16) = ([203, 0, 113, 7], 50000);
+
+// Configuration from the given variables on top of the minimum the gateway needs
+pub fn config(vars: &[(&str, &str)]) -> Config {
+    try_config(vars).expect("valid test configuration")
+}
+
+pub fn try_config(vars: &[(&str, &str)]) -> AppResult<Config> {
+    let env: HashMap<String, String> = [("JWT_SECRET", JWT_SECRET), ("OPENSSL_PATH", "openssl")]
+        .into_iter()
+        .chain(vars.iter().copied())
+        .map(|(key, value)| (key.to_string(), value.to_string()))
+        .collect();
+    Config::from_vars(|key| env.get(key).cloned())
+}
+
+// Router over fresh state, as main builds it
+pub fn app(config: Config) -> (Router, AppState) {
+    let config = Arc::new(config);
+    let state = AppState::new(&config);
+    let router = app::router(config, &state).layer(MockConnectInfo(SocketAddr::from(CLIENT_ADDR)));
+    (router, state)
+}
+
+// Response parts of a request sent through the router
+pub struct TestResponse {
+    pub status: StatusCode,
+    pub headers: HeaderMap,
+    pub body: Bytes,
+}
+
+impl TestResponse {
+    pub fn json(&self) -> Value {
+        serde_json::from_slice(&self.body)
+            .unwrap_or_else(|e| panic!("response is not JSON ({}): {}", e, String::from_utf8_lossy(&self.body)))
+    }
+}
+
+// Send a request through the router; the future does not borrow it, so it can be spawned
+pub fn send(router: &Router, request: Request<Body>) -> impl Future<Output = TestResponse> + Send {
+    let router = router.clone();
+    async move {
+        let response = router.oneshot(request).await.expect("router is infallible");
+        let (parts, body) = response.into_parts();
+        let body = hyper::body::to_bytes(body).await.expect("readable body");
+        TestResponse { status: parts.status, headers: parts.headers, body }
+    }
+}
+
+// Log lines emitted on this thread while the guard is held
+#[derive(Clone, Default)]
+pub struct CapturedLogs(Arc<Mutex<Vec<u8>>>);
+
+impl CapturedLogs {
+    // Capture everything logged on the current thread until the guard is dropped;
+    // single-threaded test runtimes run the whole request on it
+    pub fn start() -> (Self, tracing::subscriber::DefaultGuard) {
+        let logs = Self::default();
+        let subscriber = tracing_subscriber::fmt()
+            .with_writer(logs.clone())
+            .with_max_level(tracing::Level::TRACE)
+            .with_ansi(false)
+            .finish();
+        (logs.clone(), tracing::subscriber::set_default(subscriber))
+    }
+
+    pub fn contents(&self) -> String {
+        String::from_utf8_lossy(&self.0.lock().unwrap()).into_owned()
+    }
+}
+
+impl Write for CapturedLogs {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        self.0.lock().unwrap().extend_from_slice(buf);
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        Ok(())
+    }
+}
+
+impl<'a> MakeWriter<'a> for CapturedLogs {
+    type Writer = Self;
+
+    fn make_writer(&'a self) -> Self::Writer {
+        self.clone()
+    }
+}
+
+// Request with the given headers and body
+pub fn request(method: Method, uri: &str, headers: &[(&str, &str)], body: impl Into<Body>) -> Request<Body> {
+    let mut builder = Request::builder().method(method).uri(uri);
+    for (name, value) in headers {
+        builder = builder.header(*name, *value);
+    }
+    builder.body(body.into()).expect("valid request")
+}
+
+pub fn get(uri: &str, headers: &[(&str, &str)]) -> Request<Body> {
+    request(Method::GET, uri, headers, Body::empty())
+}
+
+// Request with a JSON body
+pub fn json_request(method: Method, uri: &str, headers: &[(&str, &str)], body: &Value) -> Request<Body> {
+    let mut headers = headers.to_vec();
+    headers.push((header::CONTENT_TYPE.as_str(), "application/json"));
+    request(method, uri, &headers, body.to_string())
+}
+
+// Plain HTTP backend on a loopback port that answers each request with the handler's
+// response and closes the connection, recording every request it receives
+pub struct MockBackend {
+    pub addr: SocketAddr,
+    requests: Arc<Mutex<Vec<Vec<u8>>>>,
+}
+
+impl MockBackend {
+    pub fn start(handler: impl Fn(&[u8]) -> Vec<u8> + Send + Sync + 'static) -> Self {
+        let listener = TcpListener::bind("127.0.0.1:0").expect("bind mock backend");
+        let addr = listener.local_addr().expect("mock backend address");
+        let requests = Arc::new(Mutex::new(Vec::new()));
+        let handler = Arc::new(handler);
+
+        let recorded = Arc::clone(&requests);
+        thread::spawn(move || {
+            for stream in listener.incoming().flatten() {
+                let (recorded, handler) = (Arc::clone(&recorded), Arc::clone(&handler));
+                thread::spawn(move || serve(stream, &recorded, handler.as_ref()));
+            }
+        });
+
+        Self { addr, requests }
+    }
+
+    // Backend answering everything with the same raw response
+    pub fn respond(response: impl Into<Vec<u8>>) -> Self {
+        let response = response.into();
+        Self::start(move |_| response.clone())
+    }
+
+    // Backend answering everything with 200 and a JSON body
+    pub fn json(body: &Value) -> Self {
+        Self::respond(http_response(200, "application/json", body.to_string().as_bytes()))
+    }
+
+    pub fn url(&self) -> String {
+        format!("http://{}", self.addr)
+    }
+
+    pub fn requests(&self) -> Vec<Vec<u8>> {
+        self.requests.lock().unwrap().clone()
+    }
+
+    // Request lines ("GET /api HTTP/1.1") received so far
+    pub fn request_lines(&self) -> Vec<String> {
+        self.requests().iter()
+            .map(|raw| String::from_utf8_lossy(raw).lines().next().unwrap_or_default().to_string())
+            .collect()
+    }
+}
+
+// Serialize a complete response with a Content-Length
+pub fn http_response(status: u16, content_type: &str, body: &[u8]) -> Vec<u8> {
+    let head = format!(
+        "HTTP/1.1 {} Status\r\nContent-Type: {}\r\nContent-Length: {}\r\nConnection: close\r\n\r\n",
+        status, content_type, body.len()
+    );
+    [head.as_bytes(), body].concat()
+}
+
+fn serve(mut stream: TcpStream, recorded: &Mutex<Vec<Vec<u8>>>, handler: &(dyn Fn(&[u8]) -> Vec<u8> + Send + Sync)) {
+    let request = read_request(&mut stream);
+    recorded.lock().unwrap().push(request.clone());
+    let _ = stream.write_all(&handler(&request));
+}
+
+// Read the request head and a Content-Length body
+fn read_request(stream: &mut TcpStream) -> Vec<u8> {
+    let mut data = Vec::new();
+    let mut buf = [0u8; 4096];
+    loop {
+        if let Some(end) = data.windows(4).position(|w| w == b"\r\n\r\n") {
+            let head = String::from_utf8_lossy(&data[..end]).to_ascii_lowercase();
+            let length = head.lines()
+                .find_map(|line| line.strip_prefix("content-length:"))
+                .and_then(|value| value.trim().parse::<usize>().ok())
+                .unwrap_or(0);
+            if data.len() >= end + 4 + length {
+                return data;
+            }
+        }
+        match stream.read(&mut buf) {
+            Ok(0) | Err(_) => return data,
+            Ok(n) => data.extend_from_slice(&buf[..n]),
+        }
+    }
+}
+
+// OpenSSL stand-in for HTTPS backends (tests/fixtures/stub-openssl): answers every
+// s_client run with the canned output and records how it was invoked
+pub struct StubOpenssl {
+    dir: tempfile::TempDir,
+}
+
+impl StubOpenssl {
+    // s_client runs print the response on stdout and the handshake summary on stderr
+    pub fn new(stdout: &[u8], stderr: &str) -> Self {
+        let dir = tempfile::tempdir().expect("stub directory");
+        let fixture = Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/fixtures/stub-openssl");
+        std::os::unix::fs::symlink(fixture, dir.path().join("openssl")).expect("link stub");
+        let stub = Self { dir };
+        stub.write("stdout", stdout);
+        stub.write("stderr", stderr.as_bytes());
+        stub.write("status", b"0");
+        stub
+    }
+
+    // Stub answering with an HTTP response over a successful TLS 1.3 PQC handshake
+    pub fn responding(response: &[u8]) -> Self {
+        Self::new(response, HANDSHAKE_SUMMARY)
+    }
+
+    // Shell snippet run before the canned answer, with the arguments in "$*"
+    pub fn script(self, script: &str) -> Self {
+        self.write("script", script.as_bytes());
+        self
+    }
+
+    // Create a file a script can wait for, to pace its output
+    pub fn signal(&self, name: &str) {
+        self.write(name, b"");
+    }
+
+    fn write(&self, name: &str, contents: &[u8]) {
+        fs::write(self.dir.path().join(name), contents).expect("write stub file");
+    }
+
+    fn read(&self, name: &str) -> String {
+        fs::read_to_string(self.dir.path().join(name)).unwrap_or_default()
+    }
+
+    // Value for OPENSSL_PATH
+    pub fn path(&self) -> String {
+        self.dir.path().join("openssl").display().to_string()
+    }
+
+    // Arguments of every invocation so far, space-separated
+    pub fn invocations(&self) -> Vec<String> {
+        self.read("args").lines().map(str::to_string).collect()
+    }
+
+    // Environment of the latest invocation, one NAME=value per line
+    pub fn env(&self) -> String {
+        self.read("env")
+    }
+
+    // s_client invocations only, leaving out version queries
+    pub fn connections(&self) -> Vec<String> {
+        self.invocations().into_iter().filter(|args| args.starts_with("s_client")).collect()
+    }
+}
+
+// OpenSSL 3.5 s_client stderr when -verify_return_error aborts the handshake on a
+// proxy certificate the CA file does not cover, or issued for another name
+pub fn verify_failure(error: &str) -> String {
+    format!(
+        "Connecting to 127.0.0.1\ndepth=0 CN=localhost\nverify error:{}\n\
+        4067EF4CF07F0000:error:0A000086:SSL routines:tls_post_process_server_certificate:certificate verify failed:ssl/statem/statem_clnt.c:2124:\n",
+        error
+    )
+}
+
+// s_client -brief summary of a TLS 1.3 handshake over the hybrid PQC group
+pub const HANDSHAKE_SUMMARY: &str = "CONNECTION ESTABLISHED\n\
+    Protocol version: TLSv1.3\n\
+    Ciphersuite: TLS_AES_256_GCM_SHA384\n\
+    Peer certificate: CN=localhost\n\
+    Signature type: mldsa65\n\
+    Negotiated TLS1.3 group: X25519MLKEM768\n";
+
+// Origin of WebAuthn ceremonies against the default configuration
+pub const ORIGIN: &str = "http://localhost:3000";
+
+// Software passkey answering the gateway's WebAuthn challenges in-process, so
+// ceremonies can be driven without a real authenticator
+pub struct Passkey<T: AuthenticatorBackend = SoftPasskey> {
+    authenticator: WebauthnAuthenticator<T>,
+    // Handle the credential was registered for. The soft token leaves it out of
+    // assertions; browsers fill it in for discoverable credentials, so this does too.
+    pub user_handle: Option<Base64UrlSafeData>,
+}
+
+impl Passkey {
+    pub fn new() -> Self {
+        // Passkey registration requires user verification, which the soft token asserts
+        Self::with_backend(SoftPasskey::new(true))
+    }
+}
+
+impl Passkey<SoftToken> {
+    // Authenticator attesting its credentials with a certificate naming its AAGUID
+    pub fn attested() -> Self {
+        Self::with_backend(SoftToken::new(true).expect("soft token").0)
+    }
+}
+
+impl<T: AuthenticatorBackend> Passkey<T> {
+    fn with_backend(backend: T) -> Self {
+        Self { authenticator: WebauthnAuthenticator::new(backend), user_handle: None }
+    }
+
+    // Registration credential answering a start_register "public_key" challenge
+    pub fn register(&mut self, public_key: &Value) -> Value {
+        let challenge: CreationChallengeResponse = serde_json::from_value(public_key.clone()).expect("creation challenge");
+        self.user_handle = Some(challenge.public_key.user.id.clone());
+        let credential = self.authenticator.do_registration(Url::parse(ORIGIN).unwrap(), challenge).expect("registration");
+        serde_json::to_value(credential).unwrap()
+    }
+
+    // Assertion answering a start_login "public_key" challenge
+    pub fn assert(&mut self, public_key: &Value) -> Value {
+        let challenge: RequestChallengeResponse = serde_json::from_value(public_key.clone()).expect("request challenge");
+        let mut credential = self.authenticator.do_authentication(Url::parse(ORIGIN).unwrap(), challenge).expect("assertion");
+        credential.response.user_handle = self.user_handle.clone();
+        serde_json::to_value(credential).unwrap()
+    }
+}
+
+impl Default for Passkey {
+    fn default() -> Self { Self::new() }
+}
+
+// Register a new passkey for the username through the router's ceremony routes
+pub async fn register(router: &Router, username: &str) -> Passkey {
+    let mut passkey = Passkey::new();
+    let response = finish_register(router, username, &mut passkey).await;
+    assert_eq!(response.status, 200, "registration failed: {}", String::from_utf8_lossy(&response.body));
+    passkey
+}
+
+// Run a registration ceremony for the passkey, returning the finish response
+pub async fn finish_register<T: AuthenticatorBackend>(router: &Router, username: &str, passkey: &mut Passkey<T>) -> TestResponse {
+    let start = send(router, json_request(Method::POST, "/auth/register", &[], &json!({"username": username}))).await;
+    assert_eq!(start.status, 200, "registration start failed: {}", String::from_utf8_lossy(&start.body));
+    let credential = passkey.register(&start.json()["public_key"]);
+    send(router, json_request(Method::POST, "/auth/verify-register", &[], &json!({"username": username, "credential": credential}))).await
+}
+
+// Start a login, without completing it
+pub async fn login_start(router: &Router, username: &str) -> TestResponse {
+    send(router, json_request(Method::POST, "/auth/login", &[], &json!({"username": username}))).await
+}
+
+// Run a login ceremony with the passkey, returning the finish response
+
+pub async fn login(router: &Router, username: &str, passkey: &mut Passkey) -> TestResponse {
+    let start = login_start(router, username).await;
+    assert_eq!(start.status, 200, "login start failed: {}", String::from_utf8_lossy(&start.body));
+    let credential = passkey.assert(&start.json()["public_key"]);
+    send(router, json_request(Method::POST, "/auth/verify-login", &[], &json!({"username": username, "credential": credential}))).await
+}
+
+// Log in with the passkey and return the issued token
+pub async fn login_token(router: &Router, username: &str, passkey: &mut Passkey) -> String {
+    let response = login(router, username, passkey).await;
+    assert_eq!(response.status, 200, "login failed: {}", String::from_utf8_lossy(&response.body));
+    response.json()["token"].as_str().expect("token").to_string()
+}
+
+// Stored passkey of a credential registered on a separate gateway, as an export to import
+pub async fn exported_passkey() -> Value {
+    let (router, state) = app(config(&[]));
+    register(&router, "exported").await;
+    let store = state.user_store.lock().unwrap();
+    json!(store.values().next().expect("registered user").credentials[0].passkey)
+}
+
+// Authorization header value for a token
+pub fn bearer(token: &str) -> String {
+    format!("Bearer {}", token)
+}
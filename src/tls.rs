@@ -1,20 +1,83 @@
-use std::{env, io::Write, process::{Command, Stdio}};
-use serde_json::Value;
+use std::{collections::HashMap, fs, io::{Read, Write}, net::IpAddr, process::{Child, Command, Output, Stdio}, sync::{Arc, Mutex}, thread, time::{Duration, Instant, SystemTime}};
+use serde::Serialize;
+use crate::clock::SharedClock;
 use crate::error::{AppError, AppResult};
 
 // TLS configuration structure
+#[derive(Debug, Clone)]
 pub struct TlsConfig {
     pub openssl: String,
     pub cert: String,
     pub key: String,
     pub ca: String,
+    pub insecure_skip_verify: bool,
+    // Server name sent in the ClientHello; defaults to the connect host
+    pub sni: Option<String>,
+    // TLS 1.3 cipher suites offered; OpenSSL defaults when empty
+    pub ciphersuites: Vec<String>,
+    // Retry with classical key exchange when the peer cannot negotiate the PQC group
+    pub allow_classical_fallback: bool,
+    // Lowest protocol version offered or accepted
+    pub min_version: TlsVersion,
+    // Extra variables passed to the OpenSSL subprocess beyond OPENSSL_ENV_ALLOWLIST
+    pub env_passthrough: Vec<String>,
+    // Sessions resumed by later requests; None forces a full handshake every time
+    pub sessions: Option<Arc<SessionCache>>,
+    pub timeout: Option<Duration>,
 }
 
+// TLS protocol versions the gateway can be limited to
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum TlsVersion {
+    Tls12,
+    Tls13,
+}
+
+impl TlsVersion {
+    // Parse "1.2"/"1.3", with or without a TLSv prefix
+    fn parse(value: &str) -> Option<Self> {
+        match value.trim().trim_start_matches("TLSv").trim_start_matches("tlsv") {
+            "1.2" => Some(Self::Tls12),
+            "1.3" => Some(Self::Tls13),
+            _ => None,
+        }
+    }
+
+    // Name as OpenSSL reports it
+    pub fn name(self) -> &'static str {
+        match self {
+            Self::Tls12 => "TLSv1.2",
+            Self::Tls13 => "TLSv1.3",
+        }
+    }
+}
+
+// Key exchange groups offered normally and after a classical fallback
+pub const PQC_GROUPS: &str = "X25519MLKEM768";
+const CLASSICAL_GROUPS: &str = "X25519:P-256";
+
+// Variables the OpenSSL subprocess keeps; everything else (JWT_SECRET, ADMIN_TOKEN, ...)
+// is scrubbed from its environment
+const OPENSSL_ENV_ALLOWLIST: [&str; 8] = [
+    "PATH", "OPENSSL_CONF", "OPENSSL_MODULES", "OPENSSL_ENGINES",
+    "SSL_CERT_FILE", "SSL_CERT_DIR", "LD_LIBRARY_PATH", "DYLD_LIBRARY_PATH",
+];
+
+// OpenSSL diagnostics for a peer that shares none of the offered groups. A bare
+// "handshake failure" also covers certificate problems, so it is not one of them.
+const GROUP_FAILURE_MARKERS: [&str; 2] = ["alert number 40", "no suitable key share"];
+
 impl TlsConfig {
-    // Create a new TLS configuration
-    pub fn new() -> Self {
-        Self {
-            openssl: env::var("OPENSSL_PATH").unwrap_or_else(|_| {
+    // Create TLS configuration from a variable lookup
+    pub fn from_vars(var: &impl Fn(&str) -> Option<String>) -> AppResult<Self> {
+        let min_version = match var("TLS_MIN_VERSION") {
+            Some(value) => TlsVersion::parse(&value)
+                .ok_or_else(|| AppError::Internal(format!("Invalid TLS_MIN_VERSION: {}", value)))?,
+            None => TlsVersion::Tls13,
+        };
+
+        Ok(Self {
+            openssl: var("OPENSSL_PATH").unwrap_or_else(|| {
                 for path in [
                     "/usr/local/Cellar/openssl@3.5/3.5.0/bin/openssl",
                     "/usr/local/opt/openssl@3.5/bin/openssl",
@@ -28,47 +91,211 @@ impl TlsConfig {
                 }
                 "openssl".to_string()
             }),
-            cert: env::var("CLIENT_CERT_PATH").unwrap_or_else(|_| "certs/hybrid-client/client.crt".to_string()),
-            key: env::var("CLIENT_KEY_PATH").unwrap_or_else(|_| "certs/hybrid-client/client_pkcs8.key".to_string()),
-            ca: env::var("CA_CERT_PATH").unwrap_or_else(|_| "certs/hybrid-ca/ca.crt".to_string()),
-        }
+            cert: var("CLIENT_CERT_PATH").unwrap_or_else(|| "certs/hybrid-client/client.crt".to_string()),
+            key: var("CLIENT_KEY_PATH").unwrap_or_else(|| "certs/hybrid-client/client_pkcs8.key".to_string()),
+            ca: var("CA_CERT_PATH").unwrap_or_else(|| "certs/hybrid-ca/ca.crt".to_string()),
+            insecure_skip_verify: var("INSECURE_SKIP_VERIFY").map(|v| v == "true" || v == "1").unwrap_or(false),
+            sni: var("TLS_SNI").filter(|sni| !sni.is_empty()),
+            ciphersuites: var("TLS_CIPHERSUITES")
+                .map(|list| list.split(':').map(str::trim).filter(|suite| !suite.is_empty()).map(str::to_string).collect())
+                .unwrap_or_default(),
+            allow_classical_fallback: var("TLS_ALLOW_CLASSICAL_FALLBACK").map(|v| v == "true" || v == "1").unwrap_or(false),
+            min_version,
+            env_passthrough: var("OPENSSL_ENV_PASSTHROUGH")
+                .map(|list| list.split(',').map(str::trim).filter(|name| !name.is_empty()).map(str::to_string).collect())
+                .unwrap_or_default(),
+            sessions: None,
+            timeout: None,
+        })
     }
 
-    // Execute OpenSSL command
-    pub fn run(&self, host: &str, port: u16, args: &[&str], stdin: Option<&[u8]>) -> AppResult<std::process::Output> {
-        let mut cmd = Command::new(&self.openssl);
+    // Apply per-service overrides such as PAYMENT_CLIENT_CERT_PATH
+    pub fn with_overrides(&self, var: &impl Fn(&str) -> Option<String>, prefix: &str) -> Self {
+        let mut config = self.clone();
+        let override_var = |key: &str| var(&format!("{}_{}", prefix, key));
+
+        if let Some(cert) = override_var("CLIENT_CERT_PATH") { config.cert = cert; }
+        if let Some(key) = override_var("CLIENT_KEY_PATH") { config.key = key; }
+        if let Some(ca) = override_var("CA_CERT_PATH") { config.ca = ca; }
+        if let Some(sni) = override_var("TLS_SNI").filter(|sni| !sni.is_empty()) { config.sni = Some(sni); }
+        config
+    }
+
+    // Bound the OpenSSL subprocess run time
+    pub fn with_timeout(mut self, timeout: Option<Duration>) -> Self {
+        self.timeout = timeout;
+        self
+    }
+
+    // Build OpenSSL s_client command for the target
+    fn command(&self, host: &str, port: u16, args: &[&str]) -> Command {
+        self.command_with_groups(host, port, args, PQC_GROUPS)
+    }
+
+    fn command_with_groups(&self, host: &str, port: u16, args: &[&str], groups: &str) -> Command {
+        let mut cmd = self.openssl_command();
         cmd.arg("s_client")
            .args(["-connect", &format!("{}:{}", host, port)])
            .args(["-cert", &self.cert])
-           .args(["-key", &self.key])
-           .args(["-CAfile", &self.ca])
-           .args(["-tls1_3", "-groups", "X25519MLKEM768"])
+           .args(["-key", &self.key]);
+
+        // Needed when connecting by IP, where s_client sends no server name
+        if let Some(sni) = &self.sni {
+            cmd.args(["-servername", sni]);
+        }
+
+        // s_client only logs verification failures unless told to abort on them, and
+        // checks the certificate's name only when given one
+        if self.insecure_skip_verify {
+            tracing::warn!("Skipping proxy certificate verification for {}:{} (INSECURE_SKIP_VERIFY)", host, port);
+        } else {
+            cmd.args(["-CAfile", &self.ca]).arg("-verify_return_error");
+            match (&self.sni, host.parse::<IpAddr>()) {
+                (None, Ok(_)) => cmd.args(["-verify_ip", host]),
+                (sni, _) => cmd.args(["-verify_hostname", sni.as_deref().unwrap_or(host)]),
+            };
+        }
+
+        if !self.ciphersuites.is_empty() {
+            cmd.args(["-ciphersuites", &self.ciphersuites.join(":")]);
+        }
+
+        match self.min_version {
+            TlsVersion::Tls13 => cmd.arg("-tls1_3"),
+            TlsVersion::Tls12 => cmd.args(["-min_protocol", TlsVersion::Tls12.name()]),
+        };
+        cmd.args(["-groups", groups])
            .args(args);
+        cmd
+    }
 
-        match stdin {
-            Some(data) => {
-                let mut child = cmd.stdin(Stdio::piped())
-                                  .stdout(Stdio::piped())
-                                  .stderr(Stdio::piped())
-                                  .spawn()
-                                  .map_err(|e| AppError::Internal(format!("OpenSSL startup error: {}", e)))?;
-
-                if let Some(mut stdin) = child.stdin.take() {
-                    stdin.write_all(data)
-                        .map_err(|e| AppError::Internal(format!("OpenSSL input error: {}", e)))?;
-                }
+    // OpenSSL invocation with a scrubbed environment, so gateway secrets never reach
+    // the subprocess (or /proc/<pid>/environ)
+    fn openssl_command(&self) -> Command {
+        let kept = OPENSSL_ENV_ALLOWLIST.iter()
+            .copied()
+            .chain(self.env_passthrough.iter().map(String::as_str))
+            .filter_map(|name| std::env::var_os(name).map(|value| (name, value)));
 
-                child.wait_with_output()
-                    .map_err(|e| AppError::Internal(format!("OpenSSL error: {}", e)))
-            },
-            None => cmd.output()
+        let mut cmd = Command::new(&self.openssl);
+        cmd.env_clear().envs(kept);
+        cmd
+    }
+
+    // Execute OpenSSL command
+    pub fn run(&self, host: &str, port: u16, args: &[&str], stdin: Option<&[u8]>) -> AppResult<std::process::Output> {
+        self.run_negotiated(host, port, args, stdin).map(|(output, _)| output)
+    }
+
+    // Execute OpenSSL command, falling back to classical groups when allowed and the
+    // peer rejects the PQC group. Also returns the colon-separated groups offered.
+    pub fn run_negotiated(&self, host: &str, port: u16, args: &[&str], stdin: Option<&[u8]>) -> AppResult<(Output, &'static str)> {
+        self.negotiate(host, port, args, stdin, false)
+    }
+
+    // Execute OpenSSL command, resuming the target's cached session when there is one
+    pub fn run_resuming(&self, host: &str, port: u16, args: &[&str], stdin: Option<&[u8]>) -> AppResult<Output> {
+        self.negotiate(host, port, args, stdin, true).map(|(output, _)| output)
+    }
+
+    fn negotiate(&self, host: &str, port: u16, args: &[&str], stdin: Option<&[u8]>, resume: bool) -> AppResult<(Output, &'static str)> {
+        let output = self.run_with_groups(host, port, args, stdin, PQC_GROUPS, resume)?;
+        if output.status.success() || !self.allow_classical_fallback {
+            return Ok((output, PQC_GROUPS));
+        }
+
+        let stderr = String::from_utf8_lossy(&output.stderr);
+        if !GROUP_FAILURE_MARKERS.iter().any(|marker| stderr.contains(marker)) {
+            return Ok((output, PQC_GROUPS));
+        }
+
+        tracing::warn!("PQC key exchange with {}:{} failed; retrying with classical groups (TLS_ALLOW_CLASSICAL_FALLBACK)", host, port);
+        let output = self.run_with_groups(host, port, args, stdin, CLASSICAL_GROUPS, resume)?;
+        // Every downgraded connection is reported, not just the first
+        if output.status.success() {
+            tracing::warn!("Connected to {}:{} with CLASSICAL key exchange; this connection is not quantum-safe", host, port);
+        }
+        Ok((output, CLASSICAL_GROUPS))
+    }
+
+    fn run_with_groups(&self, host: &str, port: u16, args: &[&str], stdin: Option<&[u8]>, groups: &str, resume: bool) -> AppResult<Output> {
+        let sessions = self.sessions.as_ref().filter(|_| resume);
+        let session = sessions
+            .map(|cache| cache.files(&self.session_key(host, port), &self.cert))
+            .transpose()?;
+
+        let mut cmd = self.command_with_groups(host, port, args, groups);
+        if let Some(session) = &session {
+            cmd.args(session.args());
+        }
+        let output = self.wait_for(cmd, stdin)?;
+
+        if let (Some(cache), Some(session)) = (sessions, session) {
+            cache.finish(session, output.status.success());
+        }
+        Ok(output)
+    }
+
+    // Cache key for sessions with the target. The cache is shared by all services, and
+    // one set up with another client identity, CA or server name must not be resumed.
+    fn session_key(&self, host: &str, port: u16) -> String {
+        format!(
+            "{}:{} cert={} key={} ca={} sni={}",
+            host, port, self.cert, self.key, self.ca, self.sni.as_deref().unwrap_or("-")
+        )
+    }
+
+    fn wait_for(&self, mut cmd: Command, stdin: Option<&[u8]>) -> AppResult<Output> {
+        let mut child = cmd
+            .stdin(if stdin.is_some() { Stdio::piped() } else { Stdio::null() })
+            .stdout(Stdio::piped())
+            .stderr(Stdio::piped())
+            .spawn()
+            .map_err(|e| self.spawn_error(e))?;
+
+        if let (Some(data), Some(mut pipe)) = (stdin, child.stdin.take()) {
+            pipe.write_all(data)
+                .map_err(|e| AppError::Internal(format!("OpenSSL input error: {}", e)))?;
+        }
+
+        match self.timeout {
+            Some(timeout) => wait_with_timeout(child, timeout),
+            None => child.wait_with_output()
                      .map_err(|e| AppError::Internal(format!("OpenSSL error: {}", e)))
         }
     }
 
+    // Open a long-lived connection, sending the request and leaving the output to stream.
+    // The subprocess is killed when the returned child is dropped.
+    pub async fn spawn_stream(&self, host: &str, port: u16, request: &[u8]) -> AppResult<tokio::process::Child> {
+        let mut child = tokio::process::Command::from(self.command(host, port, &["-quiet"]))
+            .stdin(Stdio::piped())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::null())
+            .kill_on_drop(true)
+            .spawn()
+            .map_err(|e| self.spawn_error(e))?;
+
+        // -quiet ignores stdin EOF, so the connection stays open after the request
+        if let Some(mut pipe) = child.stdin.take() {
+            tokio::io::AsyncWriteExt::write_all(&mut pipe, request).await
+                .map_err(|e| AppError::Internal(format!("OpenSSL input error: {}", e)))?;
+        }
+
+        Ok(child)
+    }
+
+    // Describe a failure to start OpenSSL, pointing at OPENSSL_PATH when the binary is missing
+    fn spawn_error(&self, e: std::io::Error) -> AppError {
+        if e.kind() == std::io::ErrorKind::NotFound {
+            return AppError::Internal(format!("OpenSSL not found at {:?}; set OPENSSL_PATH to an OpenSSL 3.5+ binary", self.openssl));
+        }
+        AppError::Internal(format!("OpenSSL startup error: {}", e))
+    }
+
     // Get OpenSSL version
     pub fn version(&self) -> String {
-        Command::new(&self.openssl)
+        self.openssl_command()
             .arg("version")
             .output()
             .map(|out| String::from_utf8_lossy(&out.stdout).trim().to_string())
@@ -76,10 +303,368 @@ impl TlsConfig {
     }
 }
 
-// Get TLS connection information
-pub fn get_tls_info(host: &str, port: u16) -> AppResult<Value> {
-    let config = TlsConfig::new();
-    let output = config.run(host, port, &["-brief"], None)?;
+// TLS sessions kept per target and TLS identity so later requests skip the full
+// handshake. A session is dropped once TLS_SESSION_MAX_AGE_SECS old, limiting how long
+// its keys stay in use, or when the client certificate file changes (a rotated certificate).
+#[derive(Debug)]
+pub struct SessionCache {
+    max_age: Duration,
+    clock: SharedClock,
+    sessions: Mutex<HashMap<String, CachedSession>>,
+}
+
+#[derive(Debug)]
+struct CachedSession {
+    pem: Vec<u8>,
+    // Time of the full handshake; resuming does not extend the session's life
+    established: Instant,
+    cert_modified: Option<SystemTime>,
+}
+
+// Session files for one OpenSSL run, removed when dropped
+struct SessionFiles {
+    target: String,
+    cert_modified: Option<SystemTime>,
+    resumed: Option<(tempfile::NamedTempFile, Instant)>,
+    out: tempfile::NamedTempFile,
+}
+
+impl SessionFiles {
+    fn args(&self) -> Vec<&std::ffi::OsStr> {
+        let mut args = vec!["-sess_out".as_ref(), self.out.path().as_os_str()];
+        if let Some((file, _)) = &self.resumed {
+            args.extend(["-sess_in".as_ref(), file.path().as_os_str()]);
+        }
+        args
+    }
+}
+
+impl SessionCache {
+    // Cache for sessions up to max_age old by the clock; None when resumption is disabled
+    pub fn with_max_age(max_age: Duration, clock: SharedClock) -> Option<Arc<Self>> {
+        (!max_age.is_zero()).then(|| Arc::new(Self { max_age, clock, sessions: Mutex::new(HashMap::new()) }))
+    }
+
+    // Empty cache with the same max age, aging sessions by another clock
+    pub fn with_clock(&self, clock: SharedClock) -> Arc<Self> {
+        Arc::new(Self { max_age: self.max_age, clock, sessions: Mutex::new(HashMap::new()) })
+    }
+
+    // Prepare a run against target, writing out the cached session if it is still usable
+    fn files(&self, target: &str, cert: &str) -> AppResult<SessionFiles> {
+        let file_err = |e: std::io::Error| AppError::Internal(format!("TLS session file error: {}", e));
+        let cert_modified = fs::metadata(cert).and_then(|meta| meta.modified()).ok();
+
+        let now = self.clock.now();
+        let mut sessions = self.sessions.lock().unwrap();
+        let stale = sessions.get(target)
+            .is_some_and(|session| now.duration_since(session.established) >= self.max_age || session.cert_modified != cert_modified);
+        if stale {
+            tracing::debug!("Discarding TLS session for {}; a full handshake follows", target);
+            sessions.remove(target);
+        }
+        let resumed = match sessions.get(target) {
+            Some(session) => {
+                let mut file = tempfile::NamedTempFile::new().map_err(file_err)?;
+                file.write_all(&session.pem).map_err(file_err)?;
+                Some((file, session.established))
+            },
+            None => None,
+        };
+        drop(sessions);
+
+        Ok(SessionFiles {
+            target: target.to_string(),
+            cert_modified,
+            resumed,
+            out: tempfile::NamedTempFile::new().map_err(file_err)?,
+        })
+    }
+
+    // Keep the session a successful run wrote; a failed run drops the target's session
+    fn finish(&self, files: SessionFiles, success: bool) {
+        let mut sessions = self.sessions.lock().unwrap();
+        let pem = fs::read(files.out.path()).unwrap_or_default();
+        if !success || pem.is_empty() {
+            if files.resumed.is_some() {
+                sessions.remove(&files.target);
+            }
+            return;
+        }
+        let established = files.resumed.map_or_else(|| self.clock.now(), |(_, established)| established);
+        sessions.insert(files.target, CachedSession { pem, established, cert_modified: files.cert_modified });
+    }
+}
+
+// Describe an mTLS failure from OpenSSL output, telling apart a client certificate
+// rejected by the peer (TLS alerts) from a peer certificate failing our CA check
+pub fn describe_tls_failure(output: &str) -> String {
+    const CLIENT_CERT_ALERTS: [&str; 5] = [
+        "alert number 42",  // bad_certificate
+        "alert number 44",  // certificate_revoked
+        "alert number 45",  // certificate_expired
+        "alert number 48",  // unknown_ca
+        "alert number 116", // certificate_required
+    ];
+
+    if let Some(alert) = CLIENT_CERT_ALERTS.iter().find(|alert| output.contains(*alert)) {
+        return format!(
+            "Proxy rejected the gateway client certificate ({}); check CLIENT_CERT_PATH and CLIENT_KEY_PATH",
+            alert
+        );
+    }
+
+    if let Some(line) = output.lines().find(|line| line.contains("verify error")) {
+        // A certificate for another name passes the CA check but not the name check
+        let hint = if line.contains("hostname mismatch") || line.contains("IP address mismatch") {
+            "check TLS_SNI or the proxy URL host"
+        } else {
+            "check CA_CERT_PATH"
+        };
+        return format!("Proxy certificate failed verification ({}); {}", line.trim(), hint);
+    }
+
+    format!("TLS connection failed: {}", output.trim())
+}
+
+// Wait for OpenSSL to exit, killing it once the timeout elapses
+fn wait_with_timeout(mut child: Child, timeout: Duration) -> AppResult<Output> {
+    fn spawn_reader<R: Read + Send + 'static>(mut reader: R) -> thread::JoinHandle<Vec<u8>> {
+        thread::spawn(move || {
+            let mut buf = Vec::new();
+            let _ = reader.read_to_end(&mut buf);
+            buf
+        })
+    }
+
+    // Drain pipes in the background so the child never blocks on a full buffer
+    let stdout = child.stdout.take().map(spawn_reader);
+    let stderr = child.stderr.take().map(spawn_reader);
+    let start = Instant::now();
+
+    let status = loop {
+        match child.try_wait() {
+            Ok(Some(status)) => break status,
+            Ok(None) if start.elapsed() >= timeout => {
+                let _ = child.kill();
+                let _ = child.wait();
+                return Err(AppError::Timeout(format!(
+                    "OpenSSL did not finish within {} ms", timeout.as_millis()
+                )));
+            },
+            Ok(None) => thread::sleep(Duration::from_millis(10)),
+            Err(e) => return Err(AppError::Internal(format!("OpenSSL error: {}", e))),
+        }
+    };
+
+    let collect = |handle: Option<thread::JoinHandle<Vec<u8>>>| {
+        handle.and_then(|h| h.join().ok()).unwrap_or_default()
+    };
+
+    Ok(Output { status, stdout: collect(stdout), stderr: collect(stderr) })
+}
+
+// Negotiated connection details reported as tls_info
+#[derive(Debug, Clone, Serialize)]
+pub struct TlsInfo {
+    pub connection: String,
+    pub protocol: String,
+    pub cipher: String,
+    pub key_exchange: String,
+    pub sni: Option<String>,
+    pub offered_groups: Vec<String>,
+    pub negotiated_group: Option<String>,
+    pub signature_type: String,
+    pub pqc_enabled: bool,
+    // The peer sent renegotiation or unexpected post-handshake messages
+    pub renegotiation_attempted: bool,
+    pub security_assessment: SecurityAssessment,
+    pub certificates: TlsCertificates,
+    pub openssl_version: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub handshake_ms: Option<u64>,
+    // Whether the session was resumed, when OpenSSL reports it
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub resumed: Option<bool>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub warning: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub debug_trace: Option<String>,
+}
+
+// Single verdict over the handshake for dashboards to alert on
+#[derive(Debug, Clone, Serialize)]
+pub struct SecurityAssessment {
+    // PQC was offered but classical key exchange was negotiated
+    pub pqc_downgraded: bool,
+    // The negotiated cipher suite is not a full-strength AEAD
+    pub weak_cipher: bool,
+    // The proxy's certificate expires within CERT_EXPIRY_WARNING_DAYS
+    pub cert_expiring_soon: bool,
+    // A: no findings; B: weak cipher or expiring certificate; C: downgrade or renegotiation
+    pub grade: char,
+}
+
+// Days before expiry at which the proxy certificate is reported
+const CERT_EXPIRY_WARNING_DAYS: u32 = 30;
+
+// AEAD cipher suites, in IANA (TLS_AES_128_GCM_SHA256) or OpenSSL (ECDHE-RSA-AES128-GCM-SHA256)
+// naming. Anything else (CBC, RC4, NULL, ...) is weak, as is CCM with a truncated tag.
+const AEAD_CIPHER_MARKERS: [&str; 3] = ["GCM", "CHACHA20", "CCM"];
+const TRUNCATED_TAG_MARKERS: [&str; 2] = ["CCM_8", "CCM8"];
+
+impl SecurityAssessment {
+    fn assess(cipher: &str, pqc_enabled: bool, renegotiation_attempted: bool, peer_cert: Option<&openssl::x509::X509>) -> Self {
+        let pqc_downgraded = !pqc_enabled;
+        let weak_cipher = cipher != "unknown"
+            && (!AEAD_CIPHER_MARKERS.iter().any(|marker| cipher.contains(marker))
+                || TRUNCATED_TAG_MARKERS.iter().any(|marker| cipher.contains(marker)));
+        let cert_expiring_soon = peer_cert.and_then(|cert| expires_within(cert, CERT_EXPIRY_WARNING_DAYS)).unwrap_or(false);
+
+        let grade = if pqc_downgraded || renegotiation_attempted {
+            'C'
+        } else if weak_cipher || cert_expiring_soon {
+            'B'
+        } else {
+            'A'
+        };
+
+        Self { pqc_downgraded, weak_cipher, cert_expiring_soon, grade }
+    }
+}
+
+// Whether the certificate expires within the given number of days
+fn expires_within(cert: &openssl::x509::X509, days: u32) -> Option<bool> {
+    let threshold = openssl::asn1::Asn1Time::days_from_now(days).ok()?;
+    Some(cert.not_after() < threshold)
+}
+
+// The proxy's certificate, printed by s_client under "Server certificate"
+fn peer_certificate(output: &str) -> Option<openssl::x509::X509> {
+    const END: &str = "-----END CERTIFICATE-----";
+    let start = output.find("-----BEGIN CERTIFICATE-----")?;
+    let end = output[start..].find(END)? + start + END.len();
+    openssl::x509::X509::from_pem(&output.as_bytes()[start..end]).ok()
+}
+
+// Client identity and trust anchor used for the connection
+#[derive(Debug, Clone, Serialize)]
+pub struct TlsCertificates {
+    pub client: String,
+    pub ca: String,
+}
+
+// Whether a key exchange group is a post-quantum hybrid
+fn is_pqc_group(group: &str) -> bool {
+    group.to_ascii_uppercase().contains("MLKEM")
+}
+
+// One-line summary of the negotiated parameters, for the startup self-test log
+pub fn summarize(info: &TlsInfo) -> String {
+    format!(
+        "{} {} group={} (pqc: {}) cipher={} signature={} client_cert={} ca={}",
+        info.connection, info.protocol,
+        info.negotiated_group.as_deref().unwrap_or("unknown"),
+        if info.pqc_enabled { "yes" } else { "NO" },
+        info.cipher, info.signature_type, info.certificates.client, info.certificates.ca,
+    )
+}
+
+// Handshake messages a TLS 1.3 server may legitimately send after its Finished
+const POST_HANDSHAKE_ALLOWED: [&str; 2] = ["NewSessionTicket", "KeyUpdate"];
+
+// Scan -msg output for renegotiation: a HelloRequest, s_client's RENEGOTIATING notice,
+// or any server handshake message after its Finished other than tickets and key updates
+// (e.g. a post-handshake CertificateRequest)
+fn detect_renegotiation(output: &str) -> bool {
+    let mut finished = false;
+    for line in output.lines() {
+        if line.contains("RENEGOTIATING") {
+            return true;
+        }
+
+        // Lines read like "<<< TLS 1.3, Handshake [length 0004], Finished"
+        let Some(message) = line.strip_prefix("<<< ")
+            .filter(|line| line.contains(", Handshake ["))
+            .and_then(|line| line.rsplit(", ").next())
+            .map(str::trim) else { continue };
+
+        if message == "HelloRequest" || (finished && !POST_HANDSHAKE_ALLOWED.contains(&message)) {
+            return true;
+        }
+        finished |= message == "Finished";
+    }
+    false
+}
+
+// Lines naming the negotiated protocol version, in -brief and full output
+const PROTOCOL_PATTERNS: [&str; 2] = ["Protocol version:", "Protocol:"];
+
+// Value after the colon on the first line containing one of the patterns, tried in order
+fn extract_value(output: &str, patterns: &[&str]) -> String {
+    for pattern in patterns {
+        for line in output.lines() {
+            if line.contains(pattern) {
+                if let Some(pos) = line.find(':') {
+                    let value = line[pos+1..].trim();
+                    if !value.is_empty() {
+                        return value.to_string();
+                    }
+                }
+            }
+        }
+    }
+    "unknown".to_string()
+}
+
+// Negotiated cipher suite, trimmed to its IANA name when OpenSSL prints one
+fn negotiated_cipher(output: &str) -> String {
+    let cipher = extract_value(output, &["Ciphersuite:", "Cipher is", "Cipher:"]);
+    match cipher.find("TLS_") {
+        Some(pos) if cipher != "unknown" => cipher[pos..].trim().to_string(),
+        _ => cipher,
+    }
+}
+
+// OpenSSL only offers the allowed versions and suites; refuse anything else should a
+// peer pick it
+fn check_negotiated(config: &TlsConfig, host: &str, port: u16, protocol: &str, cipher: &str) -> AppResult<()> {
+    if let Some(version) = TlsVersion::parse(protocol).filter(|version| *version < config.min_version) {
+        tracing::error!("{}:{} negotiated {} below TLS_MIN_VERSION {}", host, port, version.name(), config.min_version.name());
+        return Err(AppError::Internal(format!("TLS downgrade: negotiated {} but at least {} is required", version.name(), config.min_version.name())));
+    }
+
+    if !config.ciphersuites.is_empty() && cipher != "unknown" && !config.ciphersuites.iter().any(|suite| suite == cipher) {
+        tracing::error!("{}:{} negotiated cipher suite {} outside TLS_CIPHERSUITES", host, port, cipher);
+        return Err(AppError::Internal(format!("Negotiated cipher suite {} is not allowed", cipher)));
+    }
+    Ok(())
+}
+
+// Enforce TLS_MIN_VERSION, TLS_CIPHERSUITES and the renegotiation ban on a request's
+// own handshake, given s_client's -brief summary and -msg trace
+pub fn verify_handshake(config: &TlsConfig, host: &str, port: u16, output: &str) -> AppResult<()> {
+    check_negotiated(config, host, port, &extract_value(output, &PROTOCOL_PATTERNS), &negotiated_cipher(output))?;
+
+    if detect_renegotiation(output) {
+        tracing::error!("{}:{} attempted renegotiation or sent unexpected post-handshake messages", host, port);
+        return Err(AppError::Internal(format!("{}:{} attempted renegotiation, which TLS 1.3 forbids", host, port)));
+    }
+    Ok(())
+}
+
+// Maximum size of a captured handshake trace
+const MAX_DEBUG_TRACE_BYTES: usize = 16 * 1024;
+
+// Get TLS connection information; verbose mode also captures the full handshake trace
+pub fn get_tls_info(config: &TlsConfig, host: &str, port: u16, timeout: Option<Duration>, verbose: bool) -> AppResult<TlsInfo> {
+    let config = config.clone().with_timeout(timeout);
+    // -msg lists handshake messages, needed to spot renegotiation attempts. The full
+    // (not -brief) output includes the proxy's certificate.
+    let args: &[&str] = if verbose { &["-msg", "-debug"] } else { &["-msg"] };
+    let started = Instant::now();
+    let (output, offered_groups) = config.run_negotiated(host, port, args, None)?;
+    let handshake_ms = started.elapsed().as_millis() as u64;
 
     // Combine standard output and error output
     let tls_output = format!("{}\n{}",
@@ -94,54 +679,550 @@ pub fn get_tls_info(host: &str, port: u16) -> AppResult<Value> {
         format!("error: {}", String::from_utf8_lossy(&output.stderr))
     };
 
-    // General function to extract values
-    let extract_value = |patterns: &[&str]| -> String {
-        for pattern in patterns {
-            for line in tls_output.lines() {
-                if line.contains(pattern) {
-                    if let Some(pos) = line.find(':') {
-                        let value = line[pos+1..].trim();
-                        if !value.is_empty() {
-                            return value.to_string();
-                        }
-                    }
-                }
-            }
-        }
-        "unknown".to_string()
+    let protocol = extract_value(&tls_output, &PROTOCOL_PATTERNS);
+    let cipher = negotiated_cipher(&tls_output);
+    check_negotiated(&config, host, port, &protocol, &cipher)?;
+
+    // Extract key exchange information
+    let key_exchange = extract_value(&tls_output, &["Negotiated TLS1.3 group:", "Server Temp Key:"]);
+
+    // Structured group details; "Server Temp Key" reads like "X25519, 253 bits"
+    let negotiated_group = Some(&key_exchange)
+        .filter(|group| *group != "unknown")
+        .and_then(|group| group.split(',').next())
+        .map(|group| group.trim().to_string());
+    let offered_groups: Vec<String> = offered_groups.split(':').map(str::to_string).collect();
+    let pqc_enabled = match &negotiated_group {
+        Some(group) => is_pqc_group(group),
+        None => offered_groups.iter().all(|group| is_pqc_group(group)),
     };
 
-    // Extract TLS protocol version
-    let protocol = extract_value(&["Protocol version:", "Protocol:"]);
+    // Extract signature type information
+    let signature_type = extract_value(&tls_output, &["Signature type:", "Peer signature type:"]);
+
+    // s_client derives the server name from the host unless it is an IP address
+    let sni = config.sni.clone().or_else(|| host.parse::<IpAddr>().is_err().then(|| host.to_string()));
+
+    // Full (non-brief) output starts the session summary with "New," or "Reused,"
+    let resumed = tls_output.lines().find_map(|line| match line.trim_start() {
+        line if line.starts_with("Reused,") => Some(true),
+        line if line.starts_with("New,") => Some(false),
+        _ => None,
+    });
+
+    let renegotiation_attempted = detect_renegotiation(&tls_output);
+    if renegotiation_attempted {
+        tracing::error!("{}:{} attempted renegotiation or sent unexpected post-handshake messages", host, port);
+    }
+
+    let warning = if renegotiation_attempted {
+        Some("Peer attempted renegotiation, which TLS 1.3 forbids".to_string())
+    } else {
+        (!pqc_enabled).then(|| "Classical key exchange: this connection is not quantum-safe".to_string())
+    };
+
+    let security_assessment = SecurityAssessment::assess(&cipher, pqc_enabled, renegotiation_attempted, peer_certificate(&tls_output).as_ref());
+
+    let debug_trace = verbose.then(|| {
+        let mut end = tls_output.len().min(MAX_DEBUG_TRACE_BYTES);
+        while !tls_output.is_char_boundary(end) { end -= 1; }
+        tls_output[..end].to_string()
+    });
+
+    Ok(TlsInfo {
+        connection: connection_status,
+        protocol,
+        cipher,
+        key_exchange,
+        sni,
+        offered_groups,
+        negotiated_group,
+        signature_type,
+        pqc_enabled,
+        renegotiation_attempted,
+        security_assessment,
+        certificates: TlsCertificates { client: config.cert.clone(), ca: config.ca.clone() },
+        openssl_version: config.version(),
+        handshake_ms: Some(handshake_ms),
+        resumed,
+        warning,
+        debug_trace,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::clock::TestClock;
+    use crate::service::ServiceType;
+    use crate::test_support::{self, CapturedLogs, StubOpenssl};
+
+    #[test]
+    fn rejected_client_certificate_points_at_client_cert() {
+        let output = "4087F1A2C27F0000:error:0A000412:SSL routines:ssl3_read_bytes:sslv3 alert bad certificate:ssl/record/rec_layer_s3.c:1605:SSL alert number 42";
+        let message = describe_tls_failure(output);
+        assert!(message.contains("rejected the gateway client certificate"), "{}", message);
+        assert!(message.contains("CLIENT_CERT_PATH"));
+
+        let unknown_ca = describe_tls_failure("SSL routines:ssl3_read_bytes:tlsv1 alert unknown ca:SSL alert number 48");
+        assert!(unknown_ca.contains("alert number 48") && unknown_ca.contains("CLIENT_CERT_PATH"), "{}", unknown_ca);
+    }
+
+    #[test]
+    fn failed_peer_verification_points_at_ca() {
+        let message = describe_tls_failure(&test_support::verify_failure("num=18:self-signed certificate"));
+        assert_eq!(message, "Proxy certificate failed verification (verify error:num=18:self-signed certificate); check CA_CERT_PATH");
+
+        let mismatch = describe_tls_failure(&test_support::verify_failure("num=62:hostname mismatch"));
+        assert!(mismatch.contains("hostname mismatch") && mismatch.ends_with("check TLS_SNI or the proxy URL host"), "{}", mismatch);
+    }
+
+    #[test]
+    fn other_failures_keep_the_output() {
+        let message = describe_tls_failure("connect:errno=111\n");
+        assert_eq!(message, "TLS connection failed: connect:errno=111");
+    }
 
-    // Extract cipher suite information
-    let mut cipher = extract_value(&["Ciphersuite:", "Cipher is", "Cipher:"]);
+    // Peer without the PQC group: offering it fails the handshake, classical groups succeed
+    const CLASSICAL_ONLY_PEER: &str = r#"
+case "$*" in *"-groups X25519MLKEM768"*) echo 'SSL routines:ssl3_read_bytes:sslv3 alert handshake failure:SSL alert number 40' >&2; exit 1;; esac
+"#;
 
-    // Clean up cipher suite value
-    if cipher != "unknown" && cipher.contains("TLS_") {
-        if let Some(pos) = cipher.find("TLS_") {
-            cipher = cipher[pos..].trim().to_string();
+    const CLASSICAL_SUMMARY: &str = "CONNECTION ESTABLISHED\nProtocol version: TLSv1.3\nCiphersuite: TLS_AES_256_GCM_SHA384\nNegotiated TLS1.3 group: X25519\n";
+
+    fn stub_config(stub: &StubOpenssl, vars: &[(&str, &str)]) -> TlsConfig {
+        let path = stub.path();
+        let vars = [&[("OPENSSL_PATH", path.as_str())], vars].concat();
+        test_support::config(&vars).tls_for(ServiceType::Log).clone()
+    }
+
+    #[test]
+    fn group_failure_is_retried_with_classical_groups_when_allowed() {
+        let stub = StubOpenssl::new(b"", CLASSICAL_SUMMARY).script(CLASSICAL_ONLY_PEER);
+        let config = stub_config(&stub, &[("TLS_ALLOW_CLASSICAL_FALLBACK", "true")]);
+        let (logs, _guard) = CapturedLogs::start();
+
+        let info = get_tls_info(&config, "proxy", 8443, None, false).unwrap();
+
+        let connections = stub.connections();
+        assert_eq!(connections.len(), 2);
+        assert!(connections[1].contains("-groups X25519:P-256"), "{}", connections[1]);
+        assert_eq!(info.connection, "success");
+        assert_eq!(info.offered_groups, ["X25519", "P-256"]);
+        assert_eq!(info.negotiated_group.as_deref(), Some("X25519"));
+        assert!(!info.pqc_enabled);
+        assert!(info.warning.unwrap().contains("not quantum-safe"));
+        assert!(logs.contents().contains("WARN") && logs.contents().contains("CLASSICAL key exchange"), "{}", logs.contents());
+    }
+
+    #[test]
+    fn group_failure_fails_cleanly_without_the_flag() {
+        let stub = StubOpenssl::new(b"", CLASSICAL_SUMMARY).script(CLASSICAL_ONLY_PEER);
+        let config = stub_config(&stub, &[]);
+
+        let info = get_tls_info(&config, "proxy", 8443, None, false).unwrap();
+
+        assert_eq!(stub.connections().len(), 1);
+        assert!(info.connection.starts_with("error:") && info.connection.contains("alert number 40"), "{}", info.connection);
+    }
+
+    #[test]
+    fn other_handshake_failures_are_not_downgraded() {
+        let stub = StubOpenssl::new(b"", CLASSICAL_SUMMARY)
+            .script(&format!("printf '%s' '{}' >&2; exit 1", test_support::verify_failure("num=18:self-signed certificate")));
+        let config = stub_config(&stub, &[("TLS_ALLOW_CLASSICAL_FALLBACK", "true")]);
+
+        let (output, offered) = config.run_negotiated("proxy", 8443, &["-brief"], None).unwrap();
+
+        assert!(!output.status.success());
+        assert_eq!(offered, PQC_GROUPS);
+        assert_eq!(stub.connections().len(), 1);
+    }
+
+    #[test]
+    fn offered_and_negotiated_groups_are_parsed_from_the_handshake() {
+        let stub = StubOpenssl::new(b"", test_support::HANDSHAKE_SUMMARY);
+        let info = get_tls_info(&stub_config(&stub, &[]), "proxy", 8443, None, false).unwrap();
+        assert_eq!(info.offered_groups, ["X25519MLKEM768"]);
+        assert_eq!(info.negotiated_group.as_deref(), Some("X25519MLKEM768"));
+        assert!(info.pqc_enabled);
+
+        // Full output names the group in the "Server Temp Key" line instead
+        let stub = StubOpenssl::new(b"---\nServer Temp Key: X25519, 253 bits\n---\n", "");
+        let info = get_tls_info(&stub_config(&stub, &[]), "proxy", 8443, None, false).unwrap();
+        assert_eq!(info.key_exchange, "X25519, 253 bits");
+        assert_eq!(info.negotiated_group.as_deref(), Some("X25519"));
+        assert!(!info.pqc_enabled);
+    }
+
+    #[test]
+    fn configured_sni_is_passed_as_servername() {
+        let stub = StubOpenssl::responding(b"");
+        let config = stub_config(&stub, &[("TLS_SNI", "proxy.internal")]);
+
+        let info = get_tls_info(&config, "10.0.0.7", 8443, None, false).unwrap();
+
+        assert!(stub.connections()[0].contains("-connect 10.0.0.7:8443"));
+        assert!(stub.connections()[0].contains("-servername proxy.internal"), "{}", stub.connections()[0]);
+        assert_eq!(info.sni.as_deref(), Some("proxy.internal"));
+    }
+
+    #[test]
+    fn per_service_sni_overrides_the_global_one() {
+        let stub = StubOpenssl::responding(b"");
+        let path = stub.path();
+        let config = test_support::config(&[("OPENSSL_PATH", &path), ("TLS_SNI", "proxy.internal"), ("PAYMENT_TLS_SNI", "payments.internal")]);
+
+        assert_eq!(config.tls_for(ServiceType::Payment).sni.as_deref(), Some("payments.internal"));
+        assert_eq!(config.tls_for(ServiceType::Log).sni.as_deref(), Some("proxy.internal"));
+    }
+
+    #[test]
+    fn no_servername_is_sent_for_an_ip_without_sni() {
+        let stub = StubOpenssl::responding(b"");
+        let info = get_tls_info(&stub_config(&stub, &[]), "10.0.0.7", 8443, None, false).unwrap();
+
+        assert!(!stub.connections()[0].contains("-servername"));
+        assert_eq!(info.sni, None);
+    }
+
+    #[test]
+    fn proxy_certificate_verification_is_fatal_unless_skipped() {
+        let stub = StubOpenssl::responding(b"");
+        stub_config(&stub, &[("CA_CERT_PATH", "/certs/ca.crt")]).run("proxy", 8443, &["-brief"], None).unwrap();
+        stub_config(&stub, &[]).run("10.0.0.7", 8443, &["-brief"], None).unwrap();
+        stub_config(&stub, &[("TLS_SNI", "proxy.internal")]).run("10.0.0.7", 8443, &["-brief"], None).unwrap();
+        stub_config(&stub, &[("INSECURE_SKIP_VERIFY", "true")]).run("proxy", 8443, &["-brief"], None).unwrap();
+
+        let connections = stub.connections();
+        assert!(connections[0].contains("-CAfile /certs/ca.crt -verify_return_error -verify_hostname proxy"), "{}", connections[0]);
+        assert!(connections[1].contains("-verify_return_error -verify_ip 10.0.0.7"), "{}", connections[1]);
+        assert!(connections[2].contains("-verify_return_error -verify_hostname proxy.internal"), "{}", connections[2]);
+        for arg in ["-CAfile", "-verify"] {
+            assert!(!connections[3].contains(arg), "{}", connections[3]);
         }
     }
 
-    // Extract key exchange information
-    let key_exchange = extract_value(&["Negotiated TLS1.3 group:", "Server Temp Key:"]);
+    #[test]
+    fn configured_ciphersuites_reach_the_openssl_args() {
+        let stub = StubOpenssl::responding(b"");
+        let config = stub_config(&stub, &[("TLS_CIPHERSUITES", "TLS_AES_256_GCM_SHA384 : TLS_CHACHA20_POLY1305_SHA256")]);
 
-    // Extract signature type information
-    let signature_type = extract_value(&["Signature type:"]);
-
-    // Create JSON-formatted TLS information
-    Ok(serde_json::json!({
-        "connection": connection_status,
-        "protocol": protocol,
-        "cipher": cipher,
-        "key_exchange": key_exchange,
-        "signature_type": signature_type,
-        "pqc_enabled": true,
-        "certificates": {
-            "client": config.cert,
-            "ca": config.ca
-        },
-        "openssl_version": config.version()
-    }))
+        get_tls_info(&config, "proxy", 8443, None, false).unwrap();
+
+        assert!(stub.connections()[0].contains("-ciphersuites TLS_AES_256_GCM_SHA384:TLS_CHACHA20_POLY1305_SHA256"), "{}", stub.connections()[0]);
+    }
+
+    #[test]
+    fn suite_outside_the_allowlist_is_rejected() {
+        // The sample handshake negotiates TLS_AES_256_GCM_SHA384
+        let stub = StubOpenssl::responding(b"");
+        let config = stub_config(&stub, &[("TLS_CIPHERSUITES", "TLS_AES_128_GCM_SHA256")]);
+
+        let result = get_tls_info(&config, "proxy", 8443, None, false);
+
+        assert!(matches!(result, Err(AppError::Internal(msg)) if msg.contains("TLS_AES_256_GCM_SHA384 is not allowed")));
+    }
+
+    #[test]
+    fn missing_openssl_binary_gives_an_actionable_error() {
+        let config = test_support::config(&[("OPENSSL_PATH", "/nonexistent/bin/openssl")]).tls_for(ServiceType::Log).clone();
+
+        let result = config.run("proxy", 8443, &["-brief"], None);
+
+        assert!(
+            matches!(&result, Err(AppError::Internal(msg)) if msg.contains("OpenSSL not found at \"/nonexistent/bin/openssl\"") && msg.contains("set OPENSSL_PATH")),
+            "{:?}", result.err()
+        );
+    }
+
+    #[test]
+    fn tls_info_serializes_to_the_existing_keys() {
+        let stub = StubOpenssl::responding(b"");
+        let info = get_tls_info(&stub_config(&stub, &[]), "proxy", 8443, None, false).unwrap();
+
+        let json = serde_json::to_value(&info).unwrap();
+
+        // Keys clients relied on before the struct existed keep their names and types
+        assert_eq!(json["connection"], "success");
+        assert_eq!(json["protocol"], "TLSv1.3");
+        assert_eq!(json["cipher"], "TLS_AES_256_GCM_SHA384");
+        assert_eq!(json["key_exchange"], "X25519MLKEM768");
+        assert_eq!(json["signature_type"], "mldsa65");
+        assert_eq!(json["pqc_enabled"], true);
+        assert_eq!(json["certificates"]["client"], info.certificates.client);
+        assert_eq!(json["certificates"]["ca"], info.certificates.ca);
+        assert_eq!(json["openssl_version"], "OpenSSL 3.5.0 (stub)");
+        assert!(json["handshake_ms"].is_u64());
+        // Optional fields are left out rather than null
+        for absent in ["resumed", "warning", "debug_trace"] {
+            assert!(json.get(absent).is_none(), "{} present in {}", absent, json);
+        }
+    }
+
+    // Peer issuing a session ticket on every connection
+    const ISSUES_SESSIONS: &str = r#"
+for arg in "$@"; do [ "$out" = next ] && echo 'SSL-Session' > "$arg"; out=; [ "$arg" = -sess_out ] && out=next; done
+"#;
+
+    fn resumed(stub: &StubOpenssl) -> Vec<bool> {
+        stub.connections().iter().map(|args| args.contains("-sess_in")).collect()
+    }
+
+    #[test]
+    fn session_older_than_the_max_age_is_not_reused() {
+        let stub = StubOpenssl::responding(b"").script(ISSUES_SESSIONS);
+        let clock = TestClock::new();
+        let path = stub.path();
+        let config = test_support::config(&[("OPENSSL_PATH", &path), ("TLS_SESSION_MAX_AGE_SECS", "300")])
+            .with_clock(clock.clone())
+            .tls_for(ServiceType::Log)
+            .clone();
+
+        for _ in 0..2 {
+            assert!(config.run_resuming("proxy", 8443, &["-quiet"], Some(b"")).unwrap().status.success());
+        }
+        clock.advance(Duration::from_secs(300));
+        for _ in 0..2 {
+            config.run_resuming("proxy", 8443, &["-quiet"], Some(b"")).unwrap();
+        }
+
+        // The expired session forces a full handshake, whose new session is then reused
+        assert_eq!(resumed(&stub), [false, true, false, true]);
+        assert!(stub.connections().iter().all(|args| args.contains("-sess_out")));
+    }
+
+    #[test]
+    fn rotated_client_certificate_drops_cached_sessions() {
+        let stub = StubOpenssl::responding(b"").script(ISSUES_SESSIONS);
+        let cert = tempfile::NamedTempFile::new().unwrap();
+        let cert_path = cert.path().display().to_string();
+        let config = stub_config(&stub, &[("CLIENT_CERT_PATH", &cert_path), ("TLS_SESSION_MAX_AGE_SECS", "300")]);
+
+        config.run_resuming("proxy", 8443, &["-quiet"], Some(b"")).unwrap();
+        config.run_resuming("proxy", 8443, &["-quiet"], Some(b"")).unwrap();
+        cert.as_file().set_modified(SystemTime::now() + Duration::from_secs(60)).unwrap();
+        config.run_resuming("proxy", 8443, &["-quiet"], Some(b"")).unwrap();
+
+        assert_eq!(resumed(&stub), [false, true, false]);
+    }
+
+    #[test]
+    fn resumption_is_off_unless_a_max_age_is_set() {
+        let stub = StubOpenssl::responding(b"").script(ISSUES_SESSIONS);
+        for vars in [&[][..], &[("TLS_SESSION_MAX_AGE_SECS", "0")]] {
+            let config = stub_config(&stub, vars);
+            config.run_resuming("proxy", 8443, &["-quiet"], Some(b"")).unwrap();
+            config.run_resuming("proxy", 8443, &["-quiet"], Some(b"")).unwrap();
+        }
+
+        assert_eq!(stub.connections().len(), 4);
+        assert!(stub.connections().iter().all(|args| !args.contains("-sess_")));
+    }
+
+    #[test]
+    fn services_with_different_client_certificates_do_not_share_sessions() {
+        let stub = StubOpenssl::responding(b"").script(ISSUES_SESSIONS);
+        let path = stub.path();
+        let config = test_support::config(&[
+            ("OPENSSL_PATH", &path),
+            ("TLS_SESSION_MAX_AGE_SECS", "300"),
+            ("PAYMENT_CLIENT_CERT_PATH", "/certs/payment.crt"),
+            ("PAYMENT_CLIENT_KEY_PATH", "/certs/payment.key"),
+        ]);
+        let (logs, payments) = (config.tls_for(ServiceType::Log), config.tls_for(ServiceType::Payment));
+
+        logs.run_resuming("proxy", 8443, &["-quiet"], Some(b"")).unwrap();
+        payments.run_resuming("proxy", 8443, &["-quiet"], Some(b"")).unwrap();
+        logs.run_resuming("proxy", 8443, &["-quiet"], Some(b"")).unwrap();
+        payments.run_resuming("proxy", 8443, &["-quiet"], Some(b"")).unwrap();
+
+        // Each service resumes only the session set up with its own certificate
+        assert_eq!(resumed(&stub), [false, false, true, true]);
+        let connections = stub.connections();
+        assert!(connections[3].contains("-cert /certs/payment.crt"), "{}", connections[3]);
+    }
+
+    #[test]
+    fn subprocess_environment_excludes_gateway_secrets() {
+        // The check runs in a child test process given the secret, leaving this
+        // process's environment (shared by parallel tests) untouched
+        let output = Command::new(std::env::current_exe().unwrap())
+            .args(["tls::tests::subprocess_environment_in_child", "--exact", "--ignored", "--nocapture"])
+            .env("JWT_SECRET", "gateway-secret")
+            .env("TLS_TEST_PASSTHROUGH", "kept")
+            .output()
+            .unwrap();
+
+        let stdout = String::from_utf8_lossy(&output.stdout);
+        assert!(output.status.success() && stdout.contains("1 passed"), "{}{}", stdout, String::from_utf8_lossy(&output.stderr));
+    }
+
+    #[test]
+    #[ignore = "run by subprocess_environment_excludes_gateway_secrets with a controlled environment"]
+    fn subprocess_environment_in_child() {
+        assert_eq!(std::env::var("JWT_SECRET").as_deref(), Ok("gateway-secret"), "run through subprocess_environment_excludes_gateway_secrets");
+        let stub = StubOpenssl::responding(b"");
+        let config = stub_config(&stub, &[("OPENSSL_ENV_PASSTHROUGH", "TLS_TEST_PASSTHROUGH")]);
+
+        config.run("proxy", 8443, &["-brief"], None).unwrap();
+
+        let env = stub.env();
+        assert!(!env.contains("JWT_SECRET") && !env.contains("gateway-secret"), "{}", env);
+        assert!(env.lines().any(|line| line.starts_with("PATH=")), "{}", env);
+        assert!(env.lines().any(|line| line == "TLS_TEST_PASSTHROUGH=kept"), "{}", env);
+    }
+
+    // -msg trace of a TLS 1.3 handshake, followed by the given server messages
+    fn handshake_trace(after_finished: &[&str]) -> String {
+        let mut trace = [
+            ">>> TLS 1.3, Handshake [length 0300], ClientHello",
+            "<<< TLS 1.3, Handshake [length 0480], ServerHello",
+            "<<< TLS 1.3, Handshake [length 0016], EncryptedExtensions",
+            "<<< TLS 1.3, Handshake [length 1400], Certificate",
+            "<<< TLS 1.3, Handshake [length 0d00], CertificateVerify",
+            "<<< TLS 1.3, Handshake [length 0034], Finished",
+            ">>> TLS 1.3, Handshake [length 0034], Finished",
+        ].join("\n");
+        for message in after_finished {
+            trace.push_str(&format!("\n<<< TLS 1.3, Handshake [length 0040], {}", message));
+        }
+        trace
+    }
+
+    #[test]
+    fn post_handshake_messages_are_flagged_unless_allowed() {
+        assert!(!detect_renegotiation(&handshake_trace(&[])));
+        assert!(!detect_renegotiation(&handshake_trace(&["NewSessionTicket", "NewSessionTicket", "KeyUpdate"])));
+        assert!(detect_renegotiation(&handshake_trace(&["NewSessionTicket", "CertificateRequest"])));
+        assert!(detect_renegotiation("<<< TLS 1.2, Handshake [length 0004], HelloRequest"));
+        assert!(detect_renegotiation("RENEGOTIATING\n"));
+    }
+
+    #[test]
+    fn renegotiation_is_surfaced_in_tls_info() {
+        let stderr = format!("{}{}", test_support::HANDSHAKE_SUMMARY, handshake_trace(&["CertificateRequest"]));
+        let stub = StubOpenssl::new(b"", &stderr);
+
+        let info = get_tls_info(&stub_config(&stub, &[]), "proxy", 8443, None, false).unwrap();
+
+        assert!(info.renegotiation_attempted);
+        assert_eq!(info.security_assessment.grade, 'C');
+        assert!(info.warning.unwrap().contains("renegotiation"));
+    }
+
+    #[test]
+    fn summary_names_the_negotiated_parameters() {
+        let stub = StubOpenssl::responding(b"");
+        let config = stub_config(&stub, &[("CLIENT_CERT_PATH", "/certs/client.crt"), ("CA_CERT_PATH", "/certs/ca.crt")]);
+        let info = get_tls_info(&config, "proxy", 8443, None, false).unwrap();
+
+        assert_eq!(
+            summarize(&info),
+            "success TLSv1.3 group=X25519MLKEM768 (pqc: yes) cipher=TLS_AES_256_GCM_SHA384 signature=mldsa65 client_cert=/certs/client.crt ca=/certs/ca.crt",
+        );
+
+        // A classical handshake stands out
+        let stub = StubOpenssl::new(b"", CLASSICAL_SUMMARY);
+        let summary = summarize(&get_tls_info(&stub_config(&stub, &[]), "proxy", 8443, None, false).unwrap());
+        assert!(summary.contains("group=X25519 (pqc: NO)") && summary.contains("signature=unknown"), "{}", summary);
+    }
+
+    #[test]
+    fn disabled_connection_reuse_performs_a_full_handshake_every_time() {
+        let stub = StubOpenssl::responding(b"").script(ISSUES_SESSIONS);
+        let config = stub_config(&stub, &[("TLS_SESSION_MAX_AGE_SECS", "300"), ("DISABLE_CONNECTION_REUSE", "true")]);
+
+        config.run_resuming("proxy", 8443, &["-quiet"], Some(b"")).unwrap();
+        config.run_resuming("proxy", 8443, &["-quiet"], Some(b"")).unwrap();
+
+        assert_eq!(resumed(&stub), [false, false]);
+    }
+
+    #[test]
+    fn invalid_min_version_is_rejected() {
+        assert!(test_support::try_config(&[("TLS_MIN_VERSION", "1.1")]).is_err());
+        assert_eq!(test_support::config(&[("TLS_MIN_VERSION", "TLSv1.2")]).tls.min_version, TlsVersion::Tls12);
+    }
+
+    #[test]
+    fn request_handshake_below_the_minimum_version_is_refused() {
+        let tls12 = test_support::HANDSHAKE_SUMMARY.replace("TLSv1.3", "TLSv1.2");
+        let strict = test_support::config(&[]).tls;
+
+        let err = verify_handshake(&strict, "proxy", 8443, &tls12).unwrap_err();
+        assert!(matches!(&err, AppError::Internal(message) if message.contains("TLS downgrade")), "{:?}", err);
+        assert!(verify_handshake(&strict, "proxy", 8443, test_support::HANDSHAKE_SUMMARY).is_ok());
+        assert!(verify_handshake(&test_support::config(&[("TLS_MIN_VERSION", "1.2")]).tls, "proxy", 8443, &tls12).is_ok());
+    }
+
+    #[test]
+    fn request_handshake_with_renegotiation_is_refused() {
+        let config = test_support::config(&[]).tls;
+        let output = |after_finished: &[&str]| format!("{}{}", test_support::HANDSHAKE_SUMMARY, handshake_trace(after_finished));
+
+        assert!(verify_handshake(&config, "proxy", 8443, &output(&["NewSessionTicket"])).is_ok());
+        assert!(verify_handshake(&config, "proxy", 8443, &output(&["CertificateRequest"])).is_err());
+    }
+
+    // s_client's "Server certificate" block for a self-signed proxy certificate valid for the given days
+    fn server_certificate(days: u32) -> String {
+        use openssl::{asn1::Asn1Time, ec::{EcGroup, EcKey}, hash::MessageDigest, nid::Nid, pkey::PKey, x509::{X509NameBuilder, X509}};
+        let key = PKey::from_ec_key(EcKey::generate(&EcGroup::from_curve_name(Nid::X9_62_PRIME256V1).unwrap()).unwrap()).unwrap();
+        let mut name = X509NameBuilder::new().unwrap();
+        name.append_entry_by_text("CN", "proxy").unwrap();
+        let name = name.build();
+        let mut cert = X509::builder().unwrap();
+        cert.set_subject_name(&name).unwrap();
+        cert.set_issuer_name(&name).unwrap();
+        cert.set_pubkey(&key).unwrap();
+        cert.set_not_before(&Asn1Time::days_from_now(0).unwrap()).unwrap();
+        cert.set_not_after(&Asn1Time::days_from_now(days).unwrap()).unwrap();
+        cert.sign(&key, MessageDigest::sha256()).unwrap();
+        format!("Server certificate\n{}subject=CN=proxy\n", String::from_utf8(cert.build().to_pem().unwrap()).unwrap())
+    }
+
+    fn assess_output(stdout: &str, stderr: &str, vars: &[(&str, &str)]) -> SecurityAssessment {
+        let stub = StubOpenssl::new(stdout.as_bytes(), stderr);
+        get_tls_info(&stub_config(&stub, vars), "proxy", 8443, None, false).unwrap().security_assessment
+    }
+
+    #[test]
+    fn classical_negotiation_is_a_downgrade_graded_c() {
+        let assessment = assess_output(&server_certificate(365), CLASSICAL_SUMMARY, &[]);
+        assert!(assessment.pqc_downgraded);
+        assert_eq!(assessment.grade, 'C');
+
+        let assessment = assess_output(&server_certificate(365), test_support::HANDSHAKE_SUMMARY, &[]);
+        assert!(!assessment.pqc_downgraded && !assessment.weak_cipher && !assessment.cert_expiring_soon);
+        assert_eq!(assessment.grade, 'A');
+    }
+
+    #[test]
+    fn weak_cipher_is_judged_by_the_cipher_not_the_protocol() {
+        let tls12 = |cipher: &str| test_support::HANDSHAKE_SUMMARY
+            .replace("TLSv1.3", "TLSv1.2")
+            .replace("TLS_AES_256_GCM_SHA384", cipher);
+        let allow_tls12 = [("TLS_MIN_VERSION", "1.2")];
+
+        let aead = assess_output("", &tls12("ECDHE-ECDSA-AES256-GCM-SHA384"), &allow_tls12);
+        assert!(!aead.weak_cipher);
+        assert_eq!(aead.grade, 'A');
+
+        for cipher in ["ECDHE-RSA-AES128-SHA256", "TLS_AES_128_CCM_8_SHA256"] {
+            let weak = assess_output("", &tls12(cipher), &allow_tls12);
+            assert!(weak.weak_cipher, "{}", cipher);
+            assert_eq!(weak.grade, 'B');
+        }
+    }
+
+    #[test]
+    fn expiring_proxy_certificate_lowers_the_grade() {
+        let assessment = assess_output(&server_certificate(10), test_support::HANDSHAKE_SUMMARY, &[]);
+        assert!(assessment.cert_expiring_soon);
+        assert_eq!(assessment.grade, 'B');
+
+        // No certificate in the output (e.g. a resumed session) is no finding
+        assert!(!assess_output("", test_support::HANDSHAKE_SUMMARY, &[]).cert_expiring_soon);
+    }
 }
@@ -0,0 +1,52 @@
+use serde::Serialize;
+use axum::Json;
+
+use crate::jwt::JWT_ALGORITHM;
+use crate::service::ServiceType;
+use crate::tls::PQC_GROUPS;
+use crate::webauthn::RP_ID;
+
+// Capabilities of this gateway build, for clients and ops tooling
+#[derive(Debug, Serialize)]
+pub struct VersionInfo {
+    pub version: &'static str,
+    // Set at build time through the GIT_SHA environment variable
+    pub git_sha: Option<&'static str>,
+    pub pqc_groups: Vec<&'static str>,
+    pub jwt_alg: jsonwebtoken::Algorithm,
+    pub services: Vec<&'static str>,
+    pub webauthn_rp_id: &'static str,
+}
+
+// Report version and capabilities
+pub async fn version_info() -> Json<VersionInfo> {
+    Json(VersionInfo {
+        version: env!("CARGO_PKG_VERSION"),
+        git_sha: option_env!("GIT_SHA"),
+        pqc_groups: PQC_GROUPS.split(':').collect(),
+        jwt_alg: JWT_ALGORITHM,
+        services: ServiceType::ALL.iter().map(ServiceType::name).collect(),
+        webauthn_rp_id: RP_ID,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+    use crate::test_support;
+
+    #[tokio::test]
+    async fn version_reports_services_and_rp_id() {
+        let (router, _) = test_support::app(test_support::config(&[]));
+
+        let response = test_support::send(&router, test_support::get("/version", &[])).await;
+
+        assert_eq!(response.status, 200);
+        let body = response.json();
+        assert_eq!(body["services"], json!(["log", "users", "payment"]));
+        assert_eq!(body["webauthn_rp_id"], "localhost");
+        assert_eq!(body["version"], env!("CARGO_PKG_VERSION"));
+        assert_eq!(body["pqc_groups"], json!(["X25519MLKEM768"]));
+        assert_eq!(body["jwt_alg"], "HS256");
+    }
+}
@@ -1,39 +1,317 @@
-use axum::{routing::post, Router, Json, Extension};
-use serde::{Deserialize, Serialize};
-use std::{collections::HashMap, sync::{Arc, Mutex}};
+use axum::{body::Bytes, extract::{ConnectInfo, Path, Query}, http::HeaderMap, routing::{get, patch, post}, Router, Json, Extension};
+use chrono::{DateTime, Utc};
+use serde::{de::DeserializeOwned, Deserialize, Serialize};
+use std::{collections::HashMap, net::SocketAddr, sync::{atomic::Ordering, Arc, Mutex}, time::Duration};
 use uuid::Uuid;
 use webauthn_rs::prelude::*;
-use crate::{error::{AppError, AppResult}, jwt};
+use crate::{admin, api_response::ResponseStatus, auth::RequiredAuthToken, challenge::ChallengeStore, client_ip, clock::SharedClock, config::Config, error::{AppError, AppResult}, jwt::{self, ClientBinding}, metrics, pagination::{self, Page, PageQuery}};
 
 // Data models
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct User {
     pub id: String,
     pub name: String,
-    pub credentials: Vec<Passkey>,
+    pub credentials: Vec<StoredCredential>,
+    // Admin-granted override of MAX_CREDENTIALS_PER_USER
+    #[serde(default)]
+    pub credential_quota: Option<usize>,
 }
 
-#[derive(Debug, Deserialize)] pub struct RegisterRequest { pub username: String }
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct StoredCredential {
+    pub id: String,
+    pub passkey: Passkey,
+    pub created_at: DateTime<Utc>,
+    // Authenticator model, when the attestation carried one
+    #[serde(default)]
+    pub aaguid: Option<Uuid>,
+    #[serde(default)]
+    pub attestation_format: String,
+}
+
+impl StoredCredential {
+    // Wrap a verified passkey, recording its attestation details for audit
+    pub fn new(passkey: Passkey, created_at: DateTime<Utc>) -> Self {
+        let credential = Credential::from(passkey.clone());
+        let aaguid = match &credential.attestation.metadata {
+            AttestationMetadata::Packed { aaguid } | AttestationMetadata::Tpm { aaguid, .. } => Some(*aaguid),
+            _ => None,
+        };
+        let attestation_format = serde_json::to_value(credential.attestation_format)
+            .ok()
+            .and_then(|format| format.as_str().map(str::to_string))
+            .unwrap_or_default();
+
+        Self { id: Uuid::new_v4().to_string(), passkey, created_at, aaguid, attestation_format }
+    }
+}
+
+#[derive(Debug, Serialize)]
+pub struct CredentialInfo {
+    pub id: String,
+    pub credential_id: serde_json::Value,
+    pub created_at: String,
+    pub backup_eligible: bool,
+    pub backup_state: bool,
+}
+
+#[derive(Debug, Deserialize)] pub struct RegisterRequest { pub username: String, pub display_name: Option<String>, pub external_id: Option<String> }
 #[derive(Debug, Serialize)] pub struct RegisterResponse { pub public_key: serde_json::Value, pub user_id: String }
 #[derive(Debug, Deserialize)] pub struct FinishRegisterRequest { pub username: String, pub credential: RegisterPublicKeyCredential }
 #[derive(Debug, Deserialize)] pub struct LoginRequest { pub username: String }
 #[derive(Debug, Serialize)] pub struct LoginResponse { pub public_key: serde_json::Value }
 #[derive(Debug, Deserialize)] pub struct FinishLoginRequest { pub username: String, pub credential: PublicKeyCredential }
-#[derive(Debug, Serialize)] pub struct FinishLoginResponse { pub token: String }
+#[derive(Debug, Serialize)]
+#[serde(untagged)]
+pub enum FinishLoginResponse {
+    Token { token: String },
+    // Verify-only mode: the caller mints its own session
+    Verified { verified: bool, user_id: String, username: String },
+}
+
+#[derive(Debug, Deserialize)] pub struct FinishStepUpRequest { pub credential: PublicKeyCredential }
+#[derive(Debug, Serialize)] pub struct StepUpResponse { pub token: String, pub expires_in: u64 }
+
+#[derive(Debug, Deserialize)] pub struct RenameRequest { pub username: String }
+#[derive(Debug, Serialize)] pub struct RenameResponse { pub user_id: String, pub username: String }
+
+#[derive(Debug, Deserialize)] pub struct CredentialQuotaRequest { pub quota: Option<usize> }
+#[derive(Debug, Serialize)] pub struct CredentialQuotaResponse { pub username: String, pub quota: Option<usize>, pub effective_quota: Option<usize>, pub credentials: usize }
+
+#[derive(Debug, Deserialize)] pub struct ImportCredentialRequest { pub username: String, pub passkey: serde_json::Value }
+#[derive(Debug, Serialize)] pub struct ImportCredentialResult { pub username: String, pub status: ResponseStatus, pub error: Option<String> }
+
+#[derive(Debug, Serialize)] pub struct CredentialListResponse { pub credentials: Vec<CredentialInfo>, pub next_cursor: Option<String> }
+
+#[derive(Debug, Serialize)]
+pub struct AdminCredentialInfo {
+    pub id: String,
+    pub user_id: String,
+    pub username: String,
+    pub credential_id: serde_json::Value,
+    pub created_at: String,
+    pub aaguid: Option<Uuid>,
+    pub attestation_format: String,
+}
+
+#[derive(Debug, Serialize)] pub struct AdminCredentialListResponse { pub credentials: Vec<AdminCredentialInfo>, pub next_cursor: Option<String> }
+
+// Relying party ID; passkeys are scoped to it
+pub const RP_ID: &str = "localhost";
 
 // Storage types
-type UserStore = Arc<Mutex<HashMap<String, User>>>;
-type RegistrationStateStore = Arc<Mutex<HashMap<String, PasskeyRegistration>>>;
-type AuthenticationStateStore = Arc<Mutex<HashMap<String, PasskeyAuthentication>>>;
+pub type UserStore = Arc<Mutex<HashMap<String, User>>>;
+type RegistrationStateStore = Arc<Mutex<ChallengeStore<PasskeyRegistration>>>;
+type AuthenticationStateStore = Arc<Mutex<ChallengeStore<PasskeyAuthentication>>>;
+
+// Outstanding registration and authentication challenges, shared with the sweeper and metrics
+#[derive(Clone)]
+pub struct ChallengeStates {
+    registrations: RegistrationStateStore,
+    authentications: AuthenticationStateStore,
+}
+
+impl ChallengeStates {
+    pub fn new(config: &Config) -> Self {
+        Self::with_clock(config, Arc::clone(&config.clock))
+    }
+
+    // States aged by the given clock rather than the system one
+    pub fn with_clock(config: &Config, clock: SharedClock) -> Self {
+        let (max_pending, max_per_ip, ttl) = (config.max_pending_challenges, config.max_pending_challenges_per_ip, config.challenge_ttl);
+        Self {
+            registrations: Arc::new(Mutex::new(ChallengeStore::new(max_pending, max_per_ip, ttl, Arc::clone(&clock)))),
+            authentications: Arc::new(Mutex::new(ChallengeStore::new(max_pending, max_per_ip, ttl, clock))),
+        }
+    }
+
+    // Outstanding (registration, authentication) states
+    pub fn pending(&self) -> (usize, usize) {
+        (
+            lock_err(self.registrations.lock()).map(|store| store.count()).unwrap_or_default(),
+            lock_err(self.authentications.lock()).map(|store| store.count()).unwrap_or_default(),
+        )
+    }
+
+    // Drop expired states from both stores, returning how many were removed
+    fn sweep(&self) -> usize {
+        let registrations = lock_err(self.registrations.lock()).map(|mut store| store.sweep()).unwrap_or_default();
+        let authentications = lock_err(self.authentications.lock()).map(|mut store| store.sweep()).unwrap_or_default();
+        registrations + authentications
+    }
+}
+
+// Sweep expired challenge states in the background at the given interval
+pub fn spawn_state_sweeper(states: ChallengeStates, interval: Duration) {
+    tokio::spawn(async move {
+        let mut ticker = tokio::time::interval(interval);
+        loop {
+            ticker.tick().await;
+
+            let swept = states.sweep();
+            if swept > 0 {
+                metrics::SWEPT_CHALLENGES.fetch_add(swept as u64, Ordering::Relaxed);
+                tracing::debug!("Swept {} expired challenge states", swept);
+            }
+        }
+    });
+}
+
+// User ID strategy
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum UserIdStrategy {
+    // Generate a random UUID as user ID and user handle
+    Generated,
+    // Use a caller-supplied external ID (e.g. from an upstream IdP) as user handle
+    External,
+}
+
+impl UserIdStrategy {
+    // Resolve user handle for a new user
+    pub fn resolve(&self, external_id: Option<&str>) -> AppResult<Uuid> {
+        match self {
+            Self::Generated => Ok(Uuid::new_v4()),
+            Self::External => {
+                let external_id = external_id.map(str::trim).filter(|id| !id.is_empty())
+                    .ok_or_else(|| AppError::Authentication("External user ID is required".to_string()))?;
+
+                Uuid::parse_str(external_id)
+                    .map_err(|_| AppError::Authentication("External user ID must be a 16-byte handle".to_string()))
+            }
+        }
+    }
+}
 
 // Utility functions
 fn lock_err<T, E>(result: Result<T, E>) -> AppResult<T> {
     result.map_err(|_| AppError::Internal("Lock failed".to_string()))
 }
 
-fn validate_and_find_user<'a>(store: &'a mut HashMap<String, User>, username: &str) -> AppResult<&'a mut User> {
+// Verify the credential's user handle matches the user. Without a handle the
+// assertion cannot be tied to the account, so it is rejected unless allowed.
+fn verify_user_handle(user: &User, credential: &PublicKeyCredential, require: bool) -> AppResult<()> {
+    let Some(user_handle) = &credential.response.user_handle else {
+        if require {
+            return Err(AppError::Authentication("Credential user handle is required".to_string()));
+        }
+        tracing::warn!("Accepting assertion without user handle for {} (REQUIRE_USER_HANDLE=false)", user.name);
+        return Ok(());
+    };
+
+    let expected_uuid = Uuid::parse_str(&user.id)
+        .map_err(|_| AppError::Internal("UUID parse failed".to_string()))?;
+
+    let credential_user_handle = Uuid::from_slice(user_handle)
+        .map_err(|_| AppError::Authentication("Invalid credential user handle".to_string()))?;
+
+    if credential_user_handle != expected_uuid {
+        return Err(AppError::Authentication("User handle does not match".to_string()));
+    }
+
+    Ok(())
+}
+
+// Insert a new user, rejecting duplicate usernames and IDs
+fn insert_unique_user(store: &mut HashMap<String, User>, user: User) -> AppResult<()> {
+    if store.values().any(|u| u.name == user.name) {
+        return Err(AppError::Authentication("Username already exists".to_string()));
+    }
+    if store.contains_key(&user.id) {
+        return Err(AppError::Authentication("User ID already exists".to_string()));
+    }
+
+    store.insert(user.id.clone(), user);
+    Ok(())
+}
+
+// Get backup eligibility and backup state flags of a credential
+fn backup_flags(passkey: &Passkey) -> (bool, bool) {
+    let credential = Credential::from(passkey.clone());
+    (credential.backup_eligible, credential.backup_state)
+}
+
+// Authenticator response fields required before a credential reaches webauthn-rs
+const REGISTER_RESPONSE_FIELDS: [&str; 2] = ["attestationObject", "clientDataJSON"];
+const LOGIN_RESPONSE_FIELDS: [&str; 3] = ["authenticatorData", "clientDataJSON", "signature"];
+
+// Client-reported errors meaning the user cancelled the ceremony
+const CANCELLED_ERRORS: [&str; 2] = ["NotAllowedError", "AbortError"];
+
+// Parse a finish request, rejecting oversized or incomplete credentials before
+// they are handed to the (comparatively expensive) verification
+fn parse_finish_request<T: DeserializeOwned>(body: &[u8], max_bytes: usize, response_fields: &[&str]) -> AppResult<T> {
+    if body.len() > max_bytes {
+        return Err(AppError::BadRequest(format!("Credential payload exceeds {} bytes", max_bytes)));
+    }
+
+    let value: serde_json::Value = serde_json::from_slice(body)
+        .map_err(|e| AppError::BadRequest(format!("Malformed credential payload: {}", e)))?;
+
+    // The browser's DOMException name when the user dismissed or aborted the prompt
+    if let Some(name) = value.get("error").and_then(|e| e.as_str()).filter(|name| CANCELLED_ERRORS.contains(name)) {
+        return Err(AppError::UserCancelled(format!("Authenticator prompt was cancelled ({})", name)));
+    }
+    let present = |object: &serde_json::Value, field: &str| object.get(field).is_some_and(|v| !v.is_null());
+    let missing = |field: &str| AppError::BadRequest(format!("Missing credential field: {}", field));
+
+    let credential = value.get("credential").filter(|c| c.is_object()).ok_or_else(|| missing("credential"))?;
+    if let Some(field) = ["id", "rawId", "type", "response"].into_iter().find(|field| !present(credential, field)) {
+        return Err(missing(field));
+    }
+    if let Some(field) = response_fields.iter().find(|field| !present(&credential["response"], field)) {
+        return Err(missing(&format!("response.{}", field)));
+    }
+
+    serde_json::from_value(value).map_err(|e| AppError::BadRequest(format!("Invalid credential payload: {}", e)))
+}
+
+// Update the asserting credential's counter. Callers hold the store lock since the
+// user was read, and the counter is checked against the stored credential rather
+// than the start snapshot, so concurrent assertions cannot replay a counter or roll it back.
+fn record_assertion(user: &mut User, auth_result: &AuthenticationResult) -> AppResult<()> {
+    let credential = user.credentials.iter_mut()
+        .find(|c| c.passkey.cred_id() == auth_result.cred_id())
+        .ok_or_else(|| AppError::Authentication("Credential not found".to_string()))?;
+    let stored_counter = Credential::from(credential.passkey.clone()).counter;
+    if (auth_result.counter() > 0 || stored_counter > 0) && auth_result.counter() <= stored_counter {
+        tracing::warn!(target: "audit", "Rejected assertion for {}: counter {} not above stored {}", user.name, auth_result.counter(), stored_counter);
+        return Err(AppError::Authentication("Credential counter did not increase".to_string()));
+    }
+    credential.passkey.update_credential(auth_result);
+    Ok(())
+}
+
+// Reject adding a credential beyond the user's quota, or the global limit when none is set
+fn check_credential_quota(user: &User, max_per_user: Option<usize>) -> AppResult<()> {
+    match user.credential_quota.or(max_per_user) {
+        Some(quota) if user.credentials.len() >= quota => {
+            Err(AppError::BadRequest(format!("Credential limit of {} reached for {}", quota, user.name)))
+        },
+        _ => Ok(()),
+    }
+}
+
+// Verify a bearer token, including its client binding, and return its user ID
+fn token_subject(config: &Config, token: &str, headers: &HeaderMap) -> AppResult<String> {
+    let claims = jwt::verify_jwt(&config.jwt, token)?;
+    jwt::verify_client(&claims, headers)?;
+    Ok(claims.sub)
+}
+
+// Step-up ceremonies share the authentication store, keyed apart from logins
+fn step_up_key(user_id: &str) -> String {
+    format!("step-up:{}", user_id)
+}
+
+// Normalize a submitted username
+fn normalize_username(username: &str) -> AppResult<&str> {
     let username = username.trim();
     if username.is_empty() { return Err(AppError::Authentication("Username cannot be empty".to_string())); }
+    Ok(username)
+}
+
+fn validate_and_find_user<'a>(store: &'a mut HashMap<String, User>, username: &str) -> AppResult<&'a mut User> {
+    let username = normalize_username(username)?;
 
     store.values_mut()
         .find(|u| u.name == username)
@@ -41,19 +319,19 @@ fn validate_and_find_user<'a>(store: &'a mut HashMap<String, User>, username: &s
 }
 
 // Route setup
-pub fn routes(webauthn: Arc<Webauthn>) -> Router {
-    let user_store: UserStore = Arc::new(Mutex::new(HashMap::new()));
-    let registration_state_store: RegistrationStateStore = Arc::new(Mutex::new(HashMap::new()));
-    let authentication_state_store: AuthenticationStateStore = Arc::new(Mutex::new(HashMap::new()));
-
+pub fn routes(webauthn: Arc<Webauthn>, user_store: UserStore, states: ChallengeStates) -> Router {
     Router::new()
         .route("/register", post(start_register))
         .route("/verify-register", post(finish_register))
         .route("/login", post(start_login))
         .route("/verify-login", post(finish_login))
+        .route("/step-up", post(start_step_up))
+        .route("/verify-step-up", post(finish_step_up))
+        .route("/credentials", get(list_credentials))
+        .route("/account/:username", patch(rename_user))
         .layer(Extension(user_store))
-        .layer(Extension(registration_state_store))
-        .layer(Extension(authentication_state_store))
+        .layer(Extension(states.registrations))
+        .layer(Extension(states.authentications))
         .layer(Extension(webauthn))
 }
 
@@ -62,33 +340,65 @@ async fn start_register(
     Extension(webauthn): Extension<Arc<Webauthn>>,
     Extension(user_store): Extension<UserStore>,
     Extension(registration_state_store): Extension<RegistrationStateStore>,
+    Extension(config): Extension<Arc<Config>>,
+    ConnectInfo(addr): ConnectInfo<SocketAddr>,
+    headers: HeaderMap,
     Json(req): Json<RegisterRequest>,
 ) -> AppResult<Json<RegisterResponse>> {
-    let username = req.username.trim();
-    if username.is_empty() { return Err(AppError::Authentication("Username cannot be empty".to_string())); }
+    let username = normalize_username(&req.username)?;
 
-    // Check if username already exists and create new user
+    // Hold the store lock from the uniqueness check until the user is inserted,
+    // so concurrent registrations for the same username cannot both succeed
     let mut store = lock_err(user_store.lock())?;
-    if store.values().any(|u| u.name == username) {
-        return Err(AppError::Authentication("Username already exists".to_string()));
-    }
+    let existing = store.values().find(|u| u.name == username);
 
-    // Create user and registration challenge
-    let uuid = Uuid::new_v4();
+    // A user without credentials is a registration that never finished (e.g. the
+    // page was reloaded); resume it under the same user ID instead of adding another
+    let resuming = existing.is_some_and(|u| u.credentials.is_empty());
+    let uuid = match existing {
+        Some(user) if resuming => {
+            let uuid = Uuid::parse_str(&user.id)
+                .map_err(|_| AppError::Internal("UUID parse failed".to_string()))?;
+            if config.user_id_strategy == UserIdStrategy::External && config.user_id_strategy.resolve(req.external_id.as_deref())? != uuid {
+                return Err(AppError::Authentication("Username already exists".to_string()));
+            }
+            uuid
+        },
+        Some(_) => return Err(AppError::Authentication("Username already exists".to_string())),
+        None => config.user_id_strategy.resolve(req.external_id.as_deref())?,
+    };
     let user_id = uuid.to_string();
 
-    store.insert(user_id.clone(), User {
-        id: user_id.clone(),
-        name: username.to_string(),
-        credentials: Vec::new(),
-    });
+    // Display name is shown in authenticator prompts; username stays the account name
+    let display_name = req.display_name.as_deref().map(str::trim).filter(|n| !n.is_empty()).unwrap_or(username);
 
     let (ccr, reg_state) = webauthn
-        .start_passkey_registration(uuid, username, username, None)
+        .start_passkey_registration(uuid, username, display_name, None)
         .map_err(AppError::WebAuthn)?;
 
-    // Store registration state
-    lock_err(registration_state_store.lock())?.insert(user_id.clone(), reg_state);
+    // Create user only once the challenge exists, then store (or refresh) registration state
+    if resuming {
+        tracing::info!("Resuming unfinished registration for {}", username);
+    } else {
+        insert_unique_user(&mut store, User {
+            id: user_id.clone(),
+            name: username.to_string(),
+            credentials: Vec::new(),
+            credential_quota: None,
+        })?;
+    }
+    // A username gets MAX_REGISTRATION_ATTEMPTS_PER_USER starts, from anywhere, while
+    // its registration is pending
+    let stored = lock_err(registration_state_store.lock())?.insert_counted(
+        user_id.clone(), reg_state, client_ip::resolve(&config, &headers, addr), config.max_registration_attempts_per_user,
+    );
+    if let Err(e) = stored {
+        if !resuming {
+            store.remove(&user_id);
+        }
+        return Err(e);
+    }
+    drop(store);
 
     // Convert challenge to JSON
     let ccr_json = serde_json::to_value(&ccr)
@@ -106,12 +416,32 @@ async fn finish_register(
     Extension(webauthn): Extension<Arc<Webauthn>>,
     Extension(user_store): Extension<UserStore>,
     Extension(registration_state_store): Extension<RegistrationStateStore>,
-    Json(req): Json<FinishRegisterRequest>,
+    Extension(config): Extension<Arc<Config>>,
+    ConnectInfo(addr): ConnectInfo<SocketAddr>,
+    headers: HeaderMap,
+    body: Bytes,
 ) -> AppResult<Json<serde_json::Value>> {
-    // Find user
+    let req: FinishRegisterRequest = parse_finish_request(&body, config.max_credential_payload_bytes, &REGISTER_RESPONSE_FIELDS)?;
+
+    // Find user, and whoever already holds the submitted credential
     let mut store = lock_err(user_store.lock())?;
+    let submitted_id: &[u8] = req.credential.raw_id.as_ref();
+    let holds_submitted = |user: &User| user.credentials.iter().any(|c| AsRef::<[u8]>::as_ref(c.passkey.cred_id()) == submitted_id);
+    let holder = store.values().find(|u| holds_submitted(u)).map(|u| u.id.clone());
     let user = validate_and_find_user(&mut store, &req.username)?;
 
+    if holder.as_ref().is_some_and(|holder| *holder != user.id) {
+        tracing::warn!(target: "audit", "Rejected registration for {}: credential belongs to another user", user.name);
+        return Err(AppError::DuplicateCredential("Credential is already registered to another user".to_string()));
+    }
+
+    // A retried finish for a credential we already saved succeeds without touching
+    // any pending session; unknown credentials still need a live session below
+    if holder.is_some() {
+        tracing::info!("Credential already registered for {}, treating finish as a retry", user.name);
+        return Ok(Json(serde_json::json!({"status": ResponseStatus::Success, "message": "Registration successful"})));
+    }
+
     // Get registration state and verify
     let reg_state = lock_err(registration_state_store.lock())?
         .remove(&user.id)
@@ -120,11 +450,27 @@ async fn finish_register(
     // Verify registration and update user credentials
     let credential = webauthn
         .finish_passkey_registration(&req.credential, &reg_state)
-        .map_err(AppError::WebAuthn)?;
+        .map_err(|e| {
+            metrics::FAILED_REGISTRATIONS.fetch_add(1, Ordering::Relaxed);
+            AppError::WebAuthn(e)
+        })?;
+
+    // Enforce backup eligibility policy
+    if config.require_backup_eligible && !backup_flags(&credential).0 {
+        return Err(AppError::Authentication("Credential must be backup eligible".to_string()));
+    }
+    check_credential_quota(user, config.max_credentials_per_user)?;
 
-    user.credentials.push(credential);
+    let stored = StoredCredential::new(credential, config.clock.utc());
+    tracing::info!(
+        target: "audit",
+        "Registered credential {} for {} from {} (attestation format: {}, aaguid: {})",
+        stored.id, user.name, client_ip::resolve(&config, &headers, addr), stored.attestation_format,
+        stored.aaguid.map_or_else(|| "none".to_string(), |aaguid| aaguid.to_string())
+    );
+    user.credentials.push(stored);
 
-    Ok(Json(serde_json::json!({"status": "success", "message": "Registration successful"})))
+    Ok(Json(serde_json::json!({"status": ResponseStatus::Success, "message": "Registration successful"})))
 }
 
 // Start login
@@ -132,6 +478,9 @@ async fn start_login(
     Extension(webauthn): Extension<Arc<Webauthn>>,
     Extension(user_store): Extension<UserStore>,
     Extension(authentication_state_store): Extension<AuthenticationStateStore>,
+    Extension(config): Extension<Arc<Config>>,
+    ConnectInfo(addr): ConnectInfo<SocketAddr>,
+    headers: HeaderMap,
     Json(req): Json<LoginRequest>,
 ) -> AppResult<Json<LoginResponse>> {
     // Find user
@@ -149,12 +498,13 @@ async fn start_login(
     }
 
     // Create authentication challenge
+    let passkeys: Vec<Passkey> = user.credentials.iter().map(|c| c.passkey.clone()).collect();
     let (auth_challenge, auth_state) = webauthn
-        .start_passkey_authentication(&user.credentials)
+        .start_passkey_authentication(&passkeys)
         .map_err(AppError::WebAuthn)?;
 
     // Store authentication state
-    lock_err(authentication_state_store.lock())?.insert(user.id.clone(), auth_state);
+    lock_err(authentication_state_store.lock())?.insert(user.id.clone(), auth_state, client_ip::resolve(&config, &headers, addr))?;
 
     // Convert challenge to JSON
     let auth_challenge_json = serde_json::to_value(&auth_challenge)
@@ -172,8 +522,12 @@ async fn finish_login(
     Extension(webauthn): Extension<Arc<Webauthn>>,
     Extension(user_store): Extension<UserStore>,
     Extension(authentication_state_store): Extension<AuthenticationStateStore>,
-    Json(req): Json<FinishLoginRequest>,
+    Extension(config): Extension<Arc<Config>>,
+    headers: HeaderMap,
+    body: Bytes,
 ) -> AppResult<Json<FinishLoginResponse>> {
+    let req: FinishLoginRequest = parse_finish_request(&body, config.max_credential_payload_bytes, &LOGIN_RESPONSE_FIELDS)?;
+
     // Find user
     let mut store = lock_err(user_store.lock())?;
     let user = validate_and_find_user(&mut store, &req.username)?;
@@ -189,25 +543,1002 @@ async fn finish_login(
         .map_err(AppError::WebAuthn)?;
 
     // Verify user handle
-    if let Some(user_handle) = &req.credential.response.user_handle {
-        let expected_uuid = Uuid::parse_str(&user.id)
-            .map_err(|_| AppError::Internal("UUID parse failed".to_string()))?;
+    verify_user_handle(user, &req.credential, config.require_user_handle)?;
+
+    // Update credential counter
+    record_assertion(user, &auth_result)?;
+
+    if !config.issue_jwt {
+        return Ok(Json(FinishLoginResponse::Verified { verified: true, user_id: user.id.clone(), username: user.name.clone() }));
+    }
+
+    // Issue JWT token
+    let cnf = config.jwt.bind_to_client.then(|| ClientBinding::from_headers(&headers));
+    let token = jwt::issue_jwt(&config.jwt, &user.id, &user.name, &config.jwt.user_scopes, cnf)?;
+
+    Ok(Json(FinishLoginResponse::Token { token }))
+}
+
+// Start a step-up ceremony for the already authenticated user
+async fn start_step_up(
+    Extension(webauthn): Extension<Arc<Webauthn>>,
+    Extension(user_store): Extension<UserStore>,
+    Extension(authentication_state_store): Extension<AuthenticationStateStore>,
+    Extension(config): Extension<Arc<Config>>,
+    ConnectInfo(addr): ConnectInfo<SocketAddr>,
+    headers: HeaderMap,
+    RequiredAuthToken(token): RequiredAuthToken,
+) -> AppResult<Json<LoginResponse>> {
+    let user_id = token_subject(&config, &token, &headers)?;
+    let store = lock_err(user_store.lock())?;
+    let user = store.get(&user_id)
+        .ok_or_else(|| AppError::Authentication("User not found".to_string()))?;
+
+    if user.credentials.is_empty() {
+        return Err(AppError::Authentication("User has no registered credentials".to_string()));
+    }
+
+    let passkeys: Vec<Passkey> = user.credentials.iter().map(|c| c.passkey.clone()).collect();
+    let (auth_challenge, auth_state) = webauthn
+        .start_passkey_authentication(&passkeys)
+        .map_err(AppError::WebAuthn)?;
+
+    lock_err(authentication_state_store.lock())?.insert(step_up_key(&user.id), auth_state, client_ip::resolve(&config, &headers, addr))?;
+
+    let auth_challenge_json = serde_json::to_value(&auth_challenge)
+        .map_err(|_| AppError::Internal("Serialization failed".to_string()))?;
+
+    Ok(Json(LoginResponse { public_key: auth_challenge_json }))
+}
+
+// Finish a step-up ceremony, issuing a short-lived elevated token
+async fn finish_step_up(
+    Extension(webauthn): Extension<Arc<Webauthn>>,
+    Extension(user_store): Extension<UserStore>,
+    Extension(authentication_state_store): Extension<AuthenticationStateStore>,
+    Extension(config): Extension<Arc<Config>>,
+    headers: HeaderMap,
+    RequiredAuthToken(token): RequiredAuthToken,
+    body: Bytes,
+) -> AppResult<Json<StepUpResponse>> {
+    let user_id = token_subject(&config, &token, &headers)?;
+    let req: FinishStepUpRequest = parse_finish_request(&body, config.max_credential_payload_bytes, &LOGIN_RESPONSE_FIELDS)?;
+
+    let mut store = lock_err(user_store.lock())?;
+    let user = store.get_mut(&user_id)
+        .ok_or_else(|| AppError::Authentication("User not found".to_string()))?;
+
+    let auth_state = lock_err(authentication_state_store.lock())?
+        .remove(&step_up_key(&user.id))
+        .ok_or_else(|| AppError::Authentication("Step-up session expired".to_string()))?;
+
+    let auth_result = webauthn
+        .finish_passkey_authentication(&req.credential, &auth_state)
+        .map_err(AppError::WebAuthn)?;
+
+    verify_user_handle(user, &req.credential, config.require_user_handle)?;
+    record_assertion(user, &auth_result)?;
+
+    tracing::info!(target: "audit", "Step-up authentication for {}", user.name);
+    let cnf = config.jwt.bind_to_client.then(|| ClientBinding::from_headers(&headers));
+    let token = jwt::issue_step_up_jwt(&config.jwt, &user.id, &user.name, cnf, config.step_up_max_age)?;
+
+    Ok(Json(StepUpResponse { token, expires_in: config.step_up_max_age.as_secs() }))
+}
+
+// List the authenticated user's credentials
+async fn list_credentials(
+    Extension(user_store): Extension<UserStore>,
+    Extension(config): Extension<Arc<Config>>,
+    headers: HeaderMap,
+    RequiredAuthToken(token): RequiredAuthToken,
+    Query(query): Query<PageQuery>,
+) -> AppResult<Json<CredentialListResponse>> {
+    // Results are scoped to the token's user, so a cursor can only page within it
+    let user_id = token_subject(&config, &token, &headers)?;
+    let credentials = lock_err(user_store.lock())?
+        .get(&user_id)
+        .map(|user| user.credentials.clone())
+        .ok_or_else(|| AppError::Authentication("User not found".to_string()))?;
+
+    let Page { items, next_cursor } = pagination::paginate(
+        credentials,
+        |c| (c.created_at, c.id.clone()),
+        &query,
+    )?;
+
+    let credentials = items.into_iter()
+        .map(|c| {
+            let (backup_eligible, backup_state) = backup_flags(&c.passkey);
+            CredentialInfo {
+                credential_id: serde_json::to_value(c.passkey.cred_id()).unwrap_or_default(),
+                created_at: c.created_at.to_rfc3339(),
+                id: c.id,
+                backup_eligible,
+                backup_state,
+            }
+        })
+        .collect();
+
+    Ok(Json(CredentialListResponse { credentials, next_cursor }))
+}
+
+// Change the authenticated user's username. Credentials are bound to the user
+// handle (the ID), not the name, so they stay valid under the new name.
+async fn rename_user(
+    Extension(user_store): Extension<UserStore>,
+    Extension(config): Extension<Arc<Config>>,
+    headers: HeaderMap,
+    RequiredAuthToken(token): RequiredAuthToken,
+    Path(username): Path<String>,
+    Json(req): Json<RenameRequest>,
+) -> AppResult<Json<RenameResponse>> {
+    let user_id = token_subject(&config, &token, &headers)?;
+    let new_name = normalize_username(&req.username)?;
+
+    let mut store = lock_err(user_store.lock())?;
+    if store.values().any(|u| u.name == new_name && u.id != user_id) {
+        return Err(AppError::Authentication("Username already exists".to_string()));
+    }
+
+    let user = validate_and_find_user(&mut store, &username)?;
+    if user.id != user_id {
+        return Err(AppError::Authentication("Cannot rename another user".to_string()));
+    }
+
+    tracing::info!(target: "audit", "Renamed user {} from {} to {}", user.id, user.name, new_name);
+    user.name = new_name.to_string();
+
+    Ok(Json(RenameResponse { user_id: user.id.clone(), username: user.name.clone() }))
+}
+
+// Import credentials exported from another WebAuthn implementation (admin only).
+// Rows are validated and inserted independently; failures are reported per row.
+pub async fn import_credentials(
+    Extension(user_store): Extension<UserStore>,
+    Extension(config): Extension<Arc<Config>>,
+    headers: HeaderMap,
+    Json(rows): Json<Vec<ImportCredentialRequest>>,
+) -> AppResult<Json<serde_json::Value>> {
+    admin::require_admin(&config, &headers)?;
+
+    let mut store = lock_err(user_store.lock())?;
+    let results: Vec<ImportCredentialResult> = rows.into_iter()
+        .map(|row| {
+            let username = row.username.trim().to_string();
+            let error = import_credential(&mut store, &username, row.passkey, config.max_credentials_per_user, config.clock.utc()).err().map(|e| e.to_string());
+            ImportCredentialResult { username, status: if error.is_none() { ResponseStatus::Success } else { ResponseStatus::Error }, error }
+        })
+        .collect();
+    drop(store);
+
+    let imported = results.iter().filter(|r| r.error.is_none()).count();
+    tracing::info!("Imported {} of {} credentials", imported, results.len());
+
+    Ok(Json(serde_json::json!({
+        "imported": imported,
+        "failed": results.len() - imported,
+        "results": results,
+    })))
+}
+
+// Validate one imported passkey and add it to the user, creating the user if needed.
+// Every check runs before the store changes, so a rejected row leaves no trace.
+fn import_credential(store: &mut HashMap<String, User>, username: &str, passkey: serde_json::Value, max_per_user: Option<usize>, created_at: DateTime<Utc>) -> AppResult<()> {
+    if username.is_empty() { return Err(AppError::BadRequest("Username cannot be empty".to_string())); }
+
+    let passkey: Passkey = serde_json::from_value(passkey)
+        .map_err(|e| AppError::BadRequest(format!("Malformed passkey: {}", e)))?;
+
+    // Credential IDs must be unique across all users
+    if store.values().flat_map(|u| &u.credentials).any(|c| c.passkey.cred_id() == passkey.cred_id()) {
+        return Err(AppError::DuplicateCredential("Credential ID already exists".to_string()));
+    }
+
+    let credential = StoredCredential::new(passkey, created_at);
+    match store.values_mut().find(|u| u.name == username) {
+        Some(user) => {
+            check_credential_quota(user, max_per_user)?;
+            user.credentials.push(credential);
+            Ok(())
+        },
+        None => {
+            let mut user = User { id: Uuid::new_v4().to_string(), name: username.to_string(), credentials: Vec::new(), credential_quota: None };
+            check_credential_quota(&user, max_per_user)?;
+            user.credentials.push(credential);
+            insert_unique_user(store, user)
+        },
+    }
+}
+
+// Set or clear (with a null quota) a user's credential quota (admin only)
+pub async fn set_credential_quota(
+    Extension(user_store): Extension<UserStore>,
+    Extension(config): Extension<Arc<Config>>,
+    headers: HeaderMap,
+    Path(username): Path<String>,
+    Json(req): Json<CredentialQuotaRequest>,
+) -> AppResult<Json<CredentialQuotaResponse>> {
+    admin::require_admin(&config, &headers)?;
+
+    let mut store = lock_err(user_store.lock())?;
+    let user = validate_and_find_user(&mut store, &username)?;
+
+    tracing::info!(target: "audit", "Set credential quota of {} to {:?}", user.name, req.quota);
+    user.credential_quota = req.quota;
+
+    Ok(Json(CredentialQuotaResponse {
+        username: user.name.clone(),
+        quota: user.credential_quota,
+        effective_quota: user.credential_quota.or(config.max_credentials_per_user),
+        credentials: user.credentials.len(),
+    }))
+}
+
+// List credentials of all users with their attestation details (admin only)
+pub async fn admin_list_credentials(
+    Extension(user_store): Extension<UserStore>,
+    Extension(config): Extension<Arc<Config>>,
+    headers: HeaderMap,
+    Query(query): Query<PageQuery>,
+) -> AppResult<Json<AdminCredentialListResponse>> {
+    admin::require_admin(&config, &headers)?;
+
+    let credentials: Vec<(String, String, StoredCredential)> = lock_err(user_store.lock())?
+        .values()
+        .flat_map(|user| user.credentials.iter().map(|c| (user.id.clone(), user.name.clone(), c.clone())))
+        .collect();
+
+    let Page { items, next_cursor } = pagination::paginate(
+        credentials,
+        |(_, _, c)| (c.created_at, c.id.clone()),
+        &query,
+    )?;
 
-        let credential_user_handle = Uuid::from_slice(user_handle)
-            .map_err(|_| AppError::Authentication("Invalid credential user handle".to_string()))?;
+    let credentials = items.into_iter()
+        .map(|(user_id, username, c)| AdminCredentialInfo {
+            credential_id: serde_json::to_value(c.passkey.cred_id()).unwrap_or_default(),
+            created_at: c.created_at.to_rfc3339(),
+            id: c.id,
+            user_id,
+            username,
+            aaguid: c.aaguid,
+            attestation_format: c.attestation_format,
+        })
+        .collect();
 
-        if credential_user_handle != expected_uuid {
-            return Err(AppError::Authentication("User handle does not match".to_string()));
+    Ok(Json(AdminCredentialListResponse { credentials, next_cursor }))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{future::Future, net::Ipv4Addr};
+    use axum::http::Method;
+    use serde_json::json;
+
+    use super::*;
+    use crate::test_support::{self, TestResponse};
+
+    async fn start_register(router: &Router, body: serde_json::Value) -> TestResponse {
+        test_support::send(router, test_support::json_request(Method::POST, "/auth/register", &[], &body)).await
+    }
+
+    // Registration start from the given client address
+    fn start_register_from(router: &Router, ip: Ipv4Addr, body: serde_json::Value) -> impl Future<Output = TestResponse> {
+        let mut request = test_support::json_request(Method::POST, "/auth/register", &[], &body);
+        request.extensions_mut().insert(ConnectInfo(SocketAddr::from((ip, 50000))));
+        test_support::send(router, request)
+    }
+
+    // Assertion carrying only the given user handle; enough for the handle check
+    fn assertion(user_handle: Option<&[u8]>) -> PublicKeyCredential {
+        serde_json::from_value(json!({
+            "id": "AAAA",
+            "rawId": "AAAA",
+            "type": "public-key",
+            "extensions": {},
+            "response": {
+                "authenticatorData": "AAAA",
+                "clientDataJSON": "AAAA",
+                "signature": "AAAA",
+                "userHandle": user_handle.map(base64_url),
+            },
+        }))
+        .expect("valid assertion")
+    }
+
+    fn base64_url(data: &[u8]) -> String {
+        serde_json::to_value(Base64UrlSafeData::from(data.to_vec())).unwrap().as_str().unwrap().to_string()
+    }
+
+    fn user(id: Uuid) -> User {
+        User { id: id.to_string(), name: "alice".to_string(), credentials: Vec::new(), credential_quota: None }
+    }
+
+    #[test]
+    fn generated_strategy_ignores_external_id() {
+        let external = Uuid::new_v4();
+        let resolved = UserIdStrategy::Generated.resolve(Some(&external.to_string())).unwrap();
+        assert_ne!(resolved, external);
+    }
+
+    #[test]
+    fn external_strategy_requires_a_16_byte_handle() {
+        let external = Uuid::new_v4();
+        assert_eq!(UserIdStrategy::External.resolve(Some(&external.to_string())).unwrap(), external);
+        assert!(UserIdStrategy::External.resolve(None).is_err());
+        assert!(UserIdStrategy::External.resolve(Some("  ")).is_err());
+        assert!(UserIdStrategy::External.resolve(Some("idp-user-42")).is_err());
+    }
+
+    #[tokio::test]
+    async fn register_uses_generated_user_id() {
+        let (router, state) = test_support::app(test_support::config(&[]));
+
+        let response = start_register(&router, json!({"username": "alice"})).await;
+
+        assert_eq!(response.status, 200);
+        let user_id = response.json()["user_id"].as_str().unwrap().to_string();
+        assert!(Uuid::parse_str(&user_id).is_ok());
+        assert_eq!(state.user_store.lock().unwrap()[&user_id].name, "alice");
+    }
+
+    #[tokio::test]
+    async fn register_uses_external_user_id() {
+        let (router, state) = test_support::app(test_support::config(&[("USER_ID_STRATEGY", "external")]));
+        let external = Uuid::new_v4();
+
+        let response = start_register(&router, json!({"username": "alice", "external_id": external.to_string()})).await;
+
+        assert_eq!(response.status, 200);
+        assert_eq!(response.json()["user_id"], external.to_string());
+        assert!(state.user_store.lock().unwrap().contains_key(&external.to_string()));
+
+        let missing = start_register(&router, json!({"username": "bob"})).await;
+        assert_eq!(missing.status, 401);
+    }
+
+    #[tokio::test]
+    async fn resumed_registration_rejects_a_different_external_id() {
+        let (router, _) = test_support::app(test_support::config(&[("USER_ID_STRATEGY", "external")]));
+        let external = Uuid::new_v4().to_string();
+        assert_eq!(start_register(&router, json!({"username": "alice", "external_id": external})).await.status, 200);
+
+        let other = start_register(&router, json!({"username": "alice", "external_id": Uuid::new_v4().to_string()})).await;
+
+        assert_eq!(other.status, 401);
+        assert_eq!(other.json()["message"], "Username already exists");
+    }
+
+    #[test]
+    fn user_handle_must_match_user_id() {
+        let id = Uuid::new_v4();
+        assert!(verify_user_handle(&user(id), &assertion(Some(id.as_bytes())), true).is_ok());
+
+        let mismatch = verify_user_handle(&user(id), &assertion(Some(Uuid::new_v4().as_bytes())), true);
+        assert!(matches!(mismatch, Err(AppError::Authentication(msg)) if msg == "User handle does not match"));
+    }
+
+    #[tokio::test(flavor = "multi_thread", worker_threads = 2)]
+    async fn concurrent_registrations_for_one_username_create_one_user() {
+        let (router, state) = test_support::app(test_support::config(&[]));
+
+        let starts = [Ipv4Addr::new(198, 51, 100, 1), Ipv4Addr::new(198, 51, 100, 2)].map(|ip| {
+            tokio::spawn(start_register_from(&router, ip, json!({"username": "alice"})))
+        });
+        let mut statuses = Vec::new();
+        for start in starts {
+            statuses.push(start.await.unwrap().status);
         }
+
+        assert_eq!(statuses.iter().filter(|status| status.is_success()).count(), 1, "statuses: {:?}", statuses);
+        assert_eq!(state.user_store.lock().unwrap().values().filter(|u| u.name == "alice").count(), 1);
     }
 
-    // Update credential counter
-    if let Some(credential) = user.credentials.iter_mut().find(|c| c.cred_id() == auth_result.cred_id()) {
-        credential.update_credential(&auth_result);
+    #[tokio::test]
+    async fn virtual_authenticator_registers_logs_in_and_gets_a_token() {
+        let config = test_support::config(&[]);
+        let jwt_config = config.jwt.clone();
+        let (router, state) = test_support::app(config);
+
+        let mut passkey = test_support::register(&router, "alice").await;
+        let response = test_support::login(&router, "alice", &mut passkey).await;
+
+        assert_eq!(response.status, 200, "{}", String::from_utf8_lossy(&response.body));
+        let token = response.json()["token"].as_str().expect("token").to_string();
+        let claims = jwt::verify_jwt(&jwt_config, &token).expect("valid token");
+        let store = state.user_store.lock().unwrap();
+        assert_eq!(claims.name, "alice");
+        assert_eq!(store[&claims.sub].name, "alice");
+        assert_eq!(store[&claims.sub].credentials.len(), 1);
     }
 
-    // Issue JWT token
-    let token = jwt::issue_jwt(&user.id, &user.name)?;
+    #[tokio::test]
+    async fn device_bound_credential_is_rejected_under_backup_policy() {
+        let (router, state) = test_support::app(test_support::config(&[("REQUIRE_BACKUP_ELIGIBLE", "true")]));
+
+        let response = test_support::finish_register(&router, "alice", &mut test_support::Passkey::new()).await;
+
+        assert_eq!(response.status, 401);
+        assert_eq!(response.json()["message"], "Credential must be backup eligible");
+        assert!(state.user_store.lock().unwrap().values().all(|u| u.credentials.is_empty()));
+    }
+
+    #[tokio::test]
+    async fn device_bound_credential_is_accepted_and_listed_without_policy() {
+        let (router, _) = test_support::app(test_support::config(&[]));
+        let mut passkey = test_support::register(&router, "alice").await;
+        let token = test_support::login_token(&router, "alice", &mut passkey).await;
+
+        let listing = test_support::send(&router, test_support::get("/auth/credentials", &[("Authorization", &test_support::bearer(&token))])).await;
+
+        assert_eq!(listing.status, 200);
+        let credentials = listing.json()["credentials"].clone();
+        assert_eq!(credentials.as_array().unwrap().len(), 1);
+        assert_eq!(credentials[0]["backup_eligible"], false);
+        assert_eq!(credentials[0]["backup_state"], false);
+    }
+
+    #[tokio::test]
+    async fn challenge_carries_distinct_display_name_and_rp_name() {
+        let (router, _) = test_support::app(test_support::config(&[("WEBAUTHN_RP_NAME", "Example Bank")]));
+
+        let response = start_register(&router, json!({"username": "alice", "display_name": "Alice Liddell"})).await;
+
+        let options = &response.json()["public_key"]["publicKey"];
+        assert_eq!(options["user"]["name"], "alice");
+        assert_eq!(options["user"]["displayName"], "Alice Liddell");
+        assert_eq!(options["rp"]["name"], "Example Bank");
+    }
+
+    #[tokio::test]
+    async fn display_name_defaults_to_username() {
+        let (router, _) = test_support::app(test_support::config(&[]));
+
+        let response = start_register(&router, json!({"username": "alice", "display_name": "  "})).await;
+
+        assert_eq!(response.json()["public_key"]["publicKey"]["user"]["displayName"], "alice");
+    }
+
+    async fn import(router: &Router, rows: serde_json::Value) -> TestResponse {
+        test_support::send(router, test_support::json_request(
+            Method::POST, "/admin/credentials/import", &[("X-Admin-Token", "admin-secret")], &rows,
+        )).await
+    }
+
+    #[tokio::test]
+    async fn imported_credential_creates_its_user() {
+        let (router, state) = test_support::app(test_support::config(&[("ADMIN_TOKEN", "admin-secret")]));
+        let passkey = test_support::exported_passkey().await;
+
+        let response = import(&router, json!([{"username": "carol", "passkey": passkey}])).await;
+
+        assert_eq!(response.status, 200);
+        assert_eq!(response.json()["imported"], 1);
+        let store = state.user_store.lock().unwrap();
+        let carol = store.values().find(|u| u.name == "carol").expect("user created");
+        assert_eq!(carol.credentials.len(), 1);
+    }
+
+    #[tokio::test]
+    async fn malformed_row_is_reported_without_aborting_the_batch() {
+        let (router, state) = test_support::app(test_support::config(&[("ADMIN_TOKEN", "admin-secret")]));
+        let passkey = test_support::exported_passkey().await;
+
+        let response = import(&router, json!([
+            {"username": "mallory", "passkey": {"cred": "garbage"}},
+            {"username": "carol", "passkey": passkey},
+        ])).await;
+
+        let body = response.json();
+        assert_eq!((body["imported"].clone(), body["failed"].clone()), (json!(1), json!(1)));
+        assert_eq!(body["results"][0]["status"], "error");
+        assert!(body["results"][0]["error"].as_str().unwrap().contains("Malformed passkey"));
+        assert_eq!(body["results"][1]["status"], "success");
+        let store = state.user_store.lock().unwrap();
+        assert!(store.values().all(|u| u.name != "mallory"));
+    }
+
+    #[tokio::test]
+    async fn import_over_quota_creates_no_user() {
+        let (router, state) = test_support::app(test_support::config(&[("ADMIN_TOKEN", "admin-secret"), ("MAX_CREDENTIALS_PER_USER", "0")]));
+        let passkey = test_support::exported_passkey().await;
+
+        let response = import(&router, json!([{"username": "carol", "passkey": passkey}])).await;
+
+        assert_eq!(response.json()["failed"], 1);
+        assert!(state.user_store.lock().unwrap().values().all(|user| user.credentials.is_empty()));
+    }
+
+    #[tokio::test]
+    async fn verify_only_mode_returns_the_verified_user_without_a_token() {
+        let (router, state) = test_support::app(test_support::config(&[("ISSUE_JWT", "false")]));
+        let mut passkey = test_support::register(&router, "alice").await;
+
+        let response = test_support::login(&router, "alice", &mut passkey).await;
+
+        assert_eq!(response.status, 200);
+        let body = response.json();
+        let user_id = state.user_store.lock().unwrap().values().next().unwrap().id.clone();
+        assert_eq!(body, json!({"verified": true, "user_id": user_id, "username": "alice"}));
+        assert!(body.get("token").is_none());
+    }
+
+    // Start a registration and answer it, returning the finish request body
+    async fn registration_finish(router: &Router, username: &str) -> serde_json::Value {
+        let start = start_register(router, json!({"username": username})).await;
+        let credential = test_support::Passkey::new().register(&start.json()["public_key"]);
+        json!({"username": username, "credential": credential})
+    }
+
+    #[tokio::test]
+    async fn repeated_finish_for_a_saved_credential_succeeds() {
+        let (router, state) = test_support::app(test_support::config(&[]));
+        let finish = registration_finish(&router, "alice").await;
+
+        for _ in 0..2 {
+            let response = test_support::send(&router, test_support::json_request(Method::POST, "/auth/verify-register", &[], &finish)).await;
+            assert_eq!(response.status, 200, "{}", String::from_utf8_lossy(&response.body));
+        }
+
+        assert_eq!(state.user_store.lock().unwrap().values().next().unwrap().credentials.len(), 1);
+    }
+
+    #[tokio::test]
+    async fn finish_without_a_live_session_still_expires() {
+        let clock = crate::clock::TestClock::new();
+        let (router, _) = test_support::app(test_support::config(&[("CHALLENGE_TTL_SECS", "60")]).with_clock(clock.clone()));
+        let finish = registration_finish(&router, "alice").await;
+        clock.advance(std::time::Duration::from_secs(60));
+
+        let response = test_support::send(&router, test_support::json_request(Method::POST, "/auth/verify-register", &[], &finish)).await;
+
+        assert_eq!(response.status, 401);
+        assert_eq!(response.json()["message"], "Registration session expired");
+    }
+
+    #[tokio::test]
+    async fn challenges_beyond_the_per_ip_cap_get_429() {
+        let (router, _) = test_support::app(test_support::config(&[("MAX_PENDING_CHALLENGES_PER_IP", "1")]));
+        assert_eq!(start_register(&router, json!({"username": "alice"})).await.status, 200);
+
+        let response = start_register(&router, json!({"username": "bob"})).await;
+
+        assert_eq!(response.status, 429);
+        assert_eq!(response.json()["code"], "RATE_LIMITED");
+        assert!(response.headers.contains_key("retry-after"));
+    }
+
+    #[tokio::test]
+    async fn per_ip_cap_follows_the_trusted_forwarded_address() {
+        let from = |client: &str, username: &str| {
+            test_support::json_request(Method::POST, "/auth/register", &[("X-Forwarded-For", client)], &json!({"username": username}))
+        };
+        let (router, _) = test_support::app(test_support::config(&[("MAX_PENDING_CHALLENGES_PER_IP", "1"), ("TRUST_FORWARDED_FOR", "true")]));
+        assert_eq!(test_support::send(&router, from("203.0.113.7", "alice")).await.status, 200);
+        assert_eq!(test_support::send(&router, from("203.0.113.8", "bob")).await.status, 200);
+        assert_eq!(test_support::send(&router, from("203.0.113.7", "carol")).await.status, 429);
+
+        // Untrusted, every request counts against the proxy's own address
+        let (router, _) = test_support::app(test_support::config(&[("MAX_PENDING_CHALLENGES_PER_IP", "1")]));
+        assert_eq!(test_support::send(&router, from("203.0.113.7", "alice")).await.status, 200);
+        assert_eq!(test_support::send(&router, from("203.0.113.8", "bob")).await.status, 429);
+    }
+
+    #[tokio::test]
+    async fn aaguid_is_recorded_and_listed_for_admins() {
+        let (router, state) = test_support::app(test_support::config(&[("ADMIN_TOKEN", "admin-secret")]));
+        let response = test_support::finish_register(&router, "alice", &mut test_support::Passkey::attested()).await;
+        assert_eq!(response.status, 200, "{}", String::from_utf8_lossy(&response.body));
+
+        let listing = test_support::send(&router, test_support::get("/admin/credentials", &[("X-Admin-Token", "admin-secret")])).await;
+
+        let expected = webauthn_authenticator_rs::softtoken::AAGUID;
+        let credential = &listing.json()["credentials"][0];
+        assert_eq!(credential["aaguid"], expected.to_string());
+        assert_eq!(credential["attestation_format"], "packed");
+        let store = state.user_store.lock().unwrap();
+        assert_eq!(store.values().next().unwrap().credentials[0].aaguid, Some(expected));
+    }
+
+    #[tokio::test]
+    async fn credential_creation_time_comes_from_the_configured_clock() {
+        let clock = crate::clock::TestClock::new();
+        clock.advance(std::time::Duration::from_secs(86_400));
+        let (router, _) = test_support::app(test_support::config(&[("ADMIN_TOKEN", "admin-secret")]).with_clock(clock.clone()));
+        test_support::finish_register(&router, "alice", &mut test_support::Passkey::new()).await;
+
+        let listing = test_support::send(&router, test_support::get("/admin/credentials", &[("X-Admin-Token", "admin-secret")])).await;
+
+        assert_eq!(listing.json()["credentials"][0]["created_at"], crate::clock::Clock::utc(&*clock).to_rfc3339());
+    }
+
+    // Rename an account with the given token
+    async fn rename(router: &Router, username: &str, new_name: &str, token: &str) -> TestResponse {
+        let auth = test_support::bearer(token);
+        let uri = format!("/auth/account/{}", username);
+        test_support::send(router, test_support::json_request(Method::PATCH, &uri, &[("Authorization", &auth)], &json!({"username": new_name}))).await
+    }
+
+    #[tokio::test]
+    async fn renamed_user_logs_in_under_the_new_name_and_frees_the_old_one() {
+        let (router, _) = test_support::app(test_support::config(&[]));
+        let mut passkey = test_support::register(&router, "alice").await;
+        let token = test_support::login_token(&router, "alice", &mut passkey).await;
+
+        let response = rename(&router, "alice", " alicia ", &token).await;
+        assert_eq!(response.status, 200, "{}", String::from_utf8_lossy(&response.body));
+        assert_eq!(response.json()["username"], "alicia");
+
+        assert_eq!(test_support::login(&router, "alicia", &mut passkey).await.status, 200);
+        assert_ne!(test_support::login_start(&router, "alice").await.status, 200);
+        test_support::register(&router, "alice").await;
+    }
+
+    #[tokio::test]
+    async fn rename_to_a_taken_name_is_rejected() {
+        let (router, state) = test_support::app(test_support::config(&[]));
+        let mut passkey = test_support::register(&router, "alice").await;
+        test_support::register(&router, "bob").await;
+        let token = test_support::login_token(&router, "alice", &mut passkey).await;
+
+        assert_ne!(rename(&router, "alice", "bob", &token).await.status, 200);
+        // Nor can a token rename somebody else's account
+        assert_ne!(rename(&router, "bob", "robert", &token).await.status, 200);
+
+        let mut names: Vec<_> = state.user_store.lock().unwrap().values().map(|u| u.name.clone()).collect();
+        names.sort();
+        assert_eq!(names, ["alice", "bob"]);
+    }
+
+    // Registration credential produced by a real ceremony, to tamper with
+    async fn registration_credential(router: &Router, username: &str) -> serde_json::Value {
+        let start = start_register(router, json!({"username": username})).await;
+        assert_eq!(start.status, 200);
+        let credential = test_support::Passkey::new().register(&start.json()["public_key"]);
+        serde_json::to_value(credential).unwrap()
+    }
+
+    async fn finish_register(router: &Router, body: &serde_json::Value) -> TestResponse {
+        test_support::send(router, test_support::json_request(Method::POST, "/auth/verify-register", &[], body)).await
+    }
+
+    #[tokio::test]
+    async fn oversized_credential_is_rejected_with_400() {
+        let (router, state) = test_support::app(test_support::config(&[("MAX_CREDENTIAL_PAYLOAD_BYTES", "4096")]));
+        let mut credential = registration_credential(&router, "alice").await;
+        credential["clientExtensionResults"] = json!({"padding": "x".repeat(8192)});
+
+        let response = finish_register(&router, &json!({"username": "alice", "credential": credential})).await;
+
+        assert_eq!(response.status, 400);
+        assert_eq!(response.json()["code"], "BAD_REQUEST");
+        assert!(response.json()["message"].as_str().unwrap().contains("exceeds 4096 bytes"));
+        assert!(state.user_store.lock().unwrap().values().all(|user| user.credentials.is_empty()));
+    }
+
+    #[tokio::test]
+    async fn credential_missing_a_field_is_rejected_with_400() {
+        let (router, _) = test_support::app(test_support::config(&[]));
+        let mut credential = registration_credential(&router, "alice").await;
+        credential["response"].as_object_mut().unwrap().remove("attestationObject");
+
+        let response = finish_register(&router, &json!({"username": "alice", "credential": credential})).await;
+        assert_eq!(response.status, 400);
+        assert!(response.json()["message"].as_str().unwrap().contains("response.attestationObject"), "{}", response.json());
+
+        let response = finish_register(&router, &json!({"username": "alice", "credential": {"id": "abc", "type": "public-key"}})).await;
+        assert_eq!(response.status, 400);
+        assert!(response.json()["message"].as_str().unwrap().contains("rawId"), "{}", response.json());
+    }
+
+    // Login whose assertion arrives without a user handle, as from a legacy non-resident credential
+    async fn login_without_user_handle(router: &Router, username: &str, passkey: &mut test_support::Passkey) -> TestResponse {
+        let start = test_support::login_start(router, username).await;
+        assert_eq!(start.status, 200);
+        let mut credential = serde_json::to_value(passkey.assert(&start.json()["public_key"])).unwrap();
+        credential["response"]["userHandle"] = serde_json::Value::Null;
+        test_support::send(router, test_support::json_request(Method::POST, "/auth/verify-login", &[], &json!({"username": username, "credential": credential}))).await
+    }
+
+    #[tokio::test]
+    async fn missing_user_handle_is_rejected_by_default() {
+        let (router, _) = test_support::app(test_support::config(&[]));
+        let mut passkey = test_support::register(&router, "alice").await;
+
+        let response = login_without_user_handle(&router, "alice", &mut passkey).await;
+
+        assert_eq!(response.status, 401);
+        assert_eq!(response.json()["message"], "Credential user handle is required");
+        assert!(response.json().get("token").is_none());
+    }
+
+    #[tokio::test]
+    async fn missing_user_handle_is_accepted_when_permitted() {
+        let (router, _) = test_support::app(test_support::config(&[("REQUIRE_USER_HANDLE", "false")]));
+        let mut passkey = test_support::register(&router, "alice").await;
+
+        let response = login_without_user_handle(&router, "alice", &mut passkey).await;
+
+        assert_eq!(response.status, 200, "{}", String::from_utf8_lossy(&response.body));
+        assert!(response.json()["token"].is_string());
+    }
+
+    #[tokio::test]
+    async fn restarted_registration_reuses_the_pending_user() {
+        // Restarting while the first ceremony is pending takes a second attempt
+        let (router, state) = test_support::app(test_support::config(&[("MAX_REGISTRATION_ATTEMPTS_PER_USER", "2")]));
+
+        let first = start_register(&router, json!({"username": "alice"})).await;
+        let second = start_register(&router, json!({"username": "alice"})).await;
+
+        assert_eq!(first.status, 200);
+        assert_eq!(second.status, 200);
+        assert_eq!(first.json()["user_id"], second.json()["user_id"]);
+        assert_eq!(state.user_store.lock().unwrap().len(), 1);
+
+        // The refreshed challenge is the one that completes the registration
+        let mut passkey = test_support::Passkey::new();
+        let credential = passkey.register(&second.json()["public_key"]);
+        let finish = test_support::send(&router, test_support::json_request(Method::POST, "/auth/verify-register", &[], &json!({"username": "alice", "credential": credential}))).await;
+        assert_eq!(finish.status, 200, "{}", String::from_utf8_lossy(&finish.body));
+        assert_eq!(state.user_store.lock().unwrap().values().next().unwrap().credentials.len(), 1);
+    }
+
+    // Counter stored for the user's only credential
+    fn stored_counter(state: &crate::app::AppState, username: &str) -> u32 {
+        let store = state.user_store.lock().unwrap();
+        let user = store.values().find(|u| u.name == username).unwrap();
+        Credential::from(user.credentials[0].passkey.clone()).counter
+    }
+
+    #[tokio::test(flavor = "multi_thread", worker_threads = 2)]
+    async fn concurrent_assertions_keep_the_higher_counter() {
+        let (router, state) = test_support::app(test_support::config(&[]));
+        let mut passkey = test_support::register(&router, "alice").await;
+        let token = test_support::login_token(&router, "alice", &mut passkey).await;
+        let auth = test_support::bearer(&token);
+        let before = stored_counter(&state, "alice");
+
+        // A login and a step-up outstanding at once; the step-up assertion carries the higher counter
+        let login = test_support::login_start(&router, "alice").await;
+        let step_up = test_support::send(&router, test_support::request(Method::POST, "/auth/step-up", &[("Authorization", &auth)], "")).await;
+        assert_eq!(step_up.status, 200, "{}", String::from_utf8_lossy(&step_up.body));
+        let login_credential = passkey.assert(&login.json()["public_key"]);
+        let step_up_credential = passkey.assert(&step_up.json()["public_key"]);
+
+        let finishes = [
+            test_support::send(&router, test_support::json_request(Method::POST, "/auth/verify-login", &[], &json!({"username": "alice", "credential": login_credential}))),
+            test_support::send(&router, test_support::json_request(Method::POST, "/auth/verify-step-up", &[("Authorization", &auth)], &json!({"credential": step_up_credential}))),
+        ].map(tokio::spawn);
+        let mut succeeded = 0;
+        for finish in finishes {
+            succeeded += usize::from(finish.await.unwrap().status == 200);
+        }
+
+        // Whichever finished first, the stored counter never goes back to the lower value
+        assert!(succeeded >= 1);
+        assert_eq!(stored_counter(&state, "alice"), before + 2);
+    }
+
+    // Log in from the given User-Agent and call the log service with the token from another
+    async fn call_with_bound_token(bind: &str, login_agent: &str, call_agent: &str) -> TestResponse {
+        let logs = test_support::MockBackend::json(&json!({"logs": []}));
+        let (router, _) = test_support::app(test_support::config(&[
+            ("LOG_SERVICE_URL", &logs.url()),
+            ("REQUIRE_AUTH_PER_SERVICE", "log"),
+            ("BIND_TOKEN_TO_CLIENT", bind),
+        ]));
+        let mut passkey = test_support::register(&router, "alice").await;
+        let start = test_support::login_start(&router, "alice").await;
+        let finish = json!({"username": "alice", "credential": passkey.assert(&start.json()["public_key"])});
+        let login = test_support::send(&router, test_support::json_request(Method::POST, "/auth/verify-login", &[("User-Agent", login_agent)], &finish)).await;
+        let token = login.json()["token"].as_str().expect("token").to_string();
+
+        test_support::send(&router, test_support::get("/api/service/log", &[
+            ("Authorization", &test_support::bearer(&token)),
+            ("User-Agent", call_agent),
+        ])).await
+    }
 
-    Ok(Json(FinishLoginResponse { token }))
-}
\ No newline at end of file
+    #[tokio::test]
+    async fn bound_token_is_rejected_from_a_different_user_agent() {
+        const FIREFOX: &str = "Mozilla/5.0 (X11; Linux x86_64; rv:128.0) Firefox/128.0";
+        const CURL: &str = "curl/8.5.0";
+
+        let rejected = call_with_bound_token("true", FIREFOX, CURL).await;
+        assert_eq!(rejected.status, 401);
+        assert_eq!(rejected.json()["message"], "Token is bound to a different client");
+
+        assert_eq!(call_with_bound_token("true", FIREFOX, FIREFOX).await.status, 200);
+        assert_eq!(call_with_bound_token("false", FIREFOX, CURL).await.status, 200);
+    }
+
+    // Send the requests at once, racing admin and metrics reads of the same user and
+    // challenge stores; answers come back in request order
+    async fn race_with_readers(router: &Router, requests: Vec<axum::http::Request<axum::body::Body>>) -> Vec<TestResponse> {
+        let reads = (0..4).flat_map(|_| ["/admin/credentials", "/metrics"])
+            .map(|uri| test_support::get(uri, &[("X-Admin-Token", "admin-secret")]));
+        let count = requests.len();
+        let tasks: Vec<_> = requests.into_iter().chain(reads)
+            .map(|request| tokio::spawn(test_support::send(router, request)))
+            .collect();
+
+        let mut responses = Vec::new();
+        for task in tasks {
+            responses.push(task.await.unwrap());
+        }
+        assert!(responses[count..].iter().all(|read| read.status == 200));
+        responses.truncate(count);
+        responses
+    }
+
+    #[tokio::test(flavor = "multi_thread", worker_threads = 4)]
+    async fn concurrent_ceremonies_and_admin_reads_complete_without_deadlock() {
+        let (router, state) = test_support::app(test_support::config(&[("ADMIN_TOKEN", "admin-secret")]));
+        let usernames: Vec<String> = (0..8).map(|i| format!("user{}", i)).collect();
+        let mut passkeys: Vec<test_support::Passkey> = usernames.iter().map(|_| test_support::Passkey::new()).collect();
+        let step = |path: &str, bodies: Vec<serde_json::Value>| bodies.iter()
+            .map(|body| test_support::json_request(Method::POST, path, &[], body))
+            .collect::<Vec<_>>();
+
+        tokio::time::timeout(Duration::from_secs(20), async {
+            let starts = race_with_readers(&router, step("/auth/register", usernames.iter().map(|name| json!({"username": name})).collect())).await;
+            let finishes = usernames.iter().zip(&mut passkeys).zip(&starts)
+                .map(|((name, passkey), start)| json!({"username": name, "credential": passkey.register(&start.json()["public_key"])}))
+                .collect();
+            let registered = race_with_readers(&router, step("/auth/verify-register", finishes)).await;
+            assert!(registered.iter().all(|response| response.status == 200));
+
+            let starts = race_with_readers(&router, step("/auth/login", usernames.iter().map(|name| json!({"username": name})).collect())).await;
+            let finishes = usernames.iter().zip(&mut passkeys).zip(&starts)
+                .map(|((name, passkey), start)| json!({"username": name, "credential": passkey.assert(&start.json()["public_key"])}))
+                .collect();
+            let logins = race_with_readers(&router, step("/auth/verify-login", finishes)).await;
+            assert!(logins.iter().all(|response| response.status == 200 && response.json()["token"].is_string()));
+        }).await.expect("handlers deadlocked");
+
+        assert_eq!(state.user_store.lock().unwrap().len(), 8);
+    }
+
+    #[tokio::test(flavor = "multi_thread", worker_threads = 2)]
+    async fn double_submitted_login_consumes_the_challenge_once() {
+        let (router, _) = test_support::app(test_support::config(&[]));
+        let mut passkey = test_support::register(&router, "alice").await;
+        let start = test_support::login_start(&router, "alice").await;
+        let finish = json!({"username": "alice", "credential": passkey.assert(&start.json()["public_key"])});
+
+        let submits = [(), ()].map(|_| tokio::spawn(test_support::send(&router, test_support::json_request(Method::POST, "/auth/verify-login", &[], &finish))));
+        let mut responses = Vec::new();
+        for submit in submits {
+            responses.push(submit.await.unwrap());
+        }
+
+        let succeeded: Vec<_> = responses.iter().filter(|response| response.status == 200).collect();
+        assert_eq!(succeeded.len(), 1, "statuses: {:?}", responses.iter().map(|r| r.status).collect::<Vec<_>>());
+        let rejected = responses.iter().find(|response| response.status != 200).unwrap();
+        assert_eq!(rejected.status, 401);
+        assert!(rejected.json()["message"].as_str().unwrap().contains("expired"), "{}", rejected.json());
+    }
+
+    #[tokio::test]
+    async fn global_credential_limit_applies_until_an_admin_raises_the_quota() {
+        let (router, state) = test_support::app(test_support::config(&[("ADMIN_TOKEN", "admin-secret"), ("MAX_CREDENTIALS_PER_USER", "1")]));
+        test_support::register(&router, "alice").await;
+        let credentials = |state: &crate::app::AppState| state.user_store.lock().unwrap().values().next().unwrap().credentials.len();
+
+        let over = import(&router, json!([{"username": "alice", "passkey": test_support::exported_passkey().await}])).await;
+        assert_eq!(over.json()["failed"], 1);
+        assert!(over.json()["results"][0]["error"].as_str().unwrap().contains("Credential limit of 1"), "{}", over.json());
+        assert_eq!(credentials(&state), 1);
+
+        let quota = test_support::send(&router, test_support::json_request(
+            Method::PUT, "/admin/users/alice/credential-quota", &[("X-Admin-Token", "admin-secret")], &json!({"quota": 2}),
+        )).await;
+        assert_eq!(quota.status, 200);
+        assert_eq!(quota.json()["effective_quota"], 2);
+
+        let rows = json!([
+            {"username": "alice", "passkey": test_support::exported_passkey().await},
+            {"username": "alice", "passkey": test_support::exported_passkey().await},
+        ]);
+        let response = import(&router, rows).await;
+        assert_eq!(response.json()["imported"], 1, "{}", response.json());
+        assert_eq!(response.json()["failed"], 1);
+        assert_eq!(credentials(&state), 2);
+    }
+
+    #[tokio::test]
+    async fn cancelled_prompt_is_reported_as_user_cancelled() {
+        let (router, _) = test_support::app(test_support::config(&[]));
+        start_register(&router, json!({"username": "alice"})).await;
+
+        for name in ["NotAllowedError", "AbortError"] {
+            let response = test_support::send(&router, test_support::json_request(
+                Method::POST, "/auth/verify-register", &[], &json!({"username": "alice", "error": name}),
+            )).await;
+            assert_eq!(response.status, 400);
+            assert_eq!(response.json()["code"], "USER_CANCELLED");
+        }
+
+        // Other client errors are still just an incomplete request
+        let response = test_support::send(&router, test_support::json_request(
+            Method::POST, "/auth/verify-login", &[], &json!({"username": "alice", "error": "SecurityError"}),
+        )).await;
+        assert_eq!(response.json()["code"], "BAD_REQUEST");
+    }
+
+    #[tokio::test]
+    async fn credential_of_another_user_is_a_conflict() {
+        let (router, state) = test_support::app(test_support::config(&[]));
+        let alice = registration_finish(&router, "alice").await;
+        assert_eq!(test_support::send(&router, test_support::json_request(Method::POST, "/auth/verify-register", &[], &alice)).await.status, 200);
+        start_register(&router, json!({"username": "bob"})).await;
+
+        let finish = json!({"username": "bob", "credential": alice["credential"]});
+        let response = test_support::send(&router, test_support::json_request(Method::POST, "/auth/verify-register", &[], &finish)).await;
+
+        assert_eq!(response.status, 409);
+        assert_eq!(response.json()["code"], "DUPLICATE_CREDENTIAL");
+        let store = state.user_store.lock().unwrap();
+        assert!(store.values().find(|u| u.name == "bob").unwrap().credentials.is_empty());
+    }
+
+    #[tokio::test]
+    async fn imported_duplicate_credential_is_reported() {
+        let (router, _) = test_support::app(test_support::config(&[("ADMIN_TOKEN", "admin-secret")]));
+        let passkey = test_support::exported_passkey().await;
+
+        let response = import(&router, json!([{"username": "carol", "passkey": passkey}, {"username": "dave", "passkey": passkey}])).await;
+
+        assert_eq!(response.json()["imported"], 1);
+        assert!(response.json()["results"][1]["error"].as_str().unwrap().contains("Duplicate credential: Credential ID already exists"), "{}", response.json());
+    }
+
+    #[tokio::test]
+    async fn second_start_while_one_is_pending_is_rejected_by_default() {
+        let (router, _) = test_support::app(test_support::config(&[("CHALLENGE_TTL_SECS", "300")]));
+        assert_eq!(start_register(&router, json!({"username": "alice"})).await.status, 200);
+
+        let second = start_register_from(&router, Ipv4Addr::new(198, 51, 100, 2), json!({"username": "alice"})).await;
+
+        assert_eq!(second.status, 429);
+        assert_eq!(second.json()["code"], "RATE_LIMITED");
+        assert!((299..=300).contains(&second.json()["retry_after_secs"].as_u64().unwrap()), "{}", second.json());
+    }
+
+    #[tokio::test]
+    async fn finishing_a_registration_ends_its_attempt_count() {
+        let (router, _) = test_support::app(test_support::config(&[]));
+        assert_eq!(start_register(&router, json!({"username": "alice"})).await.status, 200);
+
+        // A credential for another user's challenge fails, consuming alice's pending state
+        let bob = start_register(&router, json!({"username": "bob"})).await;
+        let credential = test_support::Passkey::new().register(&bob.json()["public_key"]);
+        let finish = test_support::send(&router, test_support::json_request(Method::POST, "/auth/verify-register", &[], &json!({"username": "alice", "credential": credential}))).await;
+        assert_eq!(finish.status, 400, "{}", String::from_utf8_lossy(&finish.body));
+
+        assert_eq!(start_register(&router, json!({"username": "alice"})).await.status, 200);
+    }
+
+    #[tokio::test]
+    async fn registration_attempts_are_counted_per_username_within_the_ttl() {
+        let clock = crate::clock::TestClock::new();
+        let (router, _) = test_support::app(test_support::config(&[("CHALLENGE_TTL_SECS", "300"), ("MAX_REGISTRATION_ATTEMPTS_PER_USER", "3")]).with_clock(clock.clone()));
+
+        // A reload from another network restarts the ceremony instead of waiting it out
+        for last in 1..=3 {
+            let start = start_register_from(&router, Ipv4Addr::new(198, 51, 100, last), json!({"username": "alice"})).await;
+            assert_eq!(start.status, 200, "attempt from .{}", last);
+            clock.advance(std::time::Duration::from_secs(60));
+        }
+        let fourth = start_register(&router, json!({"username": "alice"})).await;
+        assert_eq!(fourth.status, 429);
+        assert_eq!(fourth.json()["retry_after_secs"], 120);
+        assert_eq!(start_register(&router, json!({"username": "bob"})).await.status, 200);
+
+        // The window runs from the first attempt
+        clock.advance(std::time::Duration::from_secs(120));
+        assert_eq!(start_register(&router, json!({"username": "alice"})).await.status, 200);
+    }
+}